@@ -0,0 +1,44 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use rust_diff::bench::{generate_synthetic_functions, perturb_functions, run_diff_bench};
+use rust_diff::SimilarityAnalyzer;
+
+fn bench_diff(c: &mut Criterion) {
+    let mut group = c.benchmark_group("match_functions");
+    group.sample_size(10);
+
+    for &count in &[100usize, 1000] {
+        let functions_a = generate_synthetic_functions(count, 8);
+        let mut functions_b = functions_a.clone();
+        perturb_functions(&mut functions_b, 10);
+
+        group.bench_with_input(BenchmarkId::from_parameter(count), &count, |b, _| {
+            b.iter(|| run_diff_bench(&functions_a, &functions_b).unwrap())
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_edit_distance(c: &mut Criterion) {
+    // Two ~2000-instruction functions, compared the way
+    // instruction_sequence_similarity does: as joined mnemonic strings
+    let mnemonics = |count: usize, every_nth: usize| {
+        let mut functions = generate_synthetic_functions(count, 8);
+        perturb_functions(&mut functions, every_nth);
+        functions
+            .iter()
+            .flat_map(|f| f.instructions.iter().map(|i| i.mnemonic.clone()))
+            .take(2000)
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    let seq_a = mnemonics(100, 0);
+    let seq_b = mnemonics(100, 3);
+
+    c.bench_function("edit_distance_2000_instructions", |b| {
+        b.iter(|| SimilarityAnalyzer::edit_distance(&seq_a, &seq_b))
+    });
+}
+
+criterion_group!(benches, bench_diff, bench_edit_distance);
+criterion_main!(benches);
@@ -1,11 +1,205 @@
-use crate::types::{FunctionInfo, MatchDetails};
+use crate::types::{
+    Architecture, CalleeChange, DiffResult, FunctionInfo, FunctionMatch, MatchDetails, MatchType, RecursionKind, RelocatedFunction,
+    StructuralFingerprint,
+};
 use crate::similarity::SimilarityAnalyzer;
+use crate::config::{SimilarityWeights, StructuralTolerance};
 use std::collections::HashMap;
-use rustc_hash::FxHashSet;
+use rustc_hash::{FxHashMap, FxHashSet};
 use sha2::{Sha256, Digest};
+use petgraph::Graph;
+use petgraph::algo::{dominators, tarjan_scc};
+use petgraph::graph::NodeIndex;
 
 pub struct DiffAlgorithms;
 
+/// A distinct prime for every mnemonic seen in a diff run. Primes are
+/// assigned in sorted mnemonic order, so the same set of functions always
+/// produces the same assignment.
+pub struct MnemonicPrimes {
+    primes: FxHashMap<String, u64>,
+}
+
+impl MnemonicPrimes {
+    /// Intern every mnemonic used by `functions`.
+    pub fn from_functions<'a>(functions: impl IntoIterator<Item = &'a FunctionInfo>) -> Self {
+        let mut mnemonics: Vec<&str> = functions
+            .into_iter()
+            .flat_map(|func| func.instructions.iter().map(|instr| instr.mnemonic.as_str()))
+            .collect();
+        mnemonics.sort_unstable();
+        mnemonics.dedup();
+
+        let primes = mnemonics
+            .iter()
+            .zip(DiffAlgorithms::sieve_primes(mnemonics.len()))
+            .map(|(mnemonic, prime)| (mnemonic.to_string(), prime))
+            .collect();
+        Self { primes }
+    }
+
+    /// The prime assigned to `mnemonic`, if it was interned.
+    pub fn prime(&self, mnemonic: &str) -> Option<u64> {
+        self.primes.get(mnemonic).copied()
+    }
+}
+
+/// A call target: a function of the binary, by address, or anything else
+/// (imports, indirect calls) by its operand text.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Callee {
+    Function(u64),
+    External(String),
+}
+
+/// Caller → callee edges of a whole binary, recovered from call
+/// instructions as in `SimilarityAnalyzer::extract_function_calls`.
+pub struct CallGraph {
+    callees: FxHashMap<u64, FxHashSet<Callee>>,
+    names: FxHashMap<u64, String>,
+}
+
+impl CallGraph {
+    pub fn build(functions: &[FunctionInfo]) -> Self {
+        let by_name: FxHashMap<&str, u64> = functions.iter().map(|f| (f.name.as_str(), f.address)).collect();
+        let names: FxHashMap<u64, String> = functions.iter().map(|f| (f.address, f.name.clone())).collect();
+
+        let callees = functions
+            .iter()
+            .map(|func| {
+                let targets = SimilarityAnalyzer::extract_function_calls(func)
+                    .into_iter()
+                    .map(|target| {
+                        if target == "<self>" {
+                            return Callee::Function(func.address);
+                        }
+                        let address = by_name.get(target.as_str()).copied().or_else(|| {
+                            target
+                                .strip_prefix("0x")
+                                .and_then(|hex| u64::from_str_radix(hex, 16).ok())
+                                .filter(|address| names.contains_key(address))
+                        });
+                        match address {
+                            Some(address) => Callee::Function(address),
+                            None => Callee::External(target),
+                        }
+                    })
+                    .collect();
+                (func.address, targets)
+            })
+            .collect();
+
+        Self { callees, names }
+    }
+
+    /// Call targets of the function at `address`.
+    pub fn callees(&self, address: u64) -> Option<&FxHashSet<Callee>> {
+        self.callees.get(&address)
+    }
+
+    pub fn edge_count(&self) -> usize {
+        self.callees.values().map(FxHashSet::len).sum()
+    }
+
+    fn display_name(&self, callee: &Callee) -> String {
+        match callee {
+            Callee::Function(address) => self.names.get(address).cloned().unwrap_or_else(|| format!("0x{:x}", address)),
+            Callee::External(name) => name.clone(),
+        }
+    }
+
+    /// Compare the callees of every matched pair, translating A's internal
+    /// callees to B through `matches`. Callees without a counterpart count
+    /// as removed. Pairs with unchanged callees are left out; the rest are
+    /// returned in A address order.
+    pub fn diff(&self, other: &CallGraph, matches: &[FunctionMatch]) -> Vec<CalleeChange> {
+        let a_to_b: FxHashMap<u64, u64> =
+            matches.iter().map(|m| (m.function_a.address, m.function_b.address)).collect();
+        let empty = FxHashSet::default();
+
+        let mut changes: Vec<CalleeChange> = matches
+            .iter()
+            .filter_map(|m| {
+                let callees_a = self.callees(m.function_a.address).unwrap_or(&empty);
+                let callees_b = other.callees(m.function_b.address).unwrap_or(&empty);
+
+                let mut mapped = FxHashSet::default();
+                let mut removed = Vec::new();
+                for callee in callees_a {
+                    let image = match callee {
+                        Callee::Function(address) => a_to_b.get(address).map(|&b| Callee::Function(b)),
+                        Callee::External(name) => Some(Callee::External(name.clone())),
+                    };
+                    match image {
+                        Some(image) if callees_b.contains(&image) => {
+                            mapped.insert(image);
+                        }
+                        _ => removed.push(self.display_name(callee)),
+                    }
+                }
+                let mut added: Vec<String> = callees_b
+                    .iter()
+                    .filter(|callee| !mapped.contains(*callee))
+                    .map(|callee| other.display_name(callee))
+                    .collect();
+
+                if added.is_empty() && removed.is_empty() {
+                    return None;
+                }
+                added.sort_unstable();
+                removed.sort_unstable();
+                Some(CalleeChange {
+                    function_a: m.function_a.name.clone(),
+                    address_a: m.function_a.address,
+                    function_b: m.function_b.name.clone(),
+                    address_b: m.function_b.address,
+                    added,
+                    removed,
+                })
+            })
+            .collect();
+
+        changes.sort_by_key(|change| change.address_a);
+        changes
+    }
+}
+
+/// Largest block count for which basic-block similarity solves the optimal
+/// pairing; bigger functions fall back to counting equal mnemonic hashes.
+const MAX_BLOCK_ASSIGNMENT_SIZE: usize = 128;
+
+/// Window of the rolling hash that picks context-triggered chunk boundaries.
+const CTPH_WINDOW: usize = 7;
+/// Target signature length; the block size grows until a signature fits.
+const CTPH_SIGNATURE_LENGTH: usize = 64;
+const CTPH_MIN_BLOCK_SIZE: u32 = 3;
+const CTPH_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Rolling hash over the last `CTPH_WINDOW` bytes, as in spamsum.
+struct RollingHash {
+    window: [u8; CTPH_WINDOW],
+    position: usize,
+    h1: u32,
+    h2: u32,
+    h3: u32,
+}
+
+impl RollingHash {
+    fn new() -> Self {
+        Self { window: [0; CTPH_WINDOW], position: 0, h1: 0, h2: 0, h3: 0 }
+    }
+
+    fn update(&mut self, byte: u8) -> u32 {
+        let outgoing = self.window[self.position % CTPH_WINDOW] as u32;
+        self.h2 = self.h2.wrapping_sub(self.h1).wrapping_add(CTPH_WINDOW as u32 * byte as u32);
+        self.h1 = self.h1.wrapping_add(byte as u32).wrapping_sub(outgoing);
+        self.window[self.position % CTPH_WINDOW] = byte;
+        self.position += 1;
+        self.h3 = (self.h3 << 5) ^ byte as u32;
+        self.h1.wrapping_add(self.h2).wrapping_add(self.h3)
+    }
+}
+
 /// Clamp a score to [0.0, 1.0] and replace NaN with 0.0.
 #[inline]
 fn sanitize_score(x: f64) -> f64 {
@@ -15,38 +209,78 @@ fn sanitize_score(x: f64) -> f64 {
 impl DiffAlgorithms {
     /// Calculate similarity between two functions using multiple metrics
     /// and return both the weighted score and detailed per-metric breakdown.
+    /// This is the canonical function similarity: every matching pass
+    /// reports it, so a pair scores the same whichever pass claimed it.
     pub fn compute_match_details(func_a: &FunctionInfo, func_b: &FunctionInfo) -> (f64, MatchDetails) {
-        let cfg_similarity = sanitize_score(Self::calculate_cfg_similarity(func_a, func_b));
-        let bb_similarity = sanitize_score(Self::calculate_basic_block_similarity(func_a, func_b));
-        let instruction_similarity = sanitize_score(Self::calculate_instruction_similarity(func_a, func_b));
-        let edge_similarity = sanitize_score(Self::calculate_edge_similarity(func_a, func_b));
-        let name_similarity = sanitize_score(SimilarityAnalyzer::normalized_edit_distance(&func_a.name, &func_b.name));
-        let call_similarity = sanitize_score(SimilarityAnalyzer::function_call_similarity(func_a, func_b));
-
-        let weighted_similarity = sanitize_score(
-            cfg_similarity * 0.30
-                + call_similarity * 0.20
-                + bb_similarity * 0.15
-                + instruction_similarity * 0.15
-                + name_similarity * 0.10
-                + edge_similarity * 0.10,
-        );
+        Self::compute_match_details_weighted(func_a, func_b, &SimilarityWeights::default())
+    }
 
-        let details = MatchDetails {
-            cfg_similarity,
-            bb_similarity,
-            instruction_similarity,
-            edge_similarity,
-            name_similarity,
-            call_similarity,
-        };
+    /// [`Self::compute_match_details`] under custom metric weights.
+    pub fn compute_match_details_weighted(
+        func_a: &FunctionInfo,
+        func_b: &FunctionInfo,
+        weights: &SimilarityWeights,
+    ) -> (f64, MatchDetails) {
+        let details = Self::compute_metric_scores(func_a, func_b, weights);
+        let similarity = Self::weighted_score(&details, weights);
+        (similarity, details)
+    }
 
-        (weighted_similarity, details)
+    /// Calculate similarity between two functions and explain it: returns the
+    /// weighted score, the per-metric breakdown, and each metric's weighted
+    /// contribution as `(metric name, weight * score)`. The contributions sum
+    /// to the returned score.
+    pub fn calculate_function_similarity_explained(
+        func_a: &FunctionInfo,
+        func_b: &FunctionInfo,
+    ) -> (f64, MatchDetails, Vec<(&'static str, f64)>) {
+        let weights = SimilarityWeights::default();
+        let details = Self::compute_metric_scores(func_a, func_b, &weights);
+        let similarity = Self::weighted_score(&details, &weights);
+        let contributions = weights.contributions(&details);
+        (similarity, details, contributions)
+    }
+
+    /// Combine per-metric scores under `weights`. With weights summing to
+    /// 1.0, clamping only absorbs rounding error.
+    fn weighted_score(details: &MatchDetails, weights: &SimilarityWeights) -> f64 {
+        sanitize_score(weights.contributions(details).iter().map(|(_, c)| c).sum::<f64>())
+    }
+
+    /// Compute every per-metric score, each sanitized to [0.0, 1.0].
+    fn compute_metric_scores(func_a: &FunctionInfo, func_b: &FunctionInfo, weights: &SimilarityWeights) -> MatchDetails {
+        MatchDetails {
+            cfg_similarity: sanitize_score(Self::calculate_cfg_similarity(func_a, func_b)),
+            bb_similarity: sanitize_score(Self::calculate_basic_block_similarity(func_a, func_b)),
+            instruction_similarity: sanitize_score(Self::calculate_instruction_similarity(func_a, func_b)),
+            edge_similarity: sanitize_score(Self::calculate_edge_similarity(func_a, func_b)),
+            name_similarity: sanitize_score(SimilarityAnalyzer::normalized_edit_distance(&func_a.name, &func_b.name)),
+            call_similarity: sanitize_score(SimilarityAnalyzer::function_call_similarity(func_a, func_b)),
+            register_similarity: sanitize_score(SimilarityAnalyzer::register_usage_similarity(func_a, func_b)),
+            exception_similarity: sanitize_score(SimilarityAnalyzer::exception_handling_similarity(func_a, func_b)),
+            cfg_shape_similarity: sanitize_score(SimilarityAnalyzer::cfg_shape_similarity(func_a, func_b)),
+            block_size_similarity: sanitize_score(SimilarityAnalyzer::block_size_distribution_similarity(func_a, func_b)),
+            lcs_similarity: if weights.lcs > 0.0 {
+                sanitize_score(SimilarityAnalyzer::lcs_mnemonic_similarity(&func_a.instructions, &func_b.instructions))
+            } else {
+                0.0
+            },
+        }
     }
 
     /// Calculate similarity between two functions (returns scalar only).
     pub fn calculate_function_similarity(func_a: &FunctionInfo, func_b: &FunctionInfo) -> f64 {
-        let (similarity, _) = Self::compute_match_details(func_a, func_b);
+        Self::calculate_function_similarity_weighted(func_a, func_b, &SimilarityWeights::default())
+    }
+
+    /// [`Self::calculate_function_similarity`] under custom metric weights,
+    /// e.g. tuned for one architecture.
+    pub fn calculate_function_similarity_weighted(
+        func_a: &FunctionInfo,
+        func_b: &FunctionInfo,
+        weights: &SimilarityWeights,
+    ) -> f64 {
+        let (similarity, _) = Self::compute_match_details_weighted(func_a, func_b, weights);
         similarity
     }
 
@@ -74,21 +308,47 @@ impl DiffAlgorithms {
             return 0.0;
         }
 
-        // Match basic blocks by mnemonic hash
-        let mut matched_blocks = 0;
-        let mut used_b = FxHashSet::default();
-
-        for bb_a in &func_a.basic_blocks {
-            for (i, bb_b) in func_b.basic_blocks.iter().enumerate() {
-                if !used_b.contains(&i) && bb_a.mnemonic_hash == bb_b.mnemonic_hash {
-                    matched_blocks += 1;
-                    used_b.insert(i);
-                    break;
-                }
+        // Past the size cap the cubic assignment is too slow for a pass that
+        // scores many pairs; count equal mnemonic hashes instead
+        if bb_count_a.max(bb_count_b) > MAX_BLOCK_ASSIGNMENT_SIZE {
+            let mut hashes_b: FxHashMap<&str, usize> = FxHashMap::default();
+            for bb in &func_b.basic_blocks {
+                *hashes_b.entry(bb.mnemonic_hash.as_str()).or_insert(0) += 1;
             }
+            let matched_blocks = func_a
+                .basic_blocks
+                .iter()
+                .filter(|bb| match hashes_b.get_mut(bb.mnemonic_hash.as_str()) {
+                    Some(count) if *count > 0 => {
+                        *count -= 1;
+                        true
+                    }
+                    _ => false,
+                })
+                .count();
+            return matched_blocks as f64 / bb_count_a.max(bb_count_b) as f64;
         }
 
-        matched_blocks as f64 / bb_count_a.max(bb_count_b) as f64
+        // Pair blocks to maximize total mnemonic similarity, so the score
+        // doesn't depend on block order and near-identical blocks count
+        let scores: Vec<Vec<f64>> = func_a
+            .basic_blocks
+            .iter()
+            .map(|bb_a| {
+                func_b
+                    .basic_blocks
+                    .iter()
+                    .map(|bb_b| SimilarityAnalyzer::basic_block_mnemonic_similarity(bb_a, bb_b))
+                    .collect()
+            })
+            .collect();
+        let total: f64 = Self::optimal_assignment(&scores)
+            .into_iter()
+            .enumerate()
+            .filter_map(|(idx_a, idx_b)| idx_b.map(|idx_b| scores[idx_a][idx_b]))
+            .sum();
+
+        total / bb_count_a.max(bb_count_b) as f64
     }
 
     /// Calculate instruction similarity using mnemonic histogram matching
@@ -126,113 +386,451 @@ impl DiffAlgorithms {
         matched_instructions as f64 / instr_count_a.max(instr_count_b) as f64
     }
 
-    /// Calculate edge similarity based on edge count difference
+    /// Calculate edge similarity from per-type edge counts (conditional,
+    /// unconditional, fallthrough, call, return)
     fn calculate_edge_similarity(func_a: &FunctionInfo, func_b: &FunctionInfo) -> f64 {
-        let total_edges_a: usize = func_a.basic_blocks.iter().map(|bb| bb.edges.len()).sum();
-        let total_edges_b: usize = func_b.basic_blocks.iter().map(|bb| bb.edges.len()).sum();
+        let counts_a = func_a.edge_type_counts().as_array();
+        let counts_b = func_b.edge_type_counts().as_array();
+
+        // Weighted Jaccard over the per-type count vectors, so trading
+        // conditional branches for unconditional jumps is not invisible
+        let mut min_sum = 0usize;
+        let mut max_sum = 0usize;
+        for (a, b) in counts_a.iter().zip(counts_b.iter()) {
+            min_sum += a.min(b);
+            max_sum += a.max(b);
+        }
 
-        if total_edges_a == 0 && total_edges_b == 0 {
+        if max_sum == 0 {
             return 1.0;
         }
 
-        if total_edges_a == 0 || total_edges_b == 0 {
+        min_sum as f64 / max_sum as f64
+    }
+
+    /// MD-Index (as in Diaphora): a topological invariant of the CFG. Every
+    /// edge contributes `1 / sqrt(t(src) + in(src)·√2 + out(src)·√3 +
+    /// t(dst)·√5 + in(dst)·√7 + out(dst)·√11)`, where `t` is a block's BFS
+    /// depth from the entry block and `in`/`out` are its degrees. It does not
+    /// depend on block addresses or the order of blocks and edges, only on
+    /// the graph's shape. Functions without edges score 0.0.
+    pub fn calculate_md_index_value(func: &FunctionInfo) -> f64 {
+        let graph = SimilarityAnalyzer::build_cfg_graph(func);
+        if graph.edge_count() == 0 {
             return 0.0;
         }
 
-        let edge_diff = (total_edges_a as f64 - total_edges_b as f64).abs()
-            / total_edges_a.max(total_edges_b) as f64;
-        1.0 - edge_diff
+        // Unreachable blocks sit one level past the deepest reachable one
+        let entry = NodeIndex::new(SimilarityAnalyzer::entry_block_index(func));
+        let mut depth = vec![usize::MAX; graph.node_count()];
+        depth[entry.index()] = 0;
+        let mut queue = std::collections::VecDeque::from([entry]);
+        while let Some(node) = queue.pop_front() {
+            for succ in graph.neighbors(node) {
+                if depth[succ.index()] == usize::MAX {
+                    depth[succ.index()] = depth[node.index()] + 1;
+                    queue.push_back(succ);
+                }
+            }
+        }
+        let unreachable_depth = depth.iter().filter(|&&d| d != usize::MAX).max().map_or(0, |d| d + 1);
+
+        let node_key = |node: NodeIndex| {
+            let t = match depth[node.index()] {
+                usize::MAX => unreachable_depth,
+                d => d,
+            };
+            let in_degree = graph.neighbors_directed(node, petgraph::Direction::Incoming).count();
+            let out_degree = graph.neighbors_directed(node, petgraph::Direction::Outgoing).count();
+            (t as f64, in_degree as f64, out_degree as f64)
+        };
+
+        let mut terms: Vec<f64> = graph
+            .edge_indices()
+            .filter_map(|edge| graph.edge_endpoints(edge))
+            .map(|(src, dst)| {
+                let (t_src, in_src, out_src) = node_key(src);
+                let (t_dst, in_dst, out_dst) = node_key(dst);
+                let weight = t_src
+                    + in_src * 2f64.sqrt()
+                    + out_src * 3f64.sqrt()
+                    + t_dst * 5f64.sqrt()
+                    + in_dst * 7f64.sqrt()
+                    + out_dst * 11f64.sqrt();
+                1.0 / weight.sqrt()
+            })
+            .collect();
+
+        // Sum in a fixed order so equal graphs give bit-identical values
+        terms.sort_by(f64::total_cmp);
+        terms.iter().sum()
     }
 
-    /// MD-Index calculation (similar to Diaphora).
-    /// Produces a hash from function metadata for fast bucketed matching.
+    /// Hex key of [`Self::calculate_md_index_value`] for bucketed matching.
     pub fn calculate_md_index(func: &FunctionInfo) -> String {
-        let combined = format!(
-            "{}:{}:{}:{}",
-            func.size,
-            func.basic_blocks.len(),
-            func.instructions.len(),
-            func.cyclomatic_complexity,
-        );
+        let mut hasher = Sha256::new();
+        hasher.update(Self::calculate_md_index_value(func).to_bits().to_le_bytes());
+        let result = hasher.finalize();
+        hex::encode(&result[..8])
+    }
+
+    /// Dominator-tree signature of a function's CFG.
+    /// Encodes the multiset of (dominator-tree depth, immediately-dominated
+    /// child count) over all reachable blocks, plus the unreachable block
+    /// count. Dominance survives block reordering and most layout changes, so
+    /// functions whose blocks were shuffled by the compiler share a signature.
+    pub fn calculate_dominator_signature(func: &FunctionInfo) -> String {
+        let graph = SimilarityAnalyzer::build_cfg_graph(func);
+        if graph.node_count() == 0 {
+            return String::new();
+        }
+
+        let entry = SimilarityAnalyzer::entry_block_index(func);
+        let doms = dominators::simple_fast(&graph, NodeIndex::new(entry));
+
+        let mut child_counts: HashMap<NodeIndex, usize> = HashMap::new();
+        for node in graph.node_indices() {
+            if let Some(idom) = doms.immediate_dominator(node) {
+                *child_counts.entry(idom).or_insert(0) += 1;
+            }
+        }
+
+        let mut features: Vec<(usize, usize)> = Vec::new();
+        let mut unreachable = 0usize;
+        for node in graph.node_indices() {
+            match doms.dominators(node) {
+                Some(chain) => {
+                    let depth = chain.count().saturating_sub(1);
+                    features.push((depth, child_counts.get(&node).copied().unwrap_or(0)));
+                }
+                None => unreachable += 1,
+            }
+        }
+        features.sort_unstable();
 
         let mut hasher = Sha256::new();
-        hasher.update(combined.as_bytes());
+        for (depth, children) in &features {
+            hasher.update(format!("{}:{};", depth, children).as_bytes());
+        }
+        hasher.update(format!("u{}", unreachable).as_bytes());
         let result = hasher.finalize();
         hex::encode(&result[..8])
     }
 
-    /// Small primes product calculation
-    pub fn calculate_small_primes_product(func: &FunctionInfo) -> u64 {
-        let primes = [
-            2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79,
-            83, 89, 97,
-        ];
-        let mut product = 1u64;
+    /// Compute the reorder-invariant [`StructuralFingerprint`] of a function.
+    pub fn calculate_structural_fingerprint(func: &FunctionInfo) -> StructuralFingerprint {
+        let mut fingerprint = StructuralFingerprint {
+            instruction_simhash: Self::instruction_simhash(func),
+            ..Default::default()
+        };
 
-        for instr in &func.instructions {
-            let mnemonic_hash = instr
-                .mnemonic
-                .bytes()
-                .fold(0u64, |acc, b| acc.wrapping_add(b as u64));
-            let prime_index = (mnemonic_hash % primes.len() as u64) as usize;
-            product = product.wrapping_mul(primes[prime_index]);
+        let graph = SimilarityAnalyzer::build_cfg_graph(func);
+        if graph.node_count() == 0 {
+            return fingerprint;
         }
 
-        product
+        let entry = SimilarityAnalyzer::entry_block_index(func);
+        let doms = dominators::simple_fast(&graph, NodeIndex::new(entry));
+
+        for node in graph.node_indices() {
+            if let Some(chain) = doms.dominators(node) {
+                let depth = chain.count().saturating_sub(1);
+                if fingerprint.dominator_depth_histogram.len() <= depth {
+                    fingerprint.dominator_depth_histogram.resize(depth + 1, 0);
+                }
+                fingerprint.dominator_depth_histogram[depth] += 1;
+            }
+
+            let in_degree = graph.neighbors_directed(node, petgraph::Direction::Incoming).count();
+            let out_degree = graph.neighbors(node).count();
+            fingerprint.degree_sequence.push((in_degree, out_degree));
+        }
+        fingerprint.degree_sequence.sort_unstable();
+
+        for edge in graph.raw_edges() {
+            let dominates = doms
+                .dominators(edge.source())
+                .is_some_and(|mut chain| chain.any(|d| d == edge.target()));
+            if dominates {
+                fingerprint.loop_count += 1;
+            }
+        }
+
+        fingerprint
+    }
+
+    /// 64-bit SimHash over normalized instruction tokens. Each token is
+    /// hashed with FNV-1a so the result is stable across runs and builds.
+    fn instruction_simhash(func: &FunctionInfo) -> u64 {
+        let mut counts = [0i64; 64];
+        for token in func.normalized_instructions(Architecture::Unknown) {
+            let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+            for byte in token.bytes() {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+            }
+            for (bit, count) in counts.iter_mut().enumerate() {
+                if (hash >> bit) & 1 == 1 { *count += 1 } else { *count -= 1 }
+            }
+        }
+
+        counts
+            .iter()
+            .enumerate()
+            .filter(|(_, &count)| count > 0)
+            .fold(0u64, |acc, (bit, _)| acc | (1 << bit))
+    }
+
+    /// Normalized prologue/epilogue signature: the canonical tokens of the
+    /// first and last `length` instructions. Returns `None` when the function
+    /// is too short for the two to be disjoint, or `length` is 0.
+    pub fn calculate_prologue_epilogue_signature(func: &FunctionInfo, length: usize) -> Option<String> {
+        if length == 0 || func.instructions.len() < length * 2 {
+            return None;
+        }
+
+        let tokens = func.normalized_instructions(Architecture::Unknown);
+        let prologue = tokens[..length].join(";");
+        let epilogue = tokens[tokens.len() - length..].join(";");
+        Some(format!("{}|{}", prologue, epilogue))
+    }
+
+    /// The first `count` primes, by a sieve of Eratosthenes over a bound
+    /// taken from the prime number theorem.
+    pub fn sieve_primes(count: usize) -> Vec<u64> {
+        if count == 0 {
+            return Vec::new();
+        }
+        // The n-th prime is below n(ln n + ln ln n) for n >= 6
+        let n = count.max(6) as f64;
+        let limit = (n * (n.ln() + n.ln().ln())).ceil() as usize + 1;
+
+        let mut composite = vec![false; limit + 1];
+        let mut primes = Vec::with_capacity(count);
+        for candidate in 2..=limit {
+            if composite[candidate] {
+                continue;
+            }
+            primes.push(candidate as u64);
+            if primes.len() == count {
+                break;
+            }
+            for multiple in (candidate * candidate..=limit).step_by(candidate) {
+                composite[multiple] = true;
+            }
+        }
+        primes
+    }
+
+    /// Small primes product: the product of each instruction's mnemonic
+    /// prime, encoding the function's mnemonic multiset, and the instruction
+    /// count. The product wraps on overflow, so the count keeps long
+    /// functions whose products collide apart. Mnemonics `primes` wasn't
+    /// built from contribute a factor of 1.
+    pub fn calculate_small_primes_product(func: &FunctionInfo, primes: &MnemonicPrimes) -> (u64, usize) {
+        let product = func
+            .instructions
+            .iter()
+            .filter_map(|instr| primes.prime(&instr.mnemonic))
+            .fold(1u64, |product, prime| product.wrapping_mul(prime));
+        (product, func.instructions.len())
     }
 
     /// Fuzzy hash calculation for functions.
-    /// Encodes basic block structure and instruction mnemonic patterns.
+    /// Encodes basic block structure and instruction mnemonic patterns. Every
+    /// component is length-prefixed, so distinct block layouts or mnemonic
+    /// sequences can't run together into the same hash input.
     pub fn calculate_fuzzy_hash(func: &FunctionInfo) -> String {
-        let mut hash_input = String::new();
+        let mut hasher = Sha256::new();
 
         // Encode basic block structure (instruction count per block, not addresses)
+        hasher.update((func.basic_blocks.len() as u64).to_le_bytes());
         for bb in &func.basic_blocks {
-            hash_input.push_str(&format!("bb{}e{}_", bb.instructions.len(), bb.edges.len()));
+            hasher.update((bb.instructions.len() as u64).to_le_bytes());
+            hasher.update((bb.edges.len() as u64).to_le_bytes());
         }
 
         // Encode instruction mnemonic sequence
+        hasher.update((func.instructions.len() as u64).to_le_bytes());
         for instr in &func.instructions {
-            hash_input.push_str(&instr.mnemonic);
-            hash_input.push('_');
+            hasher.update((instr.mnemonic.len() as u64).to_le_bytes());
+            hasher.update(instr.mnemonic.as_bytes());
         }
 
-        let mut hasher = Sha256::new();
-        hasher.update(hash_input.as_bytes());
         let result = hasher.finalize();
         hex::encode(&result[..8])
     }
 
-    /// Calculate confidence score for a match
+    /// Context-triggered piecewise hash (ssdeep-style) of the function's
+    /// mnemonic stream, as `<block size>:<signature>:<signature at twice the
+    /// block size>`. A rolling hash over the stream ends a chunk wherever it
+    /// hits the block size's trigger value, and each chunk contributes one
+    /// character, so a local edit only changes the characters of the chunks
+    /// it touches. Compare hashes with [`Self::fuzzy_hash_similarity`].
+    pub fn context_triggered_hash(func: &FunctionInfo) -> String {
+        let stream: Vec<u8> = func
+            .instructions
+            .iter()
+            .flat_map(|instr| instr.mnemonic.bytes().chain(std::iter::once(b';')))
+            .collect();
+
+        let mut block_size = CTPH_MIN_BLOCK_SIZE;
+        while (block_size as usize) * CTPH_SIGNATURE_LENGTH < stream.len() {
+            block_size *= 2;
+        }
+
+        loop {
+            let signature = Self::ctph_signature(&stream, block_size);
+            // Too few triggers at this size: retry with smaller chunks
+            if signature.len() < CTPH_SIGNATURE_LENGTH / 2 && block_size > CTPH_MIN_BLOCK_SIZE {
+                block_size /= 2;
+                continue;
+            }
+            let double = Self::ctph_signature(&stream, block_size * 2);
+            return format!("{}:{}:{}", block_size, signature, double);
+        }
+    }
+
+    /// One character per chunk of `stream`, chunks ending where the rolling
+    /// hash is `block_size - 1` modulo `block_size`.
+    fn ctph_signature(stream: &[u8], block_size: u32) -> String {
+        const FNV_OFFSET: u32 = 0x811c_9dc5;
+        const FNV_PRIME: u32 = 0x0100_0193;
+
+        let mut roll = RollingHash::new();
+        let mut chunk_hash = FNV_OFFSET;
+        let mut signature = String::new();
+        let mut pending = false;
+        for &byte in stream {
+            chunk_hash = (chunk_hash ^ byte as u32).wrapping_mul(FNV_PRIME);
+            pending = true;
+            if roll.update(byte) % block_size == block_size - 1 {
+                signature.push(CTPH_ALPHABET[(chunk_hash % 64) as usize] as char);
+                chunk_hash = FNV_OFFSET;
+                pending = false;
+            }
+        }
+        if pending {
+            signature.push(CTPH_ALPHABET[(chunk_hash % 64) as usize] as char);
+        }
+        signature
+    }
+
+    /// Similarity (0.0 to 1.0) of two [`Self::context_triggered_hash`]
+    /// values: one minus the normalized edit distance between signatures
+    /// taken at a common block size. Hashes whose block sizes are neither
+    /// equal nor a factor of two apart, and malformed hashes, score 0.0.
+    pub fn fuzzy_hash_similarity(hash_a: &str, hash_b: &str) -> f64 {
+        fn parse(hash: &str) -> Option<(u32, &str, &str)> {
+            let mut parts = hash.splitn(3, ':');
+            let block_size = parts.next()?.parse().ok()?;
+            Some((block_size, parts.next()?, parts.next()?))
+        }
+
+        let (Some((size_a, sig_a, double_a)), Some((size_b, sig_b, double_b))) = (parse(hash_a), parse(hash_b)) else {
+            return 0.0;
+        };
+        let score = |x: &str, y: &str| {
+            if x.is_empty() && y.is_empty() {
+                1.0
+            } else {
+                SimilarityAnalyzer::normalized_edit_distance(x, y)
+            }
+        };
+
+        if size_a == size_b {
+            score(sig_a, sig_b).max(score(double_a, double_b))
+        } else if size_a == size_b * 2 {
+            score(sig_a, double_b)
+        } else if size_b == size_a * 2 {
+            score(double_a, sig_b)
+        } else {
+            0.0
+        }
+    }
+
+    /// Hash of a function's exact content: every instruction's mnemonic and
+    /// operands plus the block layout. Addresses and names are excluded, so
+    /// byte-for-byte clones at different addresses hash equally.
+    pub fn calculate_content_hash(func: &FunctionInfo) -> String {
+        let mut hasher = Sha256::new();
+        for bb in &func.basic_blocks {
+            hasher.update(format!("bb{}e{};", bb.instructions.len(), bb.edges.len()).as_bytes());
+        }
+        for instr in &func.instructions {
+            hasher.update(instr.mnemonic.as_bytes());
+            for operand in &instr.operands {
+                hasher.update(b",");
+                hasher.update(operand.as_bytes());
+            }
+            hasher.update(b";");
+        }
+        hex::encode(&hasher.finalize()[..16])
+    }
+
+    /// Hash identifying a whole input: SHA-256 over every function's address
+    /// and content hash, in input order.
+    pub fn calculate_input_hash(functions: &[FunctionInfo]) -> String {
+        let mut hasher = Sha256::new();
+        for func in functions {
+            hasher.update(func.address.to_le_bytes());
+            hasher.update(Self::calculate_content_hash(func).as_bytes());
+        }
+        hex::encode(hasher.finalize())
+    }
+
+    /// Group functions of a single binary whose content is identical (see
+    /// [`Self::calculate_content_hash`]). Only groups of two or more are
+    /// returned, each sorted by index, ordered by first index. Functions
+    /// without instructions are ignored.
+    pub fn find_internal_duplicates(functions: &[FunctionInfo]) -> Vec<Vec<usize>> {
+        let mut groups: FxHashMap<String, Vec<usize>> = FxHashMap::default();
+        for (idx, func) in functions.iter().enumerate() {
+            if !func.instructions.is_empty() {
+                groups.entry(Self::calculate_content_hash(func)).or_default().push(idx);
+            }
+        }
+
+        let mut duplicates: Vec<Vec<usize>> = groups.into_values().filter(|g| g.len() > 1).collect();
+        duplicates.sort_unstable_by_key(|g| g[0]);
+        duplicates
+    }
+
+    /// Calculate confidence score for a match: a logistic combination of
+    /// the similarity and how closely block count, cyclomatic complexity
+    /// and size agree. A pair of similarity 0.5 with identical shape is a
+    /// coin flip (0.5); shape disagreement pulls confidence down. Unlike
+    /// additive boosts clamped at 1.0, strong matches of different quality
+    /// stay distinguishable and never reach exactly 1.0, which is reserved
+    /// for `Manual` matches and hash-identical `Exact` matches, whose
+    /// constructors set it directly.
     pub fn calculate_confidence(
         func_a: &FunctionInfo,
         func_b: &FunctionInfo,
         similarity: f64,
     ) -> f64 {
-        let mut confidence = similarity;
+        // Logit weights: similarity spans [-4, 4] around the 0.5 midpoint,
+        // complete shape disagreement costs up to 3 more
+        const SIMILARITY_WEIGHT: f64 = 8.0;
+        const SIMILARITY_MIDPOINT: f64 = 0.5;
+        const AGREEMENT_WEIGHT: f64 = 3.0;
 
-        // Boost confidence for exact structural matches
-        if func_a.basic_blocks.len() == func_b.basic_blocks.len() {
-            confidence += 0.1;
+        // Relative agreement in [0, 1]; abs_diff stays exact for sizes near
+        // u64::MAX, where subtracting the f64 conversions would lose the
+        // difference entirely
+        fn agreement(a: u64, b: u64) -> f64 {
+            let max = a.max(b);
+            if max == 0 { 1.0 } else { 1.0 - a.abs_diff(b) as f64 / max as f64 }
         }
 
-        // Boost confidence for similar complexity
-        let complexity_diff =
-            (func_a.cyclomatic_complexity as f64 - func_b.cyclomatic_complexity as f64).abs();
-        if complexity_diff < 2.0 {
-            confidence += 0.1;
-        }
-
-        // Boost confidence for similar size
-        let max_size = func_a.size.max(func_b.size) as f64;
-        if max_size > 0.0 {
-            let size_diff = (func_a.size as f64 - func_b.size as f64).abs() / max_size;
-            if size_diff < 0.1 {
-                confidence += 0.1;
-            }
-        }
+        let blocks = agreement(func_a.basic_blocks.len() as u64, func_b.basic_blocks.len() as u64);
+        let complexity = agreement(func_a.cyclomatic_complexity.into(), func_b.cyclomatic_complexity.into());
+        let size = agreement(func_a.size, func_b.size);
+        let shape_agreement = (blocks + complexity + size) / 3.0;
 
-        sanitize_score(confidence)
+        let logit = SIMILARITY_WEIGHT * (sanitize_score(similarity) - SIMILARITY_MIDPOINT)
+            - AGREEMENT_WEIGHT * (1.0 - shape_agreement);
+        sanitize_score(1.0 / (1.0 + (-logit).exp()))
     }
 
     /// Perform isomorphic subgraph matching (edge-degree distribution check)
@@ -254,4 +852,700 @@ impl DiffAlgorithms {
 
         edge_counts_a == edge_counts_b
     }
+
+    /// Check whether two functions have a similar CFG shape within the given
+    /// tolerance: block count, cyclomatic complexity, byte size, and CFG
+    /// depth and width.
+    pub fn is_structurally_similar(
+        func_a: &FunctionInfo,
+        func_b: &FunctionInfo,
+        tolerance: &StructuralTolerance,
+    ) -> bool {
+        let bb_a = func_a.basic_blocks.len();
+        let bb_b = func_b.basic_blocks.len();
+        if bb_a.abs_diff(bb_b) > tolerance.allowed_block_diff(bb_a.max(bb_b)) {
+            return false;
+        }
+
+        let cc_a = func_a.cyclomatic_complexity;
+        let cc_b = func_b.cyclomatic_complexity;
+        if cc_a.abs_diff(cc_b) > tolerance.allowed_complexity_diff(cc_a.max(cc_b)) {
+            return false;
+        }
+
+        let max_size = func_a.size.max(func_b.size);
+        if max_size > 0 {
+            let size_diff = func_a.size.abs_diff(func_b.size) as f64 / max_size as f64;
+            if size_diff > tolerance.max_size_diff_ratio {
+                return false;
+            }
+        }
+
+        let (depth_a, width_a) = SimilarityAnalyzer::cfg_depth_and_width(func_a);
+        let (depth_b, width_b) = SimilarityAnalyzer::cfg_depth_and_width(func_b);
+        depth_a.abs_diff(depth_b) <= tolerance.allowed_depth_diff(depth_a.max(depth_b))
+            && width_a.abs_diff(width_b) <= tolerance.allowed_width_diff(width_a.max(width_b))
+    }
+
+    /// If `func` is a single-instruction jump thunk, return its jump target
+    /// operand with memory-operand decoration (`qword ptr [...]`) stripped.
+    pub fn thunk_target(func: &FunctionInfo, jump_mnemonics: &[String]) -> Option<String> {
+        if func.instructions.len() != 1 {
+            return None;
+        }
+        let instr = &func.instructions[0];
+        if !jump_mnemonics.iter().any(|m| m.eq_ignore_ascii_case(instr.mnemonic.trim())) {
+            return None;
+        }
+
+        let operand = instr.operands.first()?;
+        let target = operand
+            .rsplit(' ')
+            .next()
+            .unwrap_or(operand)
+            .trim_matches(|c| c == '[' || c == ']')
+            .trim();
+        if target.is_empty() {
+            None
+        } else {
+            Some(target.to_string())
+        }
+    }
+
+    /// Resolve a thunk target operand to the index of the function it jumps
+    /// to, by address (`0x...`) or by name.
+    pub fn resolve_thunk_target(target: &str, functions: &[FunctionInfo]) -> Option<usize> {
+        let address = target
+            .strip_prefix("0x")
+            .and_then(|hex| u64::from_str_radix(hex, 16).ok());
+
+        functions.iter().position(|f| match address {
+            Some(addr) => f.address == addr,
+            None => f.name == target,
+        })
+    }
+
+    /// Classify every function in one binary by its role in recursion.
+    /// Call targets are resolved by address (`0x...`) or name within
+    /// `functions`; unresolved targets are ignored. Cycles are found with a
+    /// single strongly-connected-components pass, so arbitrarily deep or
+    /// mutually recursive call graphs are handled without traversal loops.
+    pub fn recursion_kinds(functions: &[FunctionInfo]) -> Vec<RecursionKind> {
+        let mut by_name: FxHashMap<&str, usize> = FxHashMap::default();
+        let mut by_address: FxHashMap<u64, usize> = FxHashMap::default();
+        for (idx, func) in functions.iter().enumerate() {
+            by_name.entry(func.name.as_str()).or_insert(idx);
+            by_address.entry(func.address).or_insert(idx);
+        }
+
+        let mut graph: Graph<usize, ()> = Graph::new();
+        let nodes: Vec<NodeIndex> = (0..functions.len()).map(|idx| graph.add_node(idx)).collect();
+        let mut kinds = vec![RecursionKind::NonRecursive; functions.len()];
+
+        for (idx, func) in functions.iter().enumerate() {
+            for instr in func.instructions.iter().filter(|i| i.is_call()) {
+                let Some(target) = instr.operands.first() else { continue };
+                let callee = target
+                    .strip_prefix("0x")
+                    .and_then(|hex| u64::from_str_radix(hex, 16).ok())
+                    .and_then(|addr| by_address.get(&addr))
+                    .or_else(|| by_name.get(target.as_str()));
+
+                match callee {
+                    Some(&callee) if callee == idx => kinds[idx] = RecursionKind::SelfRecursive,
+                    Some(&callee) => {
+                        graph.update_edge(nodes[idx], nodes[callee], ());
+                    }
+                    None => {}
+                }
+            }
+        }
+
+        for component in tarjan_scc(&graph) {
+            if component.len() > 1 {
+                for node in component {
+                    kinds[graph[node]] = RecursionKind::Mutual;
+                }
+            }
+        }
+
+        kinds
+    }
+
+    /// Solve the assignment problem on a score matrix (Hungarian /
+    /// Kuhn-Munkres, O(n³)): pick at most one column per row and one row per
+    /// column so the total score is maximal. The matrix may be rectangular
+    /// or ragged; missing and NaN entries score 0.0. Returns the assigned
+    /// column for each row; rows left over when there are more rows than
+    /// columns get `None`. Zero-score assignments are not filtered out.
+    pub fn optimal_assignment(scores: &[Vec<f64>]) -> Vec<Option<usize>> {
+        let rows = scores.len();
+        let cols = scores.iter().map(Vec::len).max().unwrap_or(0);
+        let n = rows.max(cols);
+        if n == 0 {
+            return Vec::new();
+        }
+
+        // Minimize negated scores over the square matrix padded with zeros;
+        // indices below are 1-based with 0 as the sentinel
+        let cost = |i: usize, j: usize| -> f64 {
+            let score = scores.get(i).and_then(|row| row.get(j)).copied().unwrap_or(0.0);
+            if score.is_nan() { 0.0 } else { -score }
+        };
+        let mut u = vec![0.0; n + 1];
+        let mut v = vec![0.0; n + 1];
+        // row_of[j] = row assigned to column j
+        let mut row_of = vec![0usize; n + 1];
+        let mut way = vec![0usize; n + 1];
+
+        for i in 1..=n {
+            row_of[0] = i;
+            let mut j0 = 0;
+            let mut min_slack = vec![f64::INFINITY; n + 1];
+            let mut visited = vec![false; n + 1];
+            loop {
+                visited[j0] = true;
+                let i0 = row_of[j0];
+                let mut delta = f64::INFINITY;
+                let mut j1 = 0;
+                for j in 1..=n {
+                    if visited[j] {
+                        continue;
+                    }
+                    let slack = cost(i0 - 1, j - 1) - u[i0] - v[j];
+                    if slack < min_slack[j] {
+                        min_slack[j] = slack;
+                        way[j] = j0;
+                    }
+                    if min_slack[j] < delta {
+                        delta = min_slack[j];
+                        j1 = j;
+                    }
+                }
+                for j in 0..=n {
+                    if visited[j] {
+                        u[row_of[j]] += delta;
+                        v[j] -= delta;
+                    } else {
+                        min_slack[j] -= delta;
+                    }
+                }
+                j0 = j1;
+                if row_of[j0] == 0 {
+                    break;
+                }
+            }
+            // Flip the augmenting path
+            while j0 != 0 {
+                let j1 = way[j0];
+                row_of[j0] = row_of[j1];
+                j0 = j1;
+            }
+        }
+
+        let mut assignment = vec![None; rows];
+        for (j, &i) in row_of.iter().enumerate().skip(1).take(cols) {
+            if (1..=rows).contains(&i) {
+                assignment[i - 1] = Some(j - 1);
+            }
+        }
+        assignment
+    }
+
+    /// Fill in the address rank of both functions of each match within
+    /// their binaries.
+    pub fn assign_layout_positions(matches: &mut [FunctionMatch], functions_a: &[FunctionInfo], functions_b: &[FunctionInfo]) {
+        let sorted_addresses = |functions: &[FunctionInfo]| {
+            let mut addrs: Vec<u64> = functions.iter().map(|f| f.address).collect();
+            addrs.sort_unstable();
+            addrs
+        };
+        let addrs_a = sorted_addresses(functions_a);
+        let addrs_b = sorted_addresses(functions_b);
+
+        for m in matches {
+            m.position_a = addrs_a.partition_point(|&addr| addr < m.function_a.address);
+            m.position_b = addrs_b.partition_point(|&addr| addr < m.function_b.address);
+        }
+    }
+
+    /// Find matches whose position shift differs from the median shift of
+    /// the two matches on either side (in binary A order) by more than
+    /// `threshold`. Insertions and deletions shift whole runs of functions
+    /// equally, so only functions that actually moved stand out.
+    pub fn find_relocated_functions(matches: &[FunctionMatch], threshold: usize) -> Vec<RelocatedFunction> {
+        const NEIGHBORS: usize = 2;
+
+        let mut ordered: Vec<&FunctionMatch> = matches.iter().collect();
+        ordered.sort_by_key(|m| m.position_a);
+
+        let mut relocated = Vec::new();
+        for (i, m) in ordered.iter().enumerate() {
+            let window = i.saturating_sub(NEIGHBORS)..(i + NEIGHBORS + 1).min(ordered.len());
+            let mut neighbor_shifts: Vec<i64> =
+                window.filter(|&j| j != i).map(|j| ordered[j].position_shift()).collect();
+            if neighbor_shifts.is_empty() {
+                continue;
+            }
+            neighbor_shifts.sort_unstable();
+            let neighbor_shift = neighbor_shifts[neighbor_shifts.len() / 2];
+
+            if m.position_shift().abs_diff(neighbor_shift) > threshold as u64 {
+                relocated.push(RelocatedFunction {
+                    name: m.function_a.name.clone(),
+                    address_a: m.function_a.address,
+                    address_b: m.function_b.address,
+                    position_shift: m.position_shift(),
+                    neighbor_shift,
+                });
+            }
+        }
+
+        relocated
+    }
+}
+
+impl DiffResult {
+    /// Re-score every match under `weights` without re-matching: similarity,
+    /// details and confidence are recomputed from the stored function data
+    /// and the overall score is averaged again. Pairings are unchanged;
+    /// manual matches keep their scores and exact matches their 1.0
+    /// confidence.
+    pub fn rescore(&self, weights: &SimilarityWeights) -> DiffResult {
+        let mut rescored = self.clone();
+
+        for m in rescored.matched_functions.iter_mut().filter(|m| m.match_type != MatchType::Manual) {
+            let (similarity, details) =
+                DiffAlgorithms::compute_match_details_weighted(&m.function_a, &m.function_b, weights);
+            m.similarity = similarity;
+            m.details = details;
+            if !m.match_type.has_fixed_confidence() {
+                m.confidence = DiffAlgorithms::calculate_confidence(&m.function_a, &m.function_b, similarity);
+            }
+        }
+
+        rescored.similarity_score = if rescored.matched_functions.is_empty() {
+            0.0
+        } else {
+            rescored.matched_functions.iter().map(|m| m.similarity).sum::<f64>()
+                / rescored.matched_functions.len() as f64
+        };
+
+        rescored
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn optimal_assignment_beats_greedy() {
+        // Greedy takes 0.9 first and strands row 1: 0.9 + 0.0 + 0.7
+        let scores = vec![vec![0.9, 0.8, 0.0], vec![0.85, 0.0, 0.0], vec![0.0, 0.1, 0.7]];
+        let assignment = DiffAlgorithms::optimal_assignment(&scores);
+        assert_eq!(assignment, vec![Some(1), Some(0), Some(2)]);
+        let total: f64 = assignment.iter().enumerate().map(|(row, col)| scores[row][col.unwrap()]).sum();
+        assert!((total - 2.35).abs() < 1e-9);
+
+        // More rows than columns: one row stays unassigned
+        let tall = vec![vec![0.1, 0.9], vec![0.8, 0.7], vec![0.6, 0.0]];
+        assert_eq!(DiffAlgorithms::optimal_assignment(&tall), vec![Some(1), Some(0), None]);
+        let wide = vec![vec![0.5, 0.2, 0.9, 0.1]];
+        assert_eq!(DiffAlgorithms::optimal_assignment(&wide), vec![Some(2)]);
+        assert!(DiffAlgorithms::optimal_assignment(&[]).is_empty());
+    }
+
+    #[test]
+    fn structural_fingerprint_ignores_block_order() {
+        let functions = crate::mock::generate_mock_functions("binary").unwrap();
+        let function = &functions[7];
+        let mut reordered = function.clone();
+        reordered.basic_blocks.reverse();
+        reordered.basic_blocks.rotate_left(2);
+
+        let fingerprint = DiffAlgorithms::calculate_structural_fingerprint(function);
+        assert_eq!(fingerprint.distance(&DiffAlgorithms::calculate_structural_fingerprint(&reordered)), 0.0);
+        assert!(fingerprint.distance(&DiffAlgorithms::calculate_structural_fingerprint(&functions[1])) > 0.1);
+    }
+
+    #[test]
+    fn confidence_separates_strong_matches_below_one() {
+        let function = crate::mock::generate_mock_functions("binary").unwrap().remove(7);
+        let mut resized = function.clone();
+        resized.size += 40;
+
+        let strong = DiffAlgorithms::calculate_confidence(&function, &function, 0.95);
+        let weaker = DiffAlgorithms::calculate_confidence(&function, &resized, 0.85);
+        assert!(strong < 1.0 && weaker < 1.0);
+        assert!(strong > weaker && weaker > 0.5);
+        // Half-similar with identical shape is a coin flip
+        assert!((DiffAlgorithms::calculate_confidence(&function, &function, 0.5) - 0.5).abs() < 1e-9);
+        assert!(DiffAlgorithms::calculate_confidence(&function, &resized, f64::NAN).is_finite());
+
+        // Hash-identical matches are certain by construction
+        let matches = crate::matching::MatchingEngine::new().match_functions(std::slice::from_ref(&function), std::slice::from_ref(&function)).unwrap();
+        assert_eq!(matches[0].match_type, MatchType::Exact);
+        assert_eq!(matches[0].confidence, 1.0);
+    }
+
+    #[test]
+    fn structural_tolerance_scales_with_function_size() {
+        let function = |blocks: usize, complexity: u32, size: u64| FunctionInfo {
+            basic_blocks: vec![Default::default(); blocks],
+            cyclomatic_complexity: complexity,
+            size,
+            ..Default::default()
+        };
+        let tolerance = StructuralTolerance::default();
+        // Three extra blocks are noise in a large function but not a small one
+        assert!(DiffAlgorithms::is_structurally_similar(&function(60, 30, 3000), &function(63, 32, 3100), &tolerance));
+        assert!(!DiffAlgorithms::is_structurally_similar(&function(6, 3, 300), &function(9, 3, 300), &tolerance));
+
+        let strict = StructuralTolerance { block_diff_ratio: 0.0, ..Default::default() };
+        assert!(!DiffAlgorithms::is_structurally_similar(&function(60, 30, 3000), &function(63, 32, 3100), &strict));
+    }
+
+    fn with_blocks(blocks: &[(u64, &[u64])]) -> FunctionInfo {
+        FunctionInfo {
+            address: blocks[0].0,
+            basic_blocks: blocks
+                .iter()
+                .map(|&(address, edges)| crate::types::BasicBlockInfo { address, edges: edges.to_vec(), ..Default::default() })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn dominator_signature_ignores_layout_but_not_shape() {
+        let diamond = with_blocks(&[(1, &[2, 3]), (2, &[4]), (3, &[4]), (4, &[])]);
+        let mut relocated = with_blocks(&[(10, &[20, 30]), (20, &[40]), (30, &[40]), (40, &[])]);
+        relocated.basic_blocks.reverse();
+        let chain = with_blocks(&[(1, &[2]), (2, &[3]), (3, &[4]), (4, &[])]);
+
+        let signature = DiffAlgorithms::calculate_dominator_signature(&diamond);
+        assert_eq!(signature, DiffAlgorithms::calculate_dominator_signature(&relocated));
+        assert_ne!(signature, DiffAlgorithms::calculate_dominator_signature(&chain));
+    }
+
+    #[test]
+    fn edge_similarity_compares_edge_types_not_counts() {
+        // Four edges each: two conditional branches in one, unconditional
+        // jumps and a back edge in the other
+        let mut branchy = with_blocks(&[(0, &[0x10, 0x30]), (0x10, &[0x30, 0x20]), (0x20, &[]), (0x30, &[])]);
+        let mut looping = with_blocks(&[(0, &[0x30]), (0x10, &[0x30]), (0x20, &[0x30]), (0x30, &[0x0])]);
+        for block in branchy.basic_blocks.iter_mut().chain(&mut looping.basic_blocks) {
+            block.size = 0x10;
+        }
+
+        let (_, details) = DiffAlgorithms::compute_match_details(&branchy, &looping);
+        assert!(details.edge_similarity < 1.0);
+        let (_, details) = DiffAlgorithms::compute_match_details(&branchy, &branchy);
+        assert_eq!(details.edge_similarity, 1.0);
+    }
+
+    #[test]
+    fn explained_contributions_sum_to_the_similarity() {
+        let functions_a = crate::mock::generate_mock_functions("binary_a").unwrap();
+        let functions_b = crate::mock::generate_mock_functions("binary_b").unwrap();
+        for func_a in &functions_a {
+            for func_b in &functions_b {
+                let (similarity, _, contributions) = DiffAlgorithms::calculate_function_similarity_explained(func_a, func_b);
+                assert!((contributions.iter().map(|(_, c)| c).sum::<f64>() - similarity).abs() < 1e-12);
+                assert_eq!(similarity, DiffAlgorithms::calculate_function_similarity(func_a, func_b));
+            }
+        }
+    }
+
+    #[test]
+    fn recursion_kinds_detect_self_and_mutual_recursion() {
+        let function = |name: &str, address: u64, callees: &[&str]| FunctionInfo {
+            name: name.to_string(),
+            address,
+            instructions: callees
+                .iter()
+                .map(|callee| crate::types::InstructionInfo {
+                    mnemonic: "call".to_string(),
+                    operands: vec![callee.to_string()],
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        };
+        // `even` calls `odd` by address, `odd` calls `even` by name
+        let functions = [
+            function("fact", 0x10, &["fact"]),
+            function("even", 0x20, &["0x30"]),
+            function("odd", 0x30, &["even"]),
+            function("leaf", 0x40, &["printf"]),
+        ];
+        assert_eq!(
+            DiffAlgorithms::recursion_kinds(&functions),
+            vec![RecursionKind::SelfRecursive, RecursionKind::Mutual, RecursionKind::Mutual, RecursionKind::NonRecursive]
+        );
+
+        // A renamed self-call still matches the original self-call
+        let renamed = function("sub_50", 0x50, &["0x50"]);
+        assert_eq!(SimilarityAnalyzer::function_call_similarity(&functions[0], &renamed), 1.0);
+    }
+
+    #[test]
+    fn extreme_sizes_and_addresses_stay_in_range() {
+        let huge = FunctionInfo {
+            name: "huge".to_string(),
+            address: u64::MAX - 1,
+            size: u64::MAX,
+            cyclomatic_complexity: u32::MAX,
+            basic_blocks: vec![crate::types::BasicBlockInfo {
+                address: u64::MAX - 1,
+                size: u64::MAX,
+                edges: vec![u64::MAX],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let empty = FunctionInfo::default();
+        for (a, b) in [(&huge, &empty), (&empty, &huge), (&empty, &empty), (&huge, &huge)] {
+            let similarity = DiffAlgorithms::calculate_function_similarity(a, b);
+            assert!((0.0..=1.0).contains(&similarity));
+            assert!((0.0..=1.0).contains(&DiffAlgorithms::calculate_confidence(a, b, similarity)));
+            DiffAlgorithms::is_structurally_similar(a, b, &StructuralTolerance::default());
+        }
+        crate::matching::MatchingEngine::new().match_functions(&[huge.clone(), empty.clone()], &[empty, huge]).unwrap();
+    }
+
+    #[test]
+    fn rescore_with_default_weights_reproduces_the_diff() {
+        let functions_a = crate::mock::generate_mock_functions("binary_a").unwrap();
+        let mut functions_b = crate::mock::generate_mock_functions("binary_b").unwrap();
+        for function in functions_b.iter_mut().step_by(2) {
+            function.name.push_str("_v2");
+            function.instructions[0].mnemonic = "nop".to_string();
+        }
+        let matches = crate::matching::MatchingEngine::with_thresholds(0.3, 0.3).match_functions(&functions_a, &functions_b).unwrap();
+        let result = DiffResult {
+            similarity_score: matches.iter().map(|m| m.similarity).sum::<f64>() / matches.len() as f64,
+            matched_functions: matches,
+            ..Default::default()
+        };
+
+        let same = result.rescore(&SimilarityWeights::default());
+        for (before, after) in result.matched_functions.iter().zip(&same.matched_functions) {
+            assert_eq!(before.similarity, after.similarity);
+            assert_eq!(before.confidence, after.confidence);
+        }
+        assert!((result.similarity_score - same.similarity_score).abs() < 1e-12);
+
+        let names_only = SimilarityWeights::new(0.0, 0.0, 0.0, 0.0, 1.0, 0.0).unwrap();
+        assert_ne!(result.rescore(&names_only).similarity_score, result.similarity_score);
+    }
+
+    #[test]
+    fn internal_duplicates_group_identical_bodies() {
+        let mut functions = crate::mock::generate_mock_functions("binary").unwrap();
+        let original = functions.len();
+        for offset in [0x9000, 0x9100] {
+            let mut clone = functions[2].clone();
+            clone.address = offset;
+            clone.name = format!("clone_{:x}", offset);
+            functions.push(clone);
+        }
+        assert_eq!(DiffAlgorithms::find_internal_duplicates(&functions), vec![vec![2, original, original + 1]]);
+    }
+
+    #[test]
+    fn moved_function_is_reported_as_relocated() {
+        let function = |name: &str, address: u64| FunctionInfo { name: name.to_string(), address, ..Default::default() };
+        let names = ["a", "b", "c", "d", "e", "f", "g", "h"];
+        let functions_a: Vec<_> = names.iter().zip(0..).map(|(name, i)| function(name, 0x1000 + 0x100 * i)).collect();
+        // In B, "a" moves to the end and every other function shifts down
+        let mut functions_b: Vec<_> = names[1..].iter().zip(0..).map(|(name, i)| function(name, 0x2000 + 0x100 * i)).collect();
+        functions_b.push(function("a", 0x2700));
+        let mut matches: Vec<FunctionMatch> = functions_a
+            .iter()
+            .map(|a| FunctionMatch {
+                function_a: a.clone(),
+                function_b: functions_b.iter().find(|b| b.name == a.name).unwrap().clone(),
+                ..Default::default()
+            })
+            .collect();
+
+        DiffAlgorithms::assign_layout_positions(&mut matches, &functions_a, &functions_b);
+        assert_eq!((matches[0].position_a, matches[0].position_b), (0, 7));
+        assert_eq!(matches[0].address_delta(), 0x2700 - 0x1000);
+        assert_eq!(matches[1].address_delta(), 0x2000 - 0x1100);
+
+        let relocated = DiffAlgorithms::find_relocated_functions(&matches, 2);
+        assert_eq!(relocated.len(), 1);
+        assert_eq!(relocated[0].name, "a");
+        assert_eq!(relocated[0].position_shift, 7);
+        assert_eq!(relocated[0].neighbor_shift, -1);
+    }
+
+    #[test]
+    fn md_index_depends_on_topology_not_layout() {
+        let diamond = with_blocks(&[(0x100, &[0x110, 0x120]), (0x110, &[0x130]), (0x120, &[0x130]), (0x130, &[])]);
+        // The same diamond elsewhere in memory, with its blocks listed in another order
+        let mut relocated = with_blocks(&[(0x930, &[]), (0x920, &[0x930]), (0x900, &[0x920, 0x910]), (0x910, &[0x930])]);
+        relocated.address = 0x900;
+        relocated.size = 999;
+        assert_eq!(DiffAlgorithms::calculate_md_index(&diamond), DiffAlgorithms::calculate_md_index(&relocated));
+        assert!(DiffAlgorithms::calculate_md_index_value(&diamond) > 0.0);
+
+        // Same block and edge counts, but a loop instead of a diamond
+        let looped = with_blocks(&[(0x100, &[0x110]), (0x110, &[0x120]), (0x120, &[0x130, 0x110]), (0x130, &[])]);
+        assert_ne!(DiffAlgorithms::calculate_md_index(&diamond), DiffAlgorithms::calculate_md_index(&looped));
+        assert_eq!(DiffAlgorithms::calculate_md_index_value(&FunctionInfo::default()), 0.0);
+    }
+
+    #[test]
+    fn fuzzy_hash_depends_on_content_not_length() {
+        let mnemonics = |mnemonics: &[&str]| FunctionInfo {
+            instructions: mnemonics
+                .iter()
+                .map(|m| crate::types::InstructionInfo { mnemonic: m.to_string(), ..Default::default() })
+                .collect(),
+            ..Default::default()
+        };
+        let hash = |names: &[&str]| DiffAlgorithms::calculate_fuzzy_hash(&mnemonics(names));
+
+        assert_ne!(hash(&["mov", "add"]), hash(&["xor", "sub"]));
+        assert_ne!(hash(&["a_b"]), hash(&["a", "b"]));
+        assert_eq!(hash(&["mov", "add"]), hash(&["mov", "add"]));
+        assert_eq!(hash(&["mov", "add"]).len(), 16);
+    }
+
+    #[test]
+    fn context_triggered_hash_survives_a_local_edit() {
+        let stream = |seed: u64, length: usize| -> Vec<String> {
+            const MNEMONICS: [&str; 8] = ["mov", "add", "sub", "cmp", "jne", "push", "pop", "lea"];
+            let mut state = seed;
+            (0..length)
+                .map(|_| {
+                    state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                    MNEMONICS[(state >> 33) as usize % MNEMONICS.len()].to_string()
+                })
+                .collect()
+        };
+        let hash = |mnemonics: &[String]| {
+            DiffAlgorithms::context_triggered_hash(&FunctionInfo {
+                instructions: mnemonics
+                    .iter()
+                    .map(|m| crate::types::InstructionInfo { mnemonic: m.clone(), ..Default::default() })
+                    .collect(),
+                ..Default::default()
+            })
+        };
+
+        for seed in 1..=5 {
+            let original = stream(seed, 300);
+            let mut edited = original.clone();
+            edited.insert(150, "imul".to_string());
+            let (before, after) = (hash(&original), hash(&edited));
+            assert_eq!(DiffAlgorithms::fuzzy_hash_similarity(&before, &before), 1.0);
+            assert!(DiffAlgorithms::fuzzy_hash_similarity(&before, &after) > 0.8, "{} {}", before, after);
+        }
+
+        let (first, unrelated) = (hash(&stream(1, 300)), hash(&stream(99, 300)));
+        assert!(DiffAlgorithms::fuzzy_hash_similarity(&first, &unrelated) < 0.6, "{} {}", first, unrelated);
+        assert_eq!(DiffAlgorithms::fuzzy_hash_similarity("x", &first), 0.0);
+        assert_eq!(DiffAlgorithms::context_triggered_hash(&FunctionInfo::default()), "3::");
+    }
+
+    #[test]
+    fn every_mnemonic_gets_its_own_sieved_prime() {
+        assert_eq!(DiffAlgorithms::sieve_primes(10), vec![2, 3, 5, 7, 11, 13, 17, 19, 23, 29]);
+        assert_eq!(DiffAlgorithms::sieve_primes(1000).len(), 1000);
+        assert_eq!(DiffAlgorithms::sieve_primes(1000).last(), Some(&7919));
+        assert!(DiffAlgorithms::sieve_primes(0).is_empty());
+        assert_eq!(DiffAlgorithms::sieve_primes(1), vec![2]);
+
+        let mnemonics = |mnemonics: &[&str]| FunctionInfo {
+            instructions: mnemonics
+                .iter()
+                .map(|m| crate::types::InstructionInfo { mnemonic: m.to_string(), ..Default::default() })
+                .collect(),
+            ..Default::default()
+        };
+        let a = mnemonics(&["mov", "add", "mov", "ret"]);
+        let reordered = mnemonics(&["add", "mov", "ret", "mov"]);
+        let longer = mnemonics(&["add", "mov", "ret", "mov", "nop"]);
+        // "vom" has the same byte sum as "mov"
+        let anagram = mnemonics(&["vom", "add", "mov", "ret"]);
+        let primes = MnemonicPrimes::from_functions([&a, &reordered, &longer, &anagram]);
+        let product = |f: &FunctionInfo| DiffAlgorithms::calculate_small_primes_product(f, &primes);
+        assert_eq!(product(&a), product(&reordered));
+        assert_ne!(product(&a), product(&longer));
+        assert_ne!(product(&a), product(&anagram));
+
+        let assigned: std::collections::HashSet<u64> =
+            ["add", "mov", "nop", "ret", "vom"].iter().map(|m| primes.prime(m).unwrap()).collect();
+        assert_eq!(assigned.len(), 5);
+        assert_eq!(primes.prime("zzz"), None);
+        assert_eq!(product(&mnemonics(&["mov"; 200])).1, 200);
+    }
+
+    #[test]
+    fn similarity_weights_are_validated_and_applied() {
+        assert!(SimilarityWeights::new(0.3, 0.2, 0.15, 0.15, 0.1, 0.1).is_ok());
+        assert!(SimilarityWeights::new(0.5, 0.2, 0.15, 0.15, 0.1, 0.1).is_err());
+        assert!(SimilarityWeights::new(-0.1, 0.4, 0.15, 0.15, 0.3, 0.1).is_err());
+        assert!(SimilarityWeights::new(f64::NAN, 0.2, 0.15, 0.15, 0.1, 0.1).is_err());
+
+        let block = |address: u64, edges: &[u64], mnemonic: &str| crate::types::BasicBlockInfo {
+            address,
+            size: 4,
+            edges: edges.to_vec(),
+            mnemonic_hash: mnemonic.to_string(),
+            instructions: vec![crate::types::InstructionInfo { mnemonic: mnemonic.to_string(), ..Default::default() }],
+            instruction_count: 1,
+            ..Default::default()
+        };
+        let a = FunctionInfo {
+            name: "foo".to_string(),
+            cfg_hash: "same".to_string(),
+            basic_blocks: vec![block(0, &[4], "mov"), block(4, &[], "ret")],
+            ..Default::default()
+        };
+        // Same CFG, nothing else in common
+        let b = FunctionInfo {
+            name: "zzzzz".to_string(),
+            basic_blocks: vec![block(0, &[4], "push"), block(4, &[], "push")],
+            ..a.clone()
+        };
+
+        let default = DiffAlgorithms::calculate_function_similarity(&a, &b);
+        assert_eq!(default, DiffAlgorithms::calculate_function_similarity_weighted(&a, &b, &SimilarityWeights::default()));
+        let without_cfg = SimilarityWeights::new(0.0, 0.3, 0.2, 0.2, 0.15, 0.15).unwrap();
+        let weighted = DiffAlgorithms::calculate_function_similarity_weighted(&a, &b, &without_cfg);
+        assert!(weighted < default, "{} {}", weighted, default);
+    }
+
+    #[test]
+    fn block_similarity_ignores_block_order_and_credits_near_matches() {
+        let block = |address: u64, mnemonics: &[&str]| crate::types::BasicBlockInfo {
+            address,
+            mnemonic_hash: mnemonics.join(","),
+            instructions: mnemonics
+                .iter()
+                .map(|m| crate::types::InstructionInfo { mnemonic: m.to_string(), ..Default::default() })
+                .collect(),
+            instruction_count: mnemonics.len(),
+            ..Default::default()
+        };
+        let function = |blocks: Vec<crate::types::BasicBlockInfo>| FunctionInfo { basic_blocks: blocks, ..Default::default() };
+
+        let a = function(vec![block(0, &["push", "mov", "cmp"]), block(1, &["add", "sub", "jmp"]), block(2, &["pop", "ret"])]);
+        let b = function(vec![block(0, &["push", "mov", "test"]), block(1, &["add", "sub", "jmp"]), block(2, &["pop", "leave", "ret"])]);
+        let mut reordered = b.clone();
+        reordered.basic_blocks.reverse();
+
+        let score = DiffAlgorithms::calculate_basic_block_similarity(&a, &b);
+        assert!((score - DiffAlgorithms::calculate_basic_block_similarity(&a, &reordered)).abs() < 1e-9);
+        // Only one block is identical, but the other two are close
+        assert!(score > 1.0 / 3.0, "{}", score);
+        assert!(score < 1.0);
+        assert_eq!(DiffAlgorithms::calculate_basic_block_similarity(&a, &a), 1.0);
+    }
 }
@@ -0,0 +1,112 @@
+use crate::{BinaryDiffEngine, DiffResult, FunctionMatch, MockProvider};
+use anyhow::{Result, anyhow};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread::{self, JoinHandle};
+
+/// A diff running on a background thread, so GUI plugins can keep their
+/// event loop responsive. Poll [`DiffHandle::is_finished`] or
+/// [`DiffHandle::matches_found`] from the UI and collect the result with
+/// [`DiffHandle::wait`] once it is done.
+pub struct DiffHandle {
+    cancelled: Arc<AtomicBool>,
+    matches_found: Arc<AtomicUsize>,
+    thread: JoinHandle<Result<DiffResult>>,
+}
+
+impl DiffHandle {
+    /// Ask the diff to stop. It stops before its next matching pass and
+    /// [`DiffHandle::wait`] then returns an error.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Number of matches found so far.
+    pub fn matches_found(&self) -> usize {
+        self.matches_found.load(Ordering::Relaxed)
+    }
+
+    /// Whether the diff has completed, successfully or not.
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+
+    /// Block until the diff completes and return its result.
+    pub fn wait(self) -> Result<DiffResult> {
+        self.thread
+            .join()
+            .map_err(|_| anyhow!("Diff thread panicked"))?
+    }
+}
+
+impl BinaryDiffEngine {
+    /// Start `perform_diff_mock` on a dedicated thread and return at once.
+    /// `on_match` is called from that thread for every match as soon as its
+    /// pass completes.
+    pub fn perform_diff_async(
+        &self,
+        binary_a_name: &str,
+        binary_b_name: &str,
+        mut on_match: impl FnMut(&FunctionMatch) + Send + 'static,
+    ) -> DiffHandle {
+        let engine = self.clone();
+        let binary_a_name = binary_a_name.to_string();
+        let binary_b_name = binary_b_name.to_string();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let matches_found = Arc::new(AtomicUsize::new(0));
+
+        let thread = {
+            let cancelled = Arc::clone(&cancelled);
+            let matches_found = Arc::clone(&matches_found);
+            thread::spawn(move || {
+                engine.perform_diff_cancellable(
+                    &MockProvider,
+                    &binary_a_name,
+                    &binary_b_name,
+                    |m| {
+                        matches_found.fetch_add(1, Ordering::Relaxed);
+                        on_match(m);
+                    },
+                    |_, _| {},
+                    Some(cancelled),
+                )
+            })
+        };
+
+        DiffHandle { cancelled, matches_found, thread }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[test]
+    fn async_diff_matches_the_blocking_diff() {
+        let engine = BinaryDiffEngine::new();
+        let blocking = engine.perform_diff_mock("binary_a", "binary_b").unwrap();
+        let handle = engine.perform_diff_async("binary_a", "binary_b", |_| {});
+        let result = handle.wait().unwrap();
+
+        let pairs = |result: &DiffResult| {
+            result.matched_functions.iter().map(|m| (m.function_a.address, m.function_b.address)).collect::<Vec<_>>()
+        };
+        assert_eq!(pairs(&result), pairs(&blocking));
+    }
+
+    #[test]
+    fn cancelled_diff_stops_with_an_error() {
+        // The first match blocks the diff thread until the test has cancelled
+        let (release, released) = mpsc::channel::<()>();
+        let handle = BinaryDiffEngine::new().perform_diff_async("binary_a", "binary_b", move |_| {
+            let _ = released.recv();
+        });
+        while handle.matches_found() == 0 && !handle.is_finished() {
+            thread::yield_now();
+        }
+        handle.cancel();
+        drop(release);
+        assert!(handle.wait().is_err());
+    }
+}
@@ -0,0 +1,159 @@
+use crate::types::*;
+use crate::matching::MatchingEngine;
+use anyhow::Result;
+
+const MNEMONICS: [&str; 12] = [
+    "push", "mov", "lea", "add", "sub", "cmp", "test", "jne", "je", "call", "xor", "ret",
+];
+
+/// Minimal deterministic PRNG (64-bit LCG) so synthetic inputs are
+/// reproducible across runs without pulling in a `rand` dependency.
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        self.0 >> 33
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        if n == 0 { 0 } else { self.next() % n }
+    }
+}
+
+/// Generate `count` synthetic functions averaging `avg_blocks` basic blocks
+/// each, with a fixed seed.
+pub fn generate_synthetic_functions(count: usize, avg_blocks: usize) -> Vec<FunctionInfo> {
+    generate_synthetic_functions_seeded(count, avg_blocks, 0)
+}
+
+/// Generate `count` synthetic functions averaging `avg_blocks` basic blocks
+/// each. The same seed always produces the same functions.
+pub fn generate_synthetic_functions_seeded(count: usize, avg_blocks: usize, seed: u64) -> Vec<FunctionInfo> {
+    let mut rng = Lcg(seed ^ 0x9e37_79b9_7f4a_7c15);
+    let mut functions = Vec::with_capacity(count);
+    let mut address = 0x10000u64;
+
+    for idx in 0..count {
+        let spread = avg_blocks as u64;
+        let bb_count = (avg_blocks as u64 / 2 + rng.below(spread + 1)).max(1) as usize;
+        let mut basic_blocks = Vec::with_capacity(bb_count);
+        let mut all_instructions = Vec::new();
+        let base_addr = address;
+
+        for _ in 0..bb_count {
+            let bb_addr = address;
+            let instr_count = 2 + rng.below(6) as usize;
+            let mut instructions = Vec::with_capacity(instr_count);
+
+            for _ in 0..instr_count {
+                let mnemonic = MNEMONICS[rng.below(MNEMONICS.len() as u64) as usize];
+                let length = 1 + rng.below(6) as usize;
+                let instruction = InstructionInfo {
+                    address,
+                    mnemonic: mnemonic.to_string(),
+                    operands: vec![format!("r{}", rng.below(16)), format!("0x{:x}", rng.below(256))],
+                    bytes: (0..length).map(|_| rng.below(256) as u8).collect(),
+                    length,
+                };
+                address += length as u64;
+                instructions.push(instruction.clone());
+                all_instructions.push(instruction);
+            }
+
+            basic_blocks.push(BasicBlockInfo {
+                address: bb_addr,
+                size: address - bb_addr,
+                mnemonic_hash: instructions.iter().map(|i| i.mnemonic.as_str()).collect::<Vec<_>>().join("_"),
+                instruction_count: instructions.len(),
+                instructions,
+                edges: Vec::new(),
+                is_landing_pad: false,
+            });
+        }
+
+        // Fallthrough chain plus an occasional forward branch
+        let block_addrs: Vec<u64> = basic_blocks.iter().map(|bb| bb.address).collect();
+        let mut edge_count = 0u32;
+        for (bb_idx, bb) in basic_blocks.iter_mut().enumerate() {
+            if bb_idx + 1 < block_addrs.len() {
+                bb.edges.push(block_addrs[bb_idx + 1]);
+                edge_count += 1;
+            }
+            if bb_idx + 2 < block_addrs.len() && rng.below(3) == 0 {
+                bb.edges.push(block_addrs[bb_idx + 2]);
+                edge_count += 1;
+            }
+        }
+
+        let call_count = all_instructions.iter().filter(|i| i.mnemonic == "call").count();
+        functions.push(FunctionInfo {
+            name: format!("sub_{:x}", base_addr),
+            address: base_addr,
+            size: address - base_addr,
+            cyclomatic_complexity: (edge_count + 2).saturating_sub(bb_count as u32),
+            call_graph_hash: format!("call_{}_{}", idx % 97, call_count),
+            cfg_hash: format!("cfg_{}_{}", bb_count, edge_count),
+            instruction_count: all_instructions.len(),
+            call_count,
+            basic_blocks,
+            instructions: all_instructions,
+            has_exception_handling: false,
+            pointer_size: 8,
+            endianness: Endianness::Little,
+        });
+
+        address += 0x10;
+    }
+
+    functions
+}
+
+/// Simulate a patch by changing one instruction in every `every_nth`
+/// function, so a diff exercises the non-exact passes on that fraction.
+pub fn perturb_functions(functions: &mut [FunctionInfo], every_nth: usize) {
+    if every_nth == 0 {
+        return;
+    }
+    for func in functions.iter_mut().step_by(every_nth) {
+        if let Some(instr) = func.instructions.first_mut() {
+            instr.mnemonic = "nop".to_string();
+        }
+        if let Some(instr) = func.basic_blocks.first_mut().and_then(|bb| bb.instructions.first_mut()) {
+            instr.mnemonic = "nop".to_string();
+        }
+        func.cfg_hash.push_str("_patched");
+    }
+}
+
+/// Run the full matching pipeline over the given inputs and return the
+/// number of matches, for use as a benchmark body.
+pub fn run_diff_bench(functions_a: &[FunctionInfo], functions_b: &[FunctionInfo]) -> Result<usize> {
+    let engine = MatchingEngine::new();
+    let matches = engine.match_functions(functions_a, functions_b)?;
+    Ok(matches.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn synthetic_functions_are_reproducible_and_diffable() {
+        let functions = generate_synthetic_functions(200, 8);
+        assert_eq!(functions.len(), 200);
+        let avg_blocks = functions.iter().map(|f| f.basic_blocks.len()).sum::<usize>() as f64 / 200.0;
+        assert!((avg_blocks - 8.0).abs() < 1.5);
+        assert_eq!(
+            generate_synthetic_functions(200, 8).iter().map(|f| f.combined_hash()).collect::<Vec<_>>(),
+            functions.iter().map(|f| f.combined_hash()).collect::<Vec<_>>()
+        );
+
+        let mut perturbed = functions.clone();
+        perturb_functions(&mut perturbed, 10);
+        let matched = run_diff_bench(&functions, &perturbed).unwrap();
+        assert!(matched > 150 && matched <= 200);
+    }
+}
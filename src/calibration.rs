@@ -0,0 +1,162 @@
+use crate::types::FunctionMatch;
+use anyhow::{Result, Context, bail};
+use serde::{Serialize, Deserialize};
+use std::path::Path;
+use std::fs;
+
+/// A labeled sample used to fit a calibration curve: the raw confidence the
+/// matcher produced and whether the match was known to be correct.
+#[derive(Debug, Clone, Copy)]
+pub struct LabeledConfidence {
+    pub raw_confidence: f64,
+    pub correct: bool,
+}
+
+/// Monotonic mapping from raw match confidence to calibrated probability,
+/// fitted by isotonic regression (pool-adjacent-violators) over labeled
+/// matches. Between breakpoints the curve is linearly interpolated; outside
+/// the fitted range it is clamped to the nearest breakpoint.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfidenceCalibrator {
+    /// Raw confidence values, strictly increasing.
+    pub breakpoints: Vec<f64>,
+    /// Calibrated probability at each breakpoint, non-decreasing.
+    pub probabilities: Vec<f64>,
+}
+
+impl ConfidenceCalibrator {
+    /// Fit a calibration curve from labeled samples.
+    pub fn fit(samples: &[LabeledConfidence]) -> Result<Self> {
+        if samples.is_empty() {
+            bail!("Cannot fit confidence calibration without labeled samples");
+        }
+
+        let mut sorted: Vec<(f64, f64)> = samples
+            .iter()
+            .filter(|s| s.raw_confidence.is_finite())
+            .map(|s| (s.raw_confidence, if s.correct { 1.0 } else { 0.0 }))
+            .collect();
+        if sorted.is_empty() {
+            bail!("Cannot fit confidence calibration: no finite confidence values");
+        }
+        sorted.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        // Each block: (sum of raw x, sum of labels, sample count)
+        let mut blocks: Vec<(f64, f64, usize)> = Vec::new();
+        for (x, y) in sorted {
+            blocks.push((x, y, 1));
+            // Pool adjacent violators until block means are non-decreasing
+            while blocks.len() > 1 {
+                let n = blocks.len();
+                let (_, y_prev, c_prev) = blocks[n - 2];
+                let (_, y_last, c_last) = blocks[n - 1];
+                if y_prev / c_prev as f64 <= y_last / c_last as f64 {
+                    break;
+                }
+                let last = blocks.pop().unwrap();
+                let prev = blocks.last_mut().unwrap();
+                prev.0 += last.0;
+                prev.1 += last.1;
+                prev.2 += last.2;
+            }
+        }
+
+        let mut breakpoints: Vec<f64> = Vec::with_capacity(blocks.len());
+        let mut probabilities: Vec<f64> = Vec::with_capacity(blocks.len());
+        for (x_sum, y_sum, count) in blocks {
+            let x = x_sum / count as f64;
+            let y = y_sum / count as f64;
+            // Blocks with the same mean raw value collapse into one breakpoint
+            if breakpoints.last().is_some_and(|&last| x <= last) {
+                let last_y = probabilities.last_mut().unwrap();
+                *last_y = last_y.max(y);
+                continue;
+            }
+            breakpoints.push(x);
+            probabilities.push(y);
+        }
+
+        Ok(Self { breakpoints, probabilities })
+    }
+
+    /// Map a raw confidence to a calibrated probability.
+    pub fn calibrate(&self, raw_confidence: f64) -> f64 {
+        let (xs, ys) = (&self.breakpoints, &self.probabilities);
+        if xs.is_empty() || raw_confidence.is_nan() {
+            return raw_confidence;
+        }
+        if raw_confidence <= xs[0] {
+            return ys[0];
+        }
+        let last = xs.len() - 1;
+        if raw_confidence >= xs[last] {
+            return ys[last];
+        }
+
+        let upper = xs.partition_point(|&x| x < raw_confidence);
+        let (x0, x1) = (xs[upper - 1], xs[upper]);
+        let (y0, y1) = (ys[upper - 1], ys[upper]);
+        let t = (raw_confidence - x0) / (x1 - x0);
+        (y0 + t * (y1 - y0)).clamp(0.0, 1.0)
+    }
+
+    /// Replace each match's confidence with its calibrated probability.
+    /// Exact and manual matches keep their confidence of 1.0.
+    pub fn apply(&self, matches: &mut [FunctionMatch]) {
+        for m in matches.iter_mut().filter(|m| !m.match_type.has_fixed_confidence()) {
+            m.confidence = self.calibrate(m.confidence);
+        }
+    }
+
+    /// Persist the calibration curve as JSON
+    pub fn save(&self, output_path: &Path) -> Result<()> {
+        let json_data = serde_json::to_string_pretty(self)
+            .context("Failed to serialize confidence calibration")?;
+
+        fs::write(output_path, json_data)
+            .context("Failed to write calibration file")?;
+
+        Ok(())
+    }
+
+    /// Load a previously saved calibration curve
+    pub fn load(input_path: &Path) -> Result<Self> {
+        let json_data = fs::read_to_string(input_path)
+            .context("Failed to read calibration file")?;
+
+        let calibrator: Self = serde_json::from_str(&json_data)
+            .context("Failed to deserialize confidence calibration")?;
+
+        if calibrator.breakpoints.len() != calibrator.probabilities.len() {
+            bail!("Calibration file has mismatched breakpoint and probability counts");
+        }
+
+        Ok(calibrator)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fitted_curve_is_monotonic_and_survives_save_and_load() {
+        // Higher raw confidence is correct more often, with noise
+        let samples: Vec<LabeledConfidence> = (0..100)
+            .map(|i| LabeledConfidence { raw_confidence: i as f64 / 100.0, correct: (i * 7919) % 100 < i })
+            .collect();
+        let calibrator = ConfidenceCalibrator::fit(&samples).unwrap();
+
+        let curve: Vec<f64> = (0..=100).map(|i| calibrator.calibrate(i as f64 / 100.0)).collect();
+        assert!(curve.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!(calibrator.calibrate(0.05) < 0.3);
+        assert!(calibrator.calibrate(0.95) > 0.7);
+
+        let path = std::env::temp_dir().join("rust_diff_calibration_test.json");
+        calibrator.save(&path).unwrap();
+        let loaded = ConfidenceCalibrator::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.breakpoints.len(), calibrator.breakpoints.len());
+        assert!((0..=100).all(|i| (loaded.calibrate(i as f64 / 100.0) - curve[i]).abs() < 1e-9));
+    }
+}
@@ -0,0 +1,525 @@
+use serde::{Serialize, Deserialize};
+use anyhow::{Result, bail};
+use crate::types::{FunctionInfo, MatchDetails, MatchPass, MatchType};
+
+/// Weight of each per-metric score in the combined function similarity.
+/// The defaults sum to 1.0.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SimilarityWeights {
+    pub cfg: f64,
+    pub call: f64,
+    pub bb: f64,
+    pub instruction: f64,
+    pub name: f64,
+    pub edge: f64,
+    /// Register usage (weighted Jaccard over operand registers).
+    pub register: f64,
+    /// Exception-handling structure (EH flag and landing-pad count).
+    pub exception: f64,
+    /// CFG depth and width.
+    pub cfg_shape: f64,
+    /// Distribution of basic-block byte sizes.
+    pub block_size: f64,
+    /// Longest common subsequence of the mnemonic sequences. Off by default
+    /// since it is quadratic in function length; at 0.0 it isn't computed.
+    pub lcs: f64,
+}
+
+impl Default for SimilarityWeights {
+    fn default() -> Self {
+        Self {
+            cfg: 0.20,
+            call: 0.20,
+            bb: 0.10,
+            instruction: 0.10,
+            name: 0.10,
+            edge: 0.10,
+            register: 0.05,
+            exception: 0.05,
+            cfg_shape: 0.05,
+            block_size: 0.05,
+            lcs: 0.0,
+        }
+    }
+}
+
+impl SimilarityWeights {
+    /// Weights for the six core metrics, which must be finite, non-negative
+    /// and sum to 1.0 (within rounding) so the combined score stays a 0.0 to
+    /// 1.0 similarity. The auxiliary terms (`register`, ...) start at 0.0;
+    /// set them with struct update syntax and check the result with
+    /// `validate`.
+    pub fn new(cfg: f64, call: f64, bb: f64, instruction: f64, name: f64, edge: f64) -> Result<Self> {
+        let weights = Self { cfg, call, bb, instruction, name, edge, register: 0.0, exception: 0.0, cfg_shape: 0.0, block_size: 0.0, lcs: 0.0 };
+        weights.validate()?;
+        Ok(weights)
+    }
+
+    /// Check that every weight is finite and non-negative and that they sum
+    /// to 1.0 (within rounding).
+    pub fn validate(&self) -> Result<()> {
+        let values = self.values();
+        if values.iter().any(|w| !w.is_finite() || *w < 0.0) {
+            bail!("Similarity weights must be finite and non-negative: {:?}", self);
+        }
+        let sum: f64 = values.iter().sum();
+        if (sum - 1.0).abs() > 1e-6 {
+            bail!("Similarity weights must sum to 1.0, got {}", sum);
+        }
+        Ok(())
+    }
+
+    fn values(&self) -> Vec<f64> {
+        vec![self.cfg, self.call, self.bb, self.instruction, self.name, self.edge, self.register, self.exception, self.cfg_shape, self.block_size, self.lcs]
+    }
+
+    /// Each metric's weighted contribution as `(metric name, weight * score)`.
+    pub fn contributions(&self, details: &MatchDetails) -> Vec<(&'static str, f64)> {
+        vec![
+            ("cfg", details.cfg_similarity * self.cfg),
+            ("calls", details.call_similarity * self.call),
+            ("basic_blocks", details.bb_similarity * self.bb),
+            ("instructions", details.instruction_similarity * self.instruction),
+            ("name", details.name_similarity * self.name),
+            ("edges", details.edge_similarity * self.edge),
+            ("registers", details.register_similarity * self.register),
+            ("exception_handling", details.exception_similarity * self.exception),
+            ("cfg_shape", details.cfg_shape_similarity * self.cfg_shape),
+            ("block_sizes", details.block_size_similarity * self.block_size),
+            ("mnemonic_lcs", details.lcs_similarity * self.lcs),
+        ]
+    }
+}
+
+/// Limits on how far two functions' CFG shape may differ for them to still be
+/// considered by the structural matching pass. Block and complexity limits are
+/// the larger of an absolute allowance and a fraction of the bigger function,
+/// so large functions tolerate proportionally larger changes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StructuralTolerance {
+    pub max_block_diff: usize,
+    pub block_diff_ratio: f64,
+    pub max_complexity_diff: u32,
+    pub complexity_diff_ratio: f64,
+    /// Maximum relative byte-size difference, as a fraction of the larger size.
+    pub max_size_diff_ratio: f64,
+    /// Absolute allowances for CFG depth/width differences; like blocks, they
+    /// grow by `block_diff_ratio` of the larger value.
+    pub max_depth_diff: usize,
+    pub max_width_diff: usize,
+}
+
+impl Default for StructuralTolerance {
+    fn default() -> Self {
+        Self {
+            max_block_diff: 2,
+            block_diff_ratio: 0.1,
+            max_complexity_diff: 2,
+            complexity_diff_ratio: 0.1,
+            max_size_diff_ratio: 0.3,
+            max_depth_diff: 2,
+            max_width_diff: 2,
+        }
+    }
+}
+
+impl StructuralTolerance {
+    /// Allowed basic-block count difference for functions of the given size.
+    pub fn allowed_block_diff(&self, larger_block_count: usize) -> usize {
+        let relative = (larger_block_count as f64 * self.block_diff_ratio).ceil() as usize;
+        self.max_block_diff.max(relative)
+    }
+
+    /// Allowed CFG depth difference for functions of the given depth.
+    pub fn allowed_depth_diff(&self, larger_depth: usize) -> usize {
+        let relative = (larger_depth as f64 * self.block_diff_ratio).ceil() as usize;
+        self.max_depth_diff.max(relative)
+    }
+
+    /// Allowed CFG width difference for functions of the given width.
+    pub fn allowed_width_diff(&self, larger_width: usize) -> usize {
+        let relative = (larger_width as f64 * self.block_diff_ratio).ceil() as usize;
+        self.max_width_diff.max(relative)
+    }
+
+    /// Allowed cyclomatic complexity difference for functions of the given complexity.
+    pub fn allowed_complexity_diff(&self, larger_complexity: u32) -> u32 {
+        let relative = (larger_complexity as f64 * self.complexity_diff_ratio).ceil() as u32;
+        self.max_complexity_diff.max(relative)
+    }
+}
+
+/// Score cutoffs used to classify a single function pair queried outside the
+/// matching pipeline, where no pass determines the match type.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MatchTypeThresholds {
+    /// Pairs scoring above this similarity are `Exact`.
+    pub exact_similarity: f64,
+    /// Otherwise, pairs above this confidence are `Structural`; the rest are
+    /// `Heuristic`.
+    pub structural_confidence: f64,
+}
+
+impl Default for MatchTypeThresholds {
+    fn default() -> Self {
+        Self {
+            exact_similarity: 0.9,
+            structural_confidence: 0.8,
+        }
+    }
+}
+
+impl MatchTypeThresholds {
+    /// Match type for a pair with the given scores.
+    pub fn classify(&self, similarity: f64, confidence: f64) -> MatchType {
+        if similarity > self.exact_similarity {
+            MatchType::Exact
+        } else if confidence > self.structural_confidence {
+            MatchType::Structural
+        } else {
+            MatchType::Heuristic
+        }
+    }
+}
+
+/// How single-instruction jump thunks are treated before matching.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum ThunkHandling {
+    /// Match thunks like any other function.
+    #[default]
+    Keep,
+    /// Exclude every thunk from matching.
+    Exclude,
+    /// Exclude thunks whose jump target is another function in the same
+    /// binary, so the underlying functions match directly. Thunks to
+    /// unresolvable targets (e.g. imports) are kept.
+    Resolve,
+}
+
+/// Named starting points for common diffing scenarios, applied with
+/// [`DiffConfig::preset`]. Each preset only changes the fields it documents;
+/// everything else keeps its default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Preset {
+    /// Two builds of the same code with the same toolchain, e.g. before and
+    /// after a security patch. Verifies exact matches byte-for-byte (masking
+    /// relocations) and collects near misses to surface the changed
+    /// functions. Shape tolerances stay at their strict defaults.
+    PatchDiff,
+    /// The same source built by different compilers or optimization levels.
+    /// Drops the prologue/epilogue pass, whose signatures are
+    /// compiler-specific, resolves jump thunks, and loosens the structural
+    /// tolerance, fingerprint distance and bucket search. Weighs the CFG and
+    /// calls above blocks and instructions, and drops the register weight.
+    CrossCompiler,
+    /// Two binaries without symbols. Disables the name pass, whose
+    /// auto-generated `sub_*` names only encode addresses, and zeroes the
+    /// name weight.
+    StrippedVsStripped,
+    /// Malware samples and variants. Disables the name pass, excludes
+    /// thunks and functions under 16 bytes, masks relocations and lets the
+    /// reference pass use strings/constants shared by up to 5 functions.
+    /// Zeroes the name weight, as `StrippedVsStripped` does.
+    Malware,
+}
+
+impl Preset {
+    /// Similarity weights suited to the scenario, set as
+    /// `DiffConfig::weights` by `DiffConfig::preset` and usable with
+    /// `DiffResult::rescore`.
+    pub fn similarity_weights(self) -> SimilarityWeights {
+        match self {
+            Preset::StrippedVsStripped | Preset::Malware => SimilarityWeights {
+                cfg: 0.25,
+                call: 0.20,
+                bb: 0.15,
+                instruction: 0.10,
+                name: 0.0,
+                edge: 0.10,
+                register: 0.05,
+                exception: 0.05,
+                cfg_shape: 0.05,
+                block_size: 0.05,
+                lcs: 0.0,
+            },
+            Preset::CrossCompiler => SimilarityWeights {
+                cfg: 0.25,
+                call: 0.25,
+                bb: 0.05,
+                instruction: 0.05,
+                name: 0.15,
+                edge: 0.10,
+                // Register allocation differs between compilers
+                register: 0.0,
+                exception: 0.05,
+                cfg_shape: 0.05,
+                block_size: 0.05,
+                lcs: 0.0,
+            },
+            Preset::PatchDiff => SimilarityWeights::default(),
+        }
+    }
+}
+
+/// Tunable behaviour of a diff run beyond the similarity/confidence thresholds.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DiffConfig {
+    /// Soft limit, in bytes, on the estimated working set of a diff. When the
+    /// extracted functions are expected to exceed it, instruction bytes are
+    /// dropped before matching so matches don't retain them; nothing else is
+    /// reduced, so this lowers but doesn't cap memory use. `None` disables
+    /// the check.
+    pub memory_budget_bytes: Option<usize>,
+    /// Confirm every `Exact` match by comparing the functions' concatenated
+    /// instruction bytes, downgrading mismatches to `Structural`.
+    pub verify_exact_bytes: bool,
+    /// When verifying exact matches, ignore the trailing 4 bytes of
+    /// instructions at least 5 bytes long, where relocated displacements and
+    /// absolute addresses usually live.
+    pub mask_relocations: bool,
+    /// Shape tolerance for the structural matching pass.
+    pub structural: StructuralTolerance,
+    /// Only accept a name match when the pair's structural similarity also
+    /// reaches `structural_corroboration_threshold`, so identical names on
+    /// unrelated functions don't produce matches.
+    pub require_structural_corroboration: bool,
+    pub structural_corroboration_threshold: f64,
+    /// Treatment of jump thunks.
+    pub thunk_handling: ThunkHandling,
+    /// Unconditional jump mnemonics that make a single-instruction function a
+    /// thunk. Adjust for the target architecture.
+    pub thunk_jump_mnemonics: Vec<String>,
+    /// After matching, find the best sub-threshold candidate for every
+    /// unmatched function and carry it into the results and reports.
+    pub collect_near_misses: bool,
+    /// After matching, look for unmatched functions split into, or merged
+    /// from, two unmatched functions of the other binary. Compares every
+    /// unmatched function of A with every one of B.
+    pub detect_split_merges: bool,
+    /// After each matching pass, adjust the new matches' confidences by
+    /// their lead over the runner-up candidate; see
+    /// `MatchingEngine::recalibrate_confidence`. Off by default: each match
+    /// is scored again against every B-function with its A function's basic
+    /// block count or sharing a rare reference with it, which in binaries
+    /// with many small single-block functions approaches all of B.
+    pub recalibrate_confidence: bool,
+    /// Functions smaller than this many bytes are left out of matching and
+    /// reported as skipped. 0 disables the filter.
+    pub min_function_size: u64,
+    /// Functions with fewer instructions than this are left out of matching
+    /// and reported as skipped. 0 disables the filter.
+    pub min_instruction_count: usize,
+    /// Functions with fewer basic blocks than this are left out of matching
+    /// and reported as skipped. 0 disables the filter.
+    pub min_basic_blocks: usize,
+    /// How far (in blocks and in edges) apart the (block count, edge count)
+    /// buckets searched by the bucketed pass may be.
+    pub bucket_tolerance: usize,
+    /// Fail the diff with an error listing every warning instead of
+    /// returning a result with warnings recorded. Meant for CI gates.
+    pub strict: bool,
+    /// Number of leading and trailing instructions forming a function's
+    /// prologue/epilogue signature. Functions with fewer than twice this many
+    /// instructions have no signature.
+    pub prologue_epilogue_length: usize,
+    /// Run all of a diff's parallel work (matching passes, near misses,
+    /// split/merge detection, lookups) on a dedicated thread pool of this
+    /// size instead of rayon's global pool. The pool is built once per
+    /// engine. `Some(1)` gives a fully serial run.
+    pub num_threads: Option<usize>,
+    /// Matching passes to run, in order. Passes left out are disabled; each
+    /// pass may appear at most once.
+    pub pass_order: Vec<MatchPass>,
+    /// The structural pass also considers pairs whose structural
+    /// fingerprints are at most this far apart (0.0 to 1.0).
+    pub fingerprint_max_distance: f64,
+    /// Rebuild the CFG of imported functions that have branch instructions
+    /// but no block edges, inferring successors from fallthrough and branch
+    /// targets. For extractors that don't export edges.
+    pub reconstruct_missing_edges: bool,
+    /// Drop basic blocks unreachable from the entry block before matching,
+    /// so dead code left in by the extractor doesn't skew structural
+    /// metrics. Their presence is reported as a warning either way.
+    pub exclude_unreachable_blocks: bool,
+    /// A match is reported as relocated when its position shift differs from
+    /// the median shift of its neighbors by more than this many positions.
+    pub relocation_threshold: usize,
+    /// The reference pass only pairs functions through string/constant
+    /// references used by at most this many functions of binary B.
+    pub max_reference_frequency: usize,
+    /// Match type cutoffs for single-pair queries.
+    pub match_type_thresholds: MatchTypeThresholds,
+    /// Weights of the per-metric scores every matching pass combines into a
+    /// pair's similarity.
+    pub weights: SimilarityWeights,
+    /// `(address in A, address in B)` pairs pinned by an analyst. They are
+    /// matched as `Manual` with confidence 1.0 before any automatic pass,
+    /// which then leaves both functions alone.
+    pub manual_matches: Vec<(u64, u64)>,
+}
+
+impl Default for DiffConfig {
+    fn default() -> Self {
+        Self {
+            memory_budget_bytes: None,
+            verify_exact_bytes: false,
+            mask_relocations: false,
+            structural: StructuralTolerance::default(),
+            require_structural_corroboration: false,
+            structural_corroboration_threshold: 0.5,
+            thunk_handling: ThunkHandling::Keep,
+            // x86, ARM/AArch64, MIPS
+            thunk_jump_mnemonics: ["jmp", "b", "bx", "br", "j", "jr"]
+                .iter()
+                .map(|m| m.to_string())
+                .collect(),
+            collect_near_misses: false,
+            detect_split_merges: false,
+            recalibrate_confidence: false,
+            min_function_size: 0,
+            min_instruction_count: 0,
+            min_basic_blocks: 0,
+            bucket_tolerance: 1,
+            strict: false,
+            prologue_epilogue_length: 3,
+            num_threads: None,
+            pass_order: MatchPass::DEFAULT_ORDER.to_vec(),
+            fingerprint_max_distance: 0.1,
+            reconstruct_missing_edges: false,
+            exclude_unreachable_blocks: false,
+            relocation_threshold: 2,
+            max_reference_frequency: 3,
+            match_type_thresholds: MatchTypeThresholds::default(),
+            weights: SimilarityWeights::default(),
+            manual_matches: Vec::new(),
+        }
+    }
+}
+
+impl DiffConfig {
+    /// Configuration tuned for a common scenario; see [`Preset`] for what
+    /// each one changes.
+    pub fn preset(preset: Preset) -> Self {
+        let defaults = Self { weights: preset.similarity_weights(), ..Self::default() };
+        let without_pass = |excluded: MatchPass| -> Vec<MatchPass> {
+            defaults.pass_order.iter().copied().filter(|&p| p != excluded).collect()
+        };
+
+        match preset {
+            Preset::PatchDiff => Self {
+                verify_exact_bytes: true,
+                mask_relocations: true,
+                collect_near_misses: true,
+                ..defaults
+            },
+            Preset::CrossCompiler => Self {
+                pass_order: without_pass(MatchPass::PrologueEpilogue),
+                thunk_handling: ThunkHandling::Resolve,
+                structural: StructuralTolerance {
+                    max_block_diff: 4,
+                    block_diff_ratio: 0.25,
+                    max_complexity_diff: 4,
+                    complexity_diff_ratio: 0.25,
+                    max_size_diff_ratio: 0.5,
+                    max_depth_diff: 4,
+                    max_width_diff: 4,
+                },
+                fingerprint_max_distance: 0.2,
+                bucket_tolerance: 2,
+                collect_near_misses: true,
+                ..defaults
+            },
+            Preset::StrippedVsStripped => Self {
+                pass_order: without_pass(MatchPass::Name),
+                ..defaults
+            },
+            Preset::Malware => Self {
+                pass_order: without_pass(MatchPass::Name),
+                thunk_handling: ThunkHandling::Exclude,
+                min_function_size: 16,
+                mask_relocations: true,
+                max_reference_frequency: 5,
+                fingerprint_max_distance: 0.15,
+                ..defaults
+            },
+        }
+    }
+
+    /// Check the configuration for values the matching engine can't run with.
+    pub fn validate(&self) -> Result<()> {
+        for (i, pass) in self.pass_order.iter().enumerate() {
+            if self.pass_order[..i].contains(pass) {
+                bail!("Matching pass {:?} appears more than once in pass_order", pass);
+            }
+        }
+        if self.pass_order.contains(&MatchPass::Manual) {
+            bail!("The Manual pass always runs first and cannot be listed in pass_order");
+        }
+        if self.num_threads == Some(0) {
+            bail!("num_threads must be at least 1");
+        }
+        self.weights.validate()?;
+
+        Ok(())
+    }
+
+    /// Whether `func` falls below the configured minimum size filters.
+    pub fn is_below_minimum_size(&self, func: &FunctionInfo) -> bool {
+        func.size < self.min_function_size
+            || func.instructions.len() < self.min_instruction_count
+            || func.basic_blocks.len() < self.min_basic_blocks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_are_valid_and_change_only_their_fields() {
+        for preset in [Preset::PatchDiff, Preset::CrossCompiler, Preset::StrippedVsStripped, Preset::Malware] {
+            let config = DiffConfig::preset(preset);
+            config.validate().unwrap();
+            preset.similarity_weights().validate().unwrap();
+
+            let mut engine = crate::BinaryDiffEngine::new();
+            engine.config = config;
+            engine.perform_diff_mock("binary_a", "binary_b").unwrap();
+        }
+
+        let stripped = DiffConfig::preset(Preset::StrippedVsStripped);
+        assert!(!stripped.pass_order.contains(&MatchPass::Name));
+        assert_eq!(stripped.pass_order.len(), MatchPass::DEFAULT_ORDER.len() - 1);
+        assert_eq!(stripped.weights, Preset::StrippedVsStripped.similarity_weights());
+        assert_eq!(stripped.weights.name, 0.0);
+
+        // Only the name differs, which stripped matching ignores
+        let function = crate::mock::generate_mock_functions("binary").unwrap().remove(1);
+        let mut renamed = function.clone();
+        renamed.name = "sub_401000".to_string();
+        let default_engine = crate::matching::MatchingEngine::new();
+        assert!(
+            default_engine.match_single_function(&function, &renamed).similarity
+                < default_engine.match_single_function(&function, &function).similarity
+        );
+        let stripped_engine = crate::matching::MatchingEngine::new().with_config(stripped.clone());
+        assert_eq!(
+            stripped_engine.match_single_function(&function, &renamed).similarity,
+            stripped_engine.match_single_function(&function, &function).similarity
+        );
+        let pipeline = stripped_engine.match_functions(std::slice::from_ref(&function), std::slice::from_ref(&renamed)).unwrap();
+        assert_eq!(pipeline[0].similarity, stripped_engine.match_single_function(&function, &function).similarity);
+
+        let mut unweighted = DiffConfig::default();
+        unweighted.weights.name = 0.5;
+        assert!(unweighted.validate().is_err());
+
+        let patch = DiffConfig::preset(Preset::PatchDiff);
+        assert!(patch.verify_exact_bytes);
+        assert_eq!(patch.pass_order, DiffConfig::default().pass_order);
+    }
+}
@@ -1,8 +1,10 @@
-use crate::{FunctionInfo, FunctionMatch, DiffResult};
-use anyhow::{Result, Context};
+use crate::{Coverage, FunctionInfo, FunctionMatch, DiffResult, MatchType, NearMiss, Provenance};
+use anyhow::{Result, Context, bail};
 use serde::{Serialize, Deserialize};
 use std::path::Path;
-use std::fs;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use sha2::{Digest, Sha256};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct DiffDatabase {
@@ -12,6 +14,12 @@ pub struct DiffDatabase {
     pub functions_b: Vec<FunctionInfo>,
     pub matches: Vec<FunctionMatch>,
     pub metadata: DatabaseMetadata,
+    /// Near-miss candidates for unmatched functions; empty unless collected.
+    #[serde(default)]
+    pub near_misses: Vec<NearMiss>,
+    /// Version, configuration and input hashes of the run that produced it.
+    #[serde(default)]
+    pub provenance: Provenance,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -24,45 +32,230 @@ pub struct DatabaseMetadata {
     pub total_functions_b: usize,
     pub total_matches: usize,
     pub analysis_time_seconds: f64,
+    /// Functions left out of matching by the size filters; they are
+    /// included in the totals but are not unmatched.
+    #[serde(default)]
+    pub skipped_functions_a: usize,
+    #[serde(default)]
+    pub skipped_functions_b: usize,
+}
+
+/// One entry of a symbol-propagation export: rename `address_b` to `name`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SymbolMapping {
+    pub address_b: u64,
+    pub name: String,
+    pub confidence: f64,
+}
+
+/// One line of a JSON Lines export: a single match, self-contained so each
+/// line can be parsed on its own.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MatchRecord {
+    pub binary_a: String,
+    pub binary_b: String,
+    pub function_a: String,
+    pub address_a: u64,
+    pub function_b: String,
+    pub address_b: u64,
+    pub similarity: f64,
+    pub confidence: f64,
+    pub match_type: MatchType,
 }
 
 pub struct DatabaseManager;
 
+impl DiffDatabase {
+    /// Check that serializing, deserializing and serializing again yields
+    /// byte-identical JSON, i.e. that no field is lost or altered by a
+    /// save/load cycle. Reports the first differing line otherwise.
+    pub fn assert_round_trip(&self) -> Result<()> {
+        let first = serde_json::to_string_pretty(self)
+            .context("Failed to serialize diff database")?;
+        let reloaded: DiffDatabase = serde_json::from_str(&first)
+            .context("Failed to deserialize diff database")?;
+        let second = serde_json::to_string_pretty(&reloaded)
+            .context("Failed to re-serialize diff database")?;
+
+        if let Some((line, (before, after))) = first
+            .lines()
+            .zip(second.lines())
+            .enumerate()
+            .find(|(_, (before, after))| before != after)
+        {
+            bail!("Diff database does not round-trip: line {} changed from '{}' to '{}'", line + 1, before, after);
+        }
+        if first.len() != second.len() {
+            bail!("Diff database does not round-trip: {} bytes became {}", first.len(), second.len());
+        }
+
+        Ok(())
+    }
+}
+
+/// Escape a string for safe interpolation into HTML text or attribute values.
+/// Function names and paths come from untrusted binaries and must never be
+/// emitted as raw markup.
+fn html_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for ch in s.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+/// First line of every SQL script export; used to check that an append
+/// targets a file with the same schema.
+const SQL_SCHEMA_HEADER: &str = "-- rust_diff SQL export, schema v2";
+
+fn sql_quote(s: &str) -> String {
+    s.replace('\'', "''")
+}
+
+/// Schema for the SQL script export. Every statement is idempotent so the
+/// script can be replayed against a database that already has the tables.
+fn push_sql_schema(sql_content: &mut String) {
+    sql_content.push_str(SQL_SCHEMA_HEADER);
+    sql_content.push('\n');
+    sql_content.push_str("CREATE TABLE IF NOT EXISTS diff_runs (\n");
+    sql_content.push_str("    run_id TEXT PRIMARY KEY,\n");
+    sql_content.push_str("    binary_a_path TEXT,\n");
+    sql_content.push_str("    binary_b_path TEXT,\n");
+    sql_content.push_str("    plugin_version TEXT,\n");
+    sql_content.push_str("    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP\n");
+    sql_content.push_str(");\n\n");
+
+    sql_content.push_str("CREATE TABLE IF NOT EXISTS function_matches (\n");
+    sql_content.push_str("    id INTEGER PRIMARY KEY AUTOINCREMENT,\n");
+    sql_content.push_str("    run_id TEXT NOT NULL,\n");
+    sql_content.push_str("    function_a_name TEXT,\n");
+    sql_content.push_str("    function_a_address TEXT,\n");
+    sql_content.push_str("    function_b_name TEXT,\n");
+    sql_content.push_str("    function_b_address TEXT,\n");
+    sql_content.push_str("    similarity REAL,\n");
+    sql_content.push_str("    confidence REAL,\n");
+    sql_content.push_str("    match_type TEXT,\n");
+    sql_content.push_str("    size_a INTEGER,\n");
+    sql_content.push_str("    size_b INTEGER,\n");
+    sql_content.push_str("    bb_count_a INTEGER,\n");
+    sql_content.push_str("    bb_count_b INTEGER,\n");
+    sql_content.push_str("    instr_count_a INTEGER,\n");
+    sql_content.push_str("    instr_count_b INTEGER,\n");
+    sql_content.push_str("    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP\n");
+    sql_content.push_str(");\n\n");
+
+    sql_content.push_str("CREATE INDEX IF NOT EXISTS idx_function_matches_run ON function_matches (run_id);\n");
+    sql_content.push_str("CREATE INDEX IF NOT EXISTS idx_function_matches_address_a ON function_matches (function_a_address);\n");
+    sql_content.push_str("CREATE INDEX IF NOT EXISTS idx_function_matches_address_b ON function_matches (function_b_address);\n\n");
+}
+
+/// Statements recording one run. Existing rows for the same `run_id` are
+/// deleted first so replaying an interrupted append is safe.
+fn push_sql_run(sql_content: &mut String, database: &DiffDatabase, run_id: &str) {
+    let run_id = sql_quote(run_id);
+
+    sql_content.push_str("BEGIN TRANSACTION;\n");
+    sql_content.push_str(&format!("DELETE FROM function_matches WHERE run_id = '{}';\n", run_id));
+    sql_content.push_str(&format!(
+        "INSERT OR REPLACE INTO diff_runs (run_id, binary_a_path, binary_b_path, plugin_version) VALUES ('{}', '{}', '{}', '{}');\n",
+        run_id,
+        sql_quote(&database.binary_a_path),
+        sql_quote(&database.binary_b_path),
+        sql_quote(&database.metadata.plugin_version)
+    ));
+
+    for match_result in &database.matches {
+        sql_content.push_str(&format!(
+            "INSERT INTO function_matches (run_id, function_a_name, function_a_address, function_b_name, function_b_address, similarity, confidence, match_type, size_a, size_b, bb_count_a, bb_count_b, instr_count_a, instr_count_b) VALUES ('{}', '{}', '0x{:016x}', '{}', '0x{:016x}', {:.4}, {:.4}, '{:?}', {}, {}, {}, {}, {}, {});\n",
+            run_id,
+            sql_quote(&match_result.function_a.name),
+            match_result.function_a.address,
+            sql_quote(&match_result.function_b.name),
+            match_result.function_b.address,
+            match_result.similarity,
+            match_result.confidence,
+            match_result.match_type,
+            match_result.function_a.size,
+            match_result.function_b.size,
+            match_result.function_a.basic_blocks.len(),
+            match_result.function_b.basic_blocks.len(),
+            match_result.function_a.instructions.len(),
+            match_result.function_b.instructions.len()
+        ));
+    }
+    sql_content.push_str("COMMIT;\n\n");
+}
+
+/// Hex SHA-256 digest of the file at `path`, streamed rather than read
+/// into memory at once.
+fn sha256_file(path: &str) -> Result<String> {
+    let mut file = File::open(path)
+        .with_context(|| format!("Failed to open binary {} for hashing", path))?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)
+        .with_context(|| format!("Failed to read binary {} for hashing", path))?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
 impl DatabaseManager {
-    /// Save diff results to a JSON file
+    /// Save diff results to a JSON file. Both binary paths must be readable:
+    /// their SHA-256 digests are recorded in the metadata.
     pub fn save_diff_results(
         diff_result: &DiffResult,
         binary_a_path: &str,
         binary_b_path: &str,
         output_path: &Path,
     ) -> Result<()> {
+        let functions_a: Vec<FunctionInfo> = diff_result.matched_functions.iter()
+            .map(|m| m.function_a.clone())
+            .chain(diff_result.unmatched_functions_a.iter().cloned())
+            .chain(diff_result.skipped_functions_a.iter().cloned())
+            .collect();
+        let functions_b: Vec<FunctionInfo> = diff_result.matched_functions.iter()
+            .map(|m| m.function_b.clone())
+            .chain(diff_result.unmatched_functions_b.iter().cloned())
+            .chain(diff_result.skipped_functions_b.iter().cloned())
+            .collect();
+
         let metadata = DatabaseMetadata {
             created_at: chrono::Utc::now().to_rfc3339(),
             plugin_version: env!("CARGO_PKG_VERSION").to_string(),
-            binary_a_hash: "".to_string(), // TODO: Calculate actual hash
-            binary_b_hash: "".to_string(), // TODO: Calculate actual hash
-            total_functions_a: diff_result.matched_functions.len() + diff_result.unmatched_functions_a.len(),
-            total_functions_b: diff_result.matched_functions.len() + diff_result.unmatched_functions_b.len(),
+            binary_a_hash: sha256_file(binary_a_path)?,
+            binary_b_hash: sha256_file(binary_b_path)?,
+            total_functions_a: functions_a.len(),
+            total_functions_b: functions_b.len(),
             total_matches: diff_result.matched_functions.len(),
-            analysis_time_seconds: 0.0, // TODO: Track actual time
+            analysis_time_seconds: diff_result.analysis_time,
+            skipped_functions_a: diff_result.skipped_functions_a.len(),
+            skipped_functions_b: diff_result.skipped_functions_b.len(),
         };
 
         let database = DiffDatabase {
             binary_a_path: binary_a_path.to_string(),
             binary_b_path: binary_b_path.to_string(),
-            functions_a: diff_result.matched_functions.iter()
-                .map(|m| m.function_a.clone())
-                .chain(diff_result.unmatched_functions_a.iter().cloned())
-                .collect(),
-            functions_b: diff_result.matched_functions.iter()
-                .map(|m| m.function_b.clone())
-                .chain(diff_result.unmatched_functions_b.iter().cloned())
-                .collect(),
+            functions_a,
+            functions_b,
             matches: diff_result.matched_functions.clone(),
             metadata,
+            near_misses: diff_result.near_misses.clone(),
+            provenance: diff_result.provenance.clone(),
         };
 
-        let json_data = serde_json::to_string_pretty(&database)
+        Self::save_database(&database, output_path)
+    }
+
+    /// Write a database as-is, e.g. one previously loaded with
+    /// `load_diff_results`. Saving a loaded database reproduces the original
+    /// file byte for byte.
+    pub fn save_database(database: &DiffDatabase, output_path: &Path) -> Result<()> {
+        let json_data = serde_json::to_string_pretty(database)
             .context("Failed to serialize diff results")?;
 
         fs::write(output_path, json_data)
@@ -71,28 +264,103 @@ impl DatabaseManager {
         Ok(())
     }
 
-    /// Load diff results from a JSON file
+    /// Load diff results from a JSON file.
+    /// The file is deserialized straight from a buffered reader, so large
+    /// databases are never held in memory as both text and parsed structures.
     pub fn load_diff_results(input_path: &Path) -> Result<DiffDatabase> {
-        let json_data = fs::read_to_string(input_path)
+        let file = File::open(input_path)
             .context("Failed to read database file")?;
 
-        let database: DiffDatabase = serde_json::from_str(&json_data)
+        let mut deserializer = serde_json::Deserializer::from_reader(BufReader::new(file));
+        let database = DiffDatabase::deserialize(&mut deserializer)
             .context("Failed to deserialize diff results")?;
+        deserializer.end()
+            .context("Trailing data after diff results")?;
 
         Ok(database)
     }
 
+    /// Export only the symbol-propagation mapping: for each match at or above
+    /// `min_confidence`, the B-function address and the A-function name to
+    /// apply to it. Written as a compact JSON array suitable for importing
+    /// into a disassembler, independent of the full database format.
+    pub fn export_symbol_map(matches: &[FunctionMatch], min_confidence: f64, output_path: &Path) -> Result<()> {
+        let mapping: Vec<SymbolMapping> = matches
+            .iter()
+            .filter(|m| m.confidence >= min_confidence)
+            .map(|m| SymbolMapping {
+                address_b: m.function_b.address,
+                name: m.function_a.name.clone(),
+                confidence: m.confidence,
+            })
+            .collect();
+
+        let json_data = serde_json::to_string(&mapping)
+            .context("Failed to serialize symbol map")?;
+
+        fs::write(output_path, json_data)
+            .context("Failed to write symbol map file")?;
+
+        Ok(())
+    }
+
+    /// Export matches as JSON Lines: one `MatchRecord` per line, streamed
+    /// through a buffered writer so memory use does not grow with the number
+    /// of matches.
+    pub fn export_to_jsonl(database: &DiffDatabase, output_path: &Path) -> Result<()> {
+        let file = File::create(output_path)
+            .context("Failed to create JSONL file")?;
+        let mut writer = BufWriter::new(file);
+
+        for match_result in &database.matches {
+            let record = MatchRecord {
+                binary_a: database.binary_a_path.clone(),
+                binary_b: database.binary_b_path.clone(),
+                function_a: match_result.function_a.name.clone(),
+                address_a: match_result.function_a.address,
+                function_b: match_result.function_b.name.clone(),
+                address_b: match_result.function_b.address,
+                similarity: match_result.similarity,
+                confidence: match_result.confidence,
+                match_type: match_result.match_type.clone(),
+            };
+            serde_json::to_writer(&mut writer, &record)
+                .context("Failed to serialize JSONL record")?;
+            writer.write_all(b"\n")
+                .context("Failed to write JSONL file")?;
+        }
+
+        writer.flush()
+            .context("Failed to write JSONL file")?;
+
+        Ok(())
+    }
+
     /// Export results to CSV format
     pub fn export_to_csv(database: &DiffDatabase, output_path: &Path) -> Result<()> {
+        Self::write_csv(database, output_path, false)
+    }
+
+    /// Like `export_to_csv`, with the per-metric `MatchDetails` scores
+    /// appended to every matched-function row.
+    pub fn export_to_detailed_csv(database: &DiffDatabase, output_path: &Path) -> Result<()> {
+        Self::write_csv(database, output_path, true)
+    }
+
+    fn write_csv(database: &DiffDatabase, output_path: &Path, detailed: bool) -> Result<()> {
         let mut csv_content = String::new();
         
         // CSV header
-        csv_content.push_str("Function A,Address A,Function B,Address B,Similarity,Confidence,Match Type,Size A,Size B,BB Count A,BB Count B,Instr Count A,Instr Count B\n");
+        csv_content.push_str("Function A,Address A,Function B,Address B,Similarity,Confidence,Match Type,Size A,Size B,BB Count A,BB Count B,Instr Count A,Instr Count B");
+        if detailed {
+            csv_content.push_str(",CFG Similarity,BB Similarity,Instruction Similarity,Edge Similarity,Name Similarity,Call Similarity,Register Similarity,Exception Similarity,CFG Shape Similarity,Block Size Similarity,LCS Similarity");
+        }
+        csv_content.push('\n');
         
         // Add matched functions
         for match_result in &database.matches {
             csv_content.push_str(&format!(
-                "{},{:x},{},{:x},{:.4},{:.4},{:?},{},{},{},{},{},{}\n",
+                "{},{:x},{},{:x},{:.4},{:.4},{:?},{},{},{},{},{},{}",
                 match_result.function_a.name,
                 match_result.function_a.address,
                 match_result.function_b.name,
@@ -107,6 +375,44 @@ impl DatabaseManager {
                 match_result.function_a.instructions.len(),
                 match_result.function_b.instructions.len()
             ));
+            if detailed {
+                let details = &match_result.details;
+                csv_content.push_str(&format!(
+                    ",{:.4},{:.4},{:.4},{:.4},{:.4},{:.4},{:.4},{:.4},{:.4},{:.4},{:.4}",
+                    details.cfg_similarity,
+                    details.bb_similarity,
+                    details.instruction_similarity,
+                    details.edge_similarity,
+                    details.name_similarity,
+                    details.call_similarity,
+                    details.register_similarity,
+                    details.exception_similarity,
+                    details.cfg_shape_similarity,
+                    details.block_size_similarity,
+                    details.lcs_similarity
+                ));
+            }
+            csv_content.push('\n');
+        }
+
+        // Unmatched functions with their best near-miss candidate
+        if !database.near_misses.is_empty() {
+            csv_content.push_str("\nUnmatched Function,Address,Binary,Near Miss,Near Miss Address,Near Miss Similarity\n");
+            for near_miss in &database.near_misses {
+                let (candidate, candidate_address) = match (&near_miss.candidate_name, near_miss.candidate_address) {
+                    (Some(name), Some(address)) => (name.clone(), format!("{:x}", address)),
+                    _ => ("none".to_string(), String::new()),
+                };
+                csv_content.push_str(&format!(
+                    "{},{:x},{},{},{},{:.4}\n",
+                    near_miss.name,
+                    near_miss.address,
+                    if near_miss.in_binary_a { "A" } else { "B" },
+                    candidate,
+                    candidate_address,
+                    near_miss.similarity
+                ));
+            }
         }
         
         fs::write(output_path, csv_content)
@@ -120,54 +426,126 @@ impl DatabaseManager {
     /// Does not open a SQLite database itself.
     pub fn export_to_sql_script(database: &DiffDatabase, output_path: &Path) -> Result<()> {
         let mut sql_content = String::new();
-        
-        // Create table
-        sql_content.push_str("CREATE TABLE IF NOT EXISTS function_matches (\n");
-        sql_content.push_str("    id INTEGER PRIMARY KEY AUTOINCREMENT,\n");
-        sql_content.push_str("    function_a_name TEXT,\n");
-        sql_content.push_str("    function_a_address TEXT,\n");
-        sql_content.push_str("    function_b_name TEXT,\n");
-        sql_content.push_str("    function_b_address TEXT,\n");
-        sql_content.push_str("    similarity REAL,\n");
-        sql_content.push_str("    confidence REAL,\n");
-        sql_content.push_str("    match_type TEXT,\n");
-        sql_content.push_str("    size_a INTEGER,\n");
-        sql_content.push_str("    size_b INTEGER,\n");
-        sql_content.push_str("    bb_count_a INTEGER,\n");
-        sql_content.push_str("    bb_count_b INTEGER,\n");
-        sql_content.push_str("    instr_count_a INTEGER,\n");
-        sql_content.push_str("    instr_count_b INTEGER,\n");
-        sql_content.push_str("    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP\n");
-        sql_content.push_str(");\n\n");
-        
-        // Insert data
-        for match_result in &database.matches {
-            sql_content.push_str(&format!(
-                "INSERT INTO function_matches (function_a_name, function_a_address, function_b_name, function_b_address, similarity, confidence, match_type, size_a, size_b, bb_count_a, bb_count_b, instr_count_a, instr_count_b) VALUES ('{}', '0x{:016x}', '{}', '0x{:016x}', {:.4}, {:.4}, '{:?}', {}, {}, {}, {}, {}, {});\n",
-                match_result.function_a.name.replace("'", "''"),
-                match_result.function_a.address,
-                match_result.function_b.name.replace("'", "''"),
-                match_result.function_b.address,
-                match_result.similarity,
-                match_result.confidence,
-                match_result.match_type,
-                match_result.function_a.size,
-                match_result.function_b.size,
-                match_result.function_a.basic_blocks.len(),
-                match_result.function_b.basic_blocks.len(),
-                match_result.function_a.instructions.len(),
-                match_result.function_b.instructions.len()
-            ));
-        }
-        
+        push_sql_schema(&mut sql_content);
+        push_sql_run(&mut sql_content, database, &database.metadata.created_at);
+
         fs::write(output_path, sql_content)
             .context("Failed to write SQL file")?;
-        
+
+        Ok(())
+    }
+
+    /// Append a run to an existing SQL script so results from many diffs can
+    /// be accumulated into one SQLite database. Rows carry `run_id`, and
+    /// re-appending a run with the same id replaces its rows instead of
+    /// duplicating them. A missing or empty file gets the schema first; a
+    /// file written with a different schema is rejected.
+    pub fn append_to_sql_script(database: &DiffDatabase, run_id: &str, output_path: &Path) -> Result<()> {
+        // Only the header line is needed; the script may be large
+        let first_line = if output_path.exists() {
+            let file = File::open(output_path)
+                .context("Failed to open existing SQL file")?;
+            BufReader::new(file).lines().next().transpose()
+                .context("Failed to read existing SQL file")?
+        } else {
+            None
+        };
+
+        let mut sql_content = String::new();
+        if first_line.as_deref().is_none_or(|line| line.trim().is_empty()) {
+            push_sql_schema(&mut sql_content);
+        } else if first_line.as_deref() != Some(SQL_SCHEMA_HEADER) {
+            bail!(
+                "Existing SQL file {} was not written with a compatible schema (expected '{}')",
+                output_path.display(),
+                SQL_SCHEMA_HEADER
+            );
+        }
+        push_sql_run(&mut sql_content, database, run_id);
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(output_path)
+            .context("Failed to open SQL file for append")?;
+        file.write_all(sql_content.as_bytes())
+            .context("Failed to append to SQL file")?;
+
+        Ok(())
+    }
+
+    /// Export results into a real SQLite database at `output_path`, creating
+    /// it and the tables if needed. Same schema as the SQL script export;
+    /// values are bound as statement parameters, so names need no escaping.
+    #[cfg(feature = "sqlite")]
+    pub fn export_to_sqlite(database: &DiffDatabase, output_path: &Path) -> Result<()> {
+        Self::append_to_sqlite(database, &database.metadata.created_at, output_path)
+    }
+
+    /// Record a run in an SQLite database, creating it if needed. As with
+    /// `append_to_sql_script`, re-appending a run with the same id replaces
+    /// its rows. The whole run is written in one transaction.
+    #[cfg(feature = "sqlite")]
+    pub fn append_to_sqlite(database: &DiffDatabase, run_id: &str, output_path: &Path) -> Result<()> {
+        use rusqlite::{params, Connection};
+
+        let mut conn = Connection::open(output_path)
+            .with_context(|| format!("Failed to open SQLite database {}", output_path.display()))?;
+
+        let mut schema = String::new();
+        push_sql_schema(&mut schema);
+        conn.execute_batch(&schema)
+            .context("Failed to create SQLite schema")?;
+
+        let tx = conn.transaction()
+            .context("Failed to start SQLite transaction")?;
+        tx.execute("DELETE FROM function_matches WHERE run_id = ?1", params![run_id])
+            .context("Failed to clear previous rows for run")?;
+        tx.execute(
+            "INSERT OR REPLACE INTO diff_runs (run_id, binary_a_path, binary_b_path, plugin_version) VALUES (?1, ?2, ?3, ?4)",
+            params![run_id, database.binary_a_path, database.binary_b_path, database.metadata.plugin_version],
+        )
+        .context("Failed to record diff run")?;
+        {
+            let mut insert = tx
+                .prepare(
+                    "INSERT INTO function_matches (run_id, function_a_name, function_a_address, function_b_name, function_b_address, similarity, confidence, match_type, size_a, size_b, bb_count_a, bb_count_b, instr_count_a, instr_count_b) \
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+                )
+                .context("Failed to prepare match insert")?;
+            for match_result in &database.matches {
+                // SQLite integers are signed 64-bit; sizes beyond that saturate
+                let clamp = |n: u64| i64::try_from(n).unwrap_or(i64::MAX);
+                let count = |n: usize| i64::try_from(n).unwrap_or(i64::MAX);
+                insert
+                    .execute(params![
+                        run_id,
+                        match_result.function_a.name,
+                        format!("0x{:016x}", match_result.function_a.address),
+                        match_result.function_b.name,
+                        format!("0x{:016x}", match_result.function_b.address),
+                        match_result.similarity,
+                        match_result.confidence,
+                        format!("{:?}", match_result.match_type),
+                        clamp(match_result.function_a.size),
+                        clamp(match_result.function_b.size),
+                        count(match_result.function_a.basic_blocks.len()),
+                        count(match_result.function_b.basic_blocks.len()),
+                        count(match_result.function_a.instructions.len()),
+                        count(match_result.function_b.instructions.len()),
+                    ])
+                    .context("Failed to insert function match")?;
+            }
+        }
+        tx.commit()
+            .context("Failed to commit SQLite transaction")?;
+
         Ok(())
     }
 
     /// Export results to HTML report
     pub fn export_to_html(database: &DiffDatabase, output_path: &Path) -> Result<()> {
+        let coverage = Self::coverage(database);
         let html_content = format!(
             r#"
 <!DOCTYPE html>
@@ -193,6 +571,9 @@ impl DatabaseManager {
         <h1>Binary Diff Report</h1>
         <p>Generated by Rust Diff Plugin v{}</p>
         <p>Created: {}</p>
+        <p>Input hash A: {}</p>
+        <p>Input hash B: {}</p>
+        <details><summary>Configuration</summary><pre>{}</pre></details>
     </div>
     
     <div class="summary">
@@ -202,6 +583,8 @@ impl DatabaseManager {
         <p><strong>Total Functions A:</strong> {}</p>
         <p><strong>Total Functions B:</strong> {}</p>
         <p><strong>Total Matches:</strong> {}</p>
+        <p><strong>Coverage A:</strong> {:.1}% of functions, {:.1}% of instructions</p>
+        <p><strong>Coverage B:</strong> {:.1}% of functions, {:.1}% of instructions</p>
         <p><strong>Analysis Time:</strong> {:.2} seconds</p>
     </div>
     
@@ -220,18 +603,27 @@ impl DatabaseManager {
             {}
         </table>
     </div>
+    {}
 </body>
 </html>
 "#,
-            database.metadata.plugin_version,
-            database.metadata.created_at,
-            database.binary_a_path,
-            database.binary_b_path,
+            html_escape(&database.metadata.plugin_version),
+            html_escape(&database.metadata.created_at),
+            html_escape(&database.provenance.input_hash_a),
+            html_escape(&database.provenance.input_hash_b),
+            html_escape(&serde_json::to_string_pretty(&database.provenance.config).unwrap_or_default()),
+            html_escape(&database.binary_a_path),
+            html_escape(&database.binary_b_path),
             database.metadata.total_functions_a,
             database.metadata.total_functions_b,
             database.metadata.total_matches,
+            coverage.functions_a * 100.0,
+            coverage.instructions_a * 100.0,
+            coverage.functions_b * 100.0,
+            coverage.instructions_b * 100.0,
             database.metadata.analysis_time_seconds,
-            Self::generate_html_table_rows(&database.matches)
+            Self::generate_html_table_rows(&database.matches),
+            Self::generate_html_near_miss_section(&database.near_misses)
         );
 
         fs::write(output_path, html_content)
@@ -256,18 +648,18 @@ impl DatabaseManager {
             rows.push_str(&format!(
                 r#"<tr class="{}">
                     <td>{}</td>
-                    <td>0x{:x}</td>
                     <td>{}</td>
-                    <td>0x{:x}</td>
+                    <td>{}</td>
+                    <td>{}</td>
                     <td>{:.4}</td>
                     <td>{:.4}</td>
                     <td>{:?}</td>
                 </tr>"#,
                 class,
-                match_result.function_a.name,
-                match_result.function_a.address,
-                match_result.function_b.name,
-                match_result.function_b.address,
+                html_escape(&match_result.function_a.name),
+                match_result.function_a.display_address(),
+                html_escape(&match_result.function_b.name),
+                match_result.function_b.display_address(),
                 match_result.similarity,
                 match_result.confidence,
                 match_result.match_type
@@ -277,6 +669,67 @@ impl DatabaseManager {
         rows
     }
 
+    /// Generate the unmatched-functions section listing each function's best
+    /// near-miss candidate. Empty when no near misses were collected.
+    fn generate_html_near_miss_section(near_misses: &[NearMiss]) -> String {
+        if near_misses.is_empty() {
+            return String::new();
+        }
+
+        let mut rows = String::new();
+        for near_miss in near_misses {
+            let (candidate, candidate_address) = match (&near_miss.candidate_name, near_miss.candidate_address) {
+                (Some(name), Some(address)) => (html_escape(name), format!("0x{:x}", address)),
+                _ => ("none".to_string(), String::new()),
+            };
+            rows.push_str(&format!(
+                r#"<tr>
+                    <td>{}</td>
+                    <td>0x{:x}</td>
+                    <td>{}</td>
+                    <td>{}</td>
+                    <td>{}</td>
+                    <td>{:.4}</td>
+                </tr>"#,
+                html_escape(&near_miss.name),
+                near_miss.address,
+                if near_miss.in_binary_a { "A" } else { "B" },
+                candidate,
+                candidate_address,
+                near_miss.similarity
+            ));
+        }
+
+        format!(
+            r#"<div class="matches">
+        <h2>Unmatched Functions</h2>
+        <table>
+            <tr>
+                <th>Function</th>
+                <th>Address</th>
+                <th>Binary</th>
+                <th>Near Miss</th>
+                <th>Near Miss Address</th>
+                <th>Near Miss Similarity</th>
+            </tr>
+            {}
+        </table>
+    </div>"#,
+            rows
+        )
+    }
+
+    /// Coverage of the stored matches against every function in the database.
+    fn coverage(database: &DiffDatabase) -> Coverage {
+        Coverage::from_totals(
+            &database.matches,
+            database.metadata.total_functions_a,
+            database.metadata.total_functions_b,
+            database.functions_a.iter().map(|f| f.instructions.len()).sum(),
+            database.functions_b.iter().map(|f| f.instructions.len()).sum(),
+        )
+    }
+
     /// Generate statistics from diff results
     pub fn generate_statistics(database: &DiffDatabase) -> DiffStatistics {
         let mut exact_matches = 0;
@@ -320,8 +773,15 @@ impl DatabaseManager {
             manual_matches,
             average_similarity,
             average_confidence,
-            unmatched_functions_a: database.metadata.total_functions_a - total_matches,
-            unmatched_functions_b: database.metadata.total_functions_b - total_matches,
+            // Saturate: metadata in a hand-edited or truncated database may
+            // claim fewer functions than there are matches
+            unmatched_functions_a: database.metadata.total_functions_a
+                .saturating_sub(total_matches)
+                .saturating_sub(database.metadata.skipped_functions_a),
+            unmatched_functions_b: database.metadata.total_functions_b
+                .saturating_sub(total_matches)
+                .saturating_sub(database.metadata.skipped_functions_b),
+            coverage: Self::coverage(database),
         }
     }
 }
@@ -337,6 +797,7 @@ pub struct DiffStatistics {
     pub average_confidence: f64,
     pub unmatched_functions_a: usize,
     pub unmatched_functions_b: usize,
+    pub coverage: Coverage,
 }
 
 impl DiffStatistics {
@@ -352,5 +813,340 @@ impl DiffStatistics {
         println!("Average Confidence: {:.4}", self.average_confidence);
         println!("Unmatched Functions A: {}", self.unmatched_functions_a);
         println!("Unmatched Functions B: {}", self.unmatched_functions_b);
+        println!("Coverage A: {:.1}% of functions, {:.1}% of instructions",
+            self.coverage.functions_a * 100.0, self.coverage.instructions_a * 100.0);
+        println!("Coverage B: {:.1}% of functions, {:.1}% of instructions",
+            self.coverage.functions_b * 100.0, self.coverage.instructions_b * 100.0);
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn database(matches: Vec<FunctionMatch>) -> DiffDatabase {
+        DiffDatabase {
+            binary_a_path: "a.bin".to_string(),
+            binary_b_path: "b.bin".to_string(),
+            functions_a: Vec::new(),
+            functions_b: Vec::new(),
+            metadata: DatabaseMetadata {
+                created_at: "0".to_string(),
+                plugin_version: env!("CARGO_PKG_VERSION").to_string(),
+                binary_a_hash: String::new(),
+                binary_b_hash: String::new(),
+                total_functions_a: matches.len(),
+                total_functions_b: matches.len(),
+                total_matches: matches.len(),
+                analysis_time_seconds: 0.0,
+                skipped_functions_a: 0,
+                skipped_functions_b: 0,
+            },
+            matches,
+            near_misses: Vec::new(),
+            provenance: Provenance::default(),
+        }
+    }
+
+    fn named_match(name_a: &str, name_b: &str) -> FunctionMatch {
+        FunctionMatch {
+            function_a: FunctionInfo { name: name_a.to_string(), ..Default::default() },
+            function_b: FunctionInfo { name: name_b.to_string(), ..Default::default() },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn html_export_escapes_names_and_paths() {
+        let mut database = database(vec![named_match("<b>bold</b>", "a&b")]);
+        database.binary_a_path = "<script>alert(1)</script>".to_string();
+        let path = std::env::temp_dir().join("rust_diff_html_escape_test.html");
+
+        DatabaseManager::export_to_html(&database, &path).unwrap();
+        let html = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(html.contains("&lt;b&gt;bold&lt;/b&gt;"));
+        assert!(html.contains("a&amp;b"));
+        assert!(!html.contains("<b>bold"));
+        assert!(!html.contains("<script>"));
+    }
+
+    #[test]
+    fn load_streams_a_large_database() {
+        let functions = crate::mock::generate_mock_functions("binary").unwrap();
+        let matches: Vec<FunctionMatch> = (0..1_000u64)
+            .map(|i| {
+                let mut function = functions[i as usize % functions.len()].clone();
+                function.address = i;
+                FunctionMatch { function_a: function.clone(), function_b: function, ..Default::default() }
+            })
+            .collect();
+        let database = database(matches);
+        let path = std::env::temp_dir().join("rust_diff_large_database_test.json");
+
+        DatabaseManager::save_database(&database, &path).unwrap();
+        let loaded = DatabaseManager::load_diff_results(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.matches.len(), 1_000);
+        assert_eq!(loaded.metadata.total_matches, 1_000);
+        assert_eq!(loaded.matches[999].function_b.address, 999);
+    }
+
+    #[test]
+    fn symbol_map_keeps_only_confident_matches() {
+        let mut confident = named_match("parse_header", "sub_401000");
+        confident.function_b.address = 0x401000;
+        confident.confidence = 0.9;
+        let mut doubtful = named_match("main", "sub_402000");
+        doubtful.confidence = 0.2;
+        let path = std::env::temp_dir().join("rust_diff_symbol_map_test.json");
+
+        DatabaseManager::export_symbol_map(&[confident, doubtful], 0.5, &path).unwrap();
+        let json = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(json, r#"[{"address_b":4198400,"name":"parse_header","confidence":0.9}]"#);
+        let mapping: Vec<SymbolMapping> = serde_json::from_str(&json).unwrap();
+        assert_eq!(mapping.len(), 1);
+    }
+
+    #[test]
+    fn sql_append_replaces_reappended_runs_and_rejects_other_schemas() {
+        let run = |n: usize| database((0..n).map(|i| named_match(&format!("f{}", i), &format!("g{}", i))).collect());
+        let path = std::env::temp_dir().join("rust_diff_sql_append_test.sql");
+        let _ = fs::remove_file(&path);
+
+        DatabaseManager::append_to_sql_script(&run(3), "r1", &path).unwrap();
+        DatabaseManager::append_to_sql_script(&run(2), "r2", &path).unwrap();
+        DatabaseManager::append_to_sql_script(&run(2), "r2", &path).unwrap();
+        let script = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(script.matches(SQL_SCHEMA_HEADER).count(), 1);
+        assert_eq!(script.matches("DELETE FROM function_matches WHERE run_id = 'r2'").count(), 2);
+        #[cfg(feature = "sqlite")]
+        {
+            let connection = rusqlite::Connection::open_in_memory().unwrap();
+            connection.execute_batch(&script).unwrap();
+            let rows: usize = connection.query_row("SELECT COUNT(*) FROM function_matches", [], |row| row.get(0)).unwrap();
+            assert_eq!(rows, 5);
+        }
+
+        let foreign = std::env::temp_dir().join("rust_diff_sql_append_foreign_test.sql");
+        fs::write(&foreign, "CREATE TABLE other (id INTEGER);\n").unwrap();
+        assert!(DatabaseManager::append_to_sql_script(&run(1), "r1", &foreign).is_err());
+        fs::remove_file(&foreign).unwrap();
+    }
+
+    #[test]
+    fn near_misses_appear_in_csv_and_html_exports() {
+        let unmatched_a = crate::mock::generate_mock_functions("binary_a").unwrap();
+        let unmatched_b = crate::mock::generate_mock_functions("binary_b").unwrap();
+        let near_misses = crate::matching::MatchingEngine::find_near_misses(&unmatched_a, &unmatched_b);
+        assert_eq!(near_misses.len(), unmatched_a.len() + unmatched_b.len());
+        assert!(near_misses.iter().all(|n| n.candidate_name.as_ref() == Some(&n.name)));
+        let isolated = crate::matching::MatchingEngine::find_near_misses(&unmatched_a, &[]);
+        assert!(isolated.iter().all(|n| n.candidate_name.is_none()));
+
+        let mut database = database(Vec::new());
+        database.near_misses = near_misses;
+        let csv_path = std::env::temp_dir().join("rust_diff_near_miss_test.csv");
+        let html_path = std::env::temp_dir().join("rust_diff_near_miss_test.html");
+        DatabaseManager::export_to_csv(&database, &csv_path).unwrap();
+        DatabaseManager::export_to_html(&database, &html_path).unwrap();
+        let csv = fs::read_to_string(&csv_path).unwrap();
+        let html = fs::read_to_string(&html_path).unwrap();
+        fs::remove_file(&csv_path).unwrap();
+        fs::remove_file(&html_path).unwrap();
+
+        assert!(csv.contains("Near Miss") && csv.contains("process_data"));
+        assert!(html.contains("Unmatched Functions") && html.contains("process_data"));
+    }
+
+    #[test]
+    fn detailed_csv_appends_every_metric_column() {
+        let functions = crate::mock::generate_mock_functions("binary").unwrap();
+        let matches = crate::matching::MatchingEngine::new().match_functions(&functions, &functions[1..]).unwrap();
+        let database = database(matches);
+        let plain_path = std::env::temp_dir().join("rust_diff_plain_csv_test.csv");
+        let detailed_path = std::env::temp_dir().join("rust_diff_detailed_csv_test.csv");
+
+        DatabaseManager::export_to_csv(&database, &plain_path).unwrap();
+        DatabaseManager::export_to_detailed_csv(&database, &detailed_path).unwrap();
+        let plain = fs::read_to_string(&plain_path).unwrap();
+        let detailed = fs::read_to_string(&detailed_path).unwrap();
+        fs::remove_file(&plain_path).unwrap();
+        fs::remove_file(&detailed_path).unwrap();
+
+        let columns = |line: &str| line.split(',').map(str::to_string).collect::<Vec<_>>();
+        assert_eq!(columns(plain.lines().next().unwrap()).len(), 13);
+        let header = columns(detailed.lines().next().unwrap());
+        assert_eq!(header.len(), 24);
+        let row = columns(detailed.lines().nth(1).unwrap());
+        assert_eq!(row.len(), header.len());
+        let details = &database.matches[0].details;
+        assert_eq!(row[13], format!("{:.4}", details.cfg_similarity));
+        assert_eq!(row[18], format!("{:.4}", details.call_similarity));
+    }
+
+    #[test]
+    fn provenance_is_stamped_on_every_export() {
+        let result = crate::BinaryDiffEngine::new().perform_diff_mock("binary_a", "binary_b").unwrap();
+        let provenance = &result.provenance;
+        assert_eq!(provenance.input_hash_a.len(), 64);
+
+        let dir = std::env::temp_dir().join("rust_diff_provenance_test");
+        fs::create_dir_all(&dir).unwrap();
+        let (binary_a, binary_b) = (dir.join("a.bin"), dir.join("b.bin"));
+        fs::write(&binary_a, b"a").unwrap();
+        fs::write(&binary_b, b"b").unwrap();
+        let (json_path, html_path) = (dir.join("diff.json"), dir.join("diff.html"));
+        DatabaseManager::save_diff_results(&result, binary_a.to_str().unwrap(), binary_b.to_str().unwrap(), &json_path).unwrap();
+        let database = DatabaseManager::load_diff_results(&json_path).unwrap();
+        DatabaseManager::export_to_html(&database, &html_path).unwrap();
+        let json = fs::read_to_string(&json_path).unwrap();
+        let html = fs::read_to_string(&html_path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let text = crate::ui::DiffUI::generate_text_report(&result);
+        let markdown = crate::ui::DiffUI::generate_markdown_report(&result);
+        assert!(markdown.contains("<summary>Config</summary>"));
+        for output in [&text, &markdown, &json, &html] {
+            assert!(output.contains(env!("CARGO_PKG_VERSION")));
+            assert!(output.contains(&provenance.input_hash_a));
+            assert!(output.contains(&provenance.input_hash_b));
+        }
+
+        let report: serde_json::Value = serde_json::from_str(&crate::ui::DiffUI::generate_json_report(&result)).unwrap();
+        assert_eq!(report["provenance"]["crate_version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(report["provenance"]["input_hash_a"], provenance.input_hash_a.as_str());
+        assert_eq!(report["provenance"]["input_hash_b"], provenance.input_hash_b.as_str());
+        assert_eq!(report["provenance"]["config"]["pass_order"], serde_json::to_value(&provenance.config.pass_order).unwrap());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn sqlite_export_writes_one_row_per_match() {
+        let mut database = database((0..4).map(|i| named_match(&format!("f{}", i), &format!("g{}", i))).collect());
+        database.matches[0].function_a.name = "we'ird\"name".to_string();
+        let path = std::env::temp_dir().join("rust_diff_sqlite_test.sqlite");
+        let _ = fs::remove_file(&path);
+
+        DatabaseManager::export_to_sqlite(&database, &path).unwrap();
+        let connection = rusqlite::Connection::open(&path).unwrap();
+        let count = |connection: &rusqlite::Connection| -> usize {
+            connection.query_row("SELECT COUNT(*) FROM function_matches", [], |row| row.get(0)).unwrap()
+        };
+        assert_eq!(count(&connection), database.matches.len());
+        let name: String = connection
+            .query_row("SELECT function_a_name FROM function_matches ORDER BY id LIMIT 1", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(name, "we'ird\"name");
+
+        // Re-appending a run replaces its rows
+        DatabaseManager::append_to_sqlite(&database, "second", &path).unwrap();
+        DatabaseManager::append_to_sqlite(&database, "second", &path).unwrap();
+        assert_eq!(count(&connection), 2 * database.matches.len());
+        drop(connection);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn metadata_records_binary_hashes_and_totals() {
+        let result = crate::BinaryDiffEngine::new().perform_diff_mock("binary_a", "binary_b").unwrap();
+        let dir = std::env::temp_dir().join("rust_diff_metadata_test");
+        fs::create_dir_all(&dir).unwrap();
+        let (binary_a, binary_b) = (dir.join("a.bin"), dir.join("b.bin"));
+        fs::write(&binary_a, b"abc").unwrap();
+        fs::write(&binary_b, b"").unwrap();
+        let output = dir.join("diff.json");
+
+        DatabaseManager::save_diff_results(&result, binary_a.to_str().unwrap(), binary_b.to_str().unwrap(), &output).unwrap();
+        let metadata = DatabaseManager::load_diff_results(&output).unwrap().metadata;
+        assert_eq!(metadata.binary_a_hash, "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        assert_eq!(metadata.binary_b_hash, "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+
+        // A missing binary is an error naming it, and nothing is written
+        let missing_output = dir.join("missing.json");
+        let error = DatabaseManager::save_diff_results(&result, "/nonexistent/binary", binary_b.to_str().unwrap(), &missing_output)
+            .unwrap_err();
+        assert!(format!("{:#}", error).contains("/nonexistent/binary"));
+        assert!(!missing_output.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn analysis_time_and_totals_survive_save_and_load() {
+        let result = crate::BinaryDiffEngine::new().perform_diff_mock("binary_a", "binary_b").unwrap();
+        assert!(result.analysis_time > 0.0);
+        let dir = std::env::temp_dir().join("rust_diff_analysis_time_test");
+        fs::create_dir_all(&dir).unwrap();
+        let binary = dir.join("a.bin");
+        fs::write(&binary, b"x").unwrap();
+        let output = dir.join("diff.json");
+
+        DatabaseManager::save_diff_results(&result, binary.to_str().unwrap(), binary.to_str().unwrap(), &output).unwrap();
+        let database = DatabaseManager::load_diff_results(&output).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!((database.metadata.analysis_time_seconds - result.analysis_time).abs() < 1e-9);
+        assert_eq!(database.metadata.total_functions_a, database.functions_a.len());
+        assert_eq!(database.metadata.total_functions_b, database.functions_b.len());
+        assert_eq!(
+            database.metadata.total_functions_a,
+            result.matched_functions.len() + result.unmatched_functions_a.len() + result.skipped_functions_a.len()
+        );
+    }
+
+    #[test]
+    fn jsonl_export_writes_one_record_per_line() {
+        let mut first = named_match("parse", "parse_v2");
+        first.function_b.address = 0x401000;
+        first.match_type = MatchType::Structural;
+        let database = database(vec![first, named_match("main", "main")]);
+        let path = std::env::temp_dir().join("rust_diff_jsonl_test.jsonl");
+
+        DatabaseManager::export_to_jsonl(&database, &path).unwrap();
+        let records: Vec<MatchRecord> = BufReader::new(File::open(&path).unwrap())
+            .lines()
+            .map(|line| serde_json::from_str(&line.unwrap()).unwrap())
+            .collect();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].binary_a, "a.bin");
+        assert_eq!(records[0].binary_b, "b.bin");
+        assert_eq!(records[0].function_a, "parse");
+        assert_eq!(records[0].function_b, "parse_v2");
+        assert_eq!(records[0].address_b, 0x401000);
+        assert_eq!(records[0].match_type, MatchType::Structural);
+        assert_eq!(records[1].function_a, "main");
+    }
+
+    #[test]
+    fn save_load_save_is_byte_identical() {
+        let mut engine = crate::BinaryDiffEngine::new();
+        engine.config.min_function_size = 40;
+        engine.config.collect_near_misses = true;
+        let result = engine.perform_diff_mock("binary_a", "binary_b").unwrap();
+        let dir = std::env::temp_dir().join("rust_diff_round_trip_test");
+        fs::create_dir_all(&dir).unwrap();
+        let binary = dir.join("a.bin");
+        fs::write(&binary, b"x").unwrap();
+        let (first, second) = (dir.join("first.json"), dir.join("second.json"));
+
+        DatabaseManager::save_diff_results(&result, binary.to_str().unwrap(), binary.to_str().unwrap(), &first).unwrap();
+        let database = DatabaseManager::load_diff_results(&first).unwrap();
+        database.assert_round_trip().unwrap();
+        DatabaseManager::save_database(&database, &second).unwrap();
+        assert_eq!(fs::read(&first).unwrap(), fs::read(&second).unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+
+        // Counts are stored, not derived from what happens to be in the file
+        assert_eq!(database.metadata.skipped_functions_a, result.skipped_functions_a.len());
+        let statistics = DatabaseManager::generate_statistics(&database);
+        assert_eq!(statistics.unmatched_functions_a, result.unmatched_functions_a.len());
+        assert_eq!(statistics.unmatched_functions_b, result.unmatched_functions_b.len());
+    }
+}
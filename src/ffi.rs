@@ -1,8 +1,8 @@
-use std::ffi::CStr;
-use std::os::raw::c_char;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_void};
 use std::panic::{catch_unwind, AssertUnwindSafe};
-use log::{info, error};
-use crate::{BinaryDiffEngine, DiffResult};
+use log::{info, error, LevelFilter};
+use crate::{BinaryDiffEngine, DiffResult, FunctionInfo};
 
 fn guard<T>(default: T, f: impl FnOnce() -> T) -> T {
     match catch_unwind(AssertUnwindSafe(f)) {
@@ -14,6 +14,18 @@ fn guard<T>(default: T, f: impl FnOnce() -> T) -> T {
     }
 }
 
+/// A C progress callback and the opaque pointer passed back to it.
+#[derive(Clone, Copy)]
+pub(crate) struct ProgressCallback {
+    callback: extern "C" fn(usize, usize, *mut c_void),
+    user_data: *mut c_void,
+}
+
+// The engine may be moved to another thread; making `user_data` safe to use
+// from there is the C caller's responsibility. Not `Sync`: the callback is
+// only ever invoked from the thread running the diff.
+unsafe impl Send for ProgressCallback {}
+
 #[no_mangle]
 pub extern "C" fn rust_diff_init() -> *mut BinaryDiffEngine {
     guard(std::ptr::null_mut(), || {
@@ -23,6 +35,23 @@ pub extern "C" fn rust_diff_init() -> *mut BinaryDiffEngine {
     })
 }
 
+/// Set the log level: 0 = off, 1 = error, 2 = warn, 3 = info, 4 = debug,
+/// 5 = trace. Out-of-range values are clamped.
+#[no_mangle]
+pub extern "C" fn rust_diff_set_log_level(level: i32) {
+    guard((), || {
+        let filter = match level {
+            i32::MIN..=0 => LevelFilter::Off,
+            1 => LevelFilter::Error,
+            2 => LevelFilter::Warn,
+            3 => LevelFilter::Info,
+            4 => LevelFilter::Debug,
+            _ => LevelFilter::Trace,
+        };
+        crate::set_log_level(filter);
+    })
+}
+
 #[no_mangle]
 pub extern "C" fn rust_diff_cleanup(engine: *mut BinaryDiffEngine) {
     guard((), || {
@@ -34,6 +63,31 @@ pub extern "C" fn rust_diff_cleanup(engine: *mut BinaryDiffEngine) {
     })
 }
 
+/// Register `callback` to be called with `(functions_processed,
+/// total_functions, user_data)` after every matching pass of subsequent
+/// `rust_diff_perform_diff_mock` calls on `engine`. It is called on the
+/// thread running the diff. Pass a null callback to unregister.
+///
+/// # Safety
+///
+/// `engine` must be null or a pointer returned by `rust_diff_init` that
+/// hasn't been passed to `rust_diff_cleanup`, and `callback` must stay
+/// callable with `user_data` for as long as it is registered.
+#[no_mangle]
+pub unsafe extern "C" fn rust_diff_set_progress_callback(
+    engine: *mut BinaryDiffEngine,
+    callback: Option<extern "C" fn(usize, usize, *mut c_void)>,
+    user_data: *mut c_void,
+) {
+    guard((), || {
+        if engine.is_null() {
+            return;
+        }
+        let engine = unsafe { &mut *engine };
+        engine.progress_callback = callback.map(|callback| ProgressCallback { callback, user_data });
+    })
+}
+
 #[no_mangle]
 pub extern "C" fn rust_diff_perform_diff_mock(
     engine: *mut BinaryDiffEngine,
@@ -59,7 +113,13 @@ pub extern "C" fn rust_diff_perform_diff_mock(
             Err(_) => return std::ptr::null_mut(),
         };
 
-        match engine.perform_diff_mock(binary_a_name, binary_b_name) {
+        let outcome = match engine.progress_callback {
+            Some(progress) => engine.perform_diff_with_progress(binary_a_name, binary_b_name, |done, total| {
+                (progress.callback)(done, total, progress.user_data)
+            }),
+            None => engine.perform_diff_mock(binary_a_name, binary_b_name),
+        };
+        match outcome {
             Ok(result) => Box::into_raw(Box::new(result)),
             Err(e) => {
                 error!("Diff failed: {}", e);
@@ -69,6 +129,72 @@ pub extern "C" fn rust_diff_perform_diff_mock(
     })
 }
 
+/// Locate the function at `target_address` in binary A among the functions
+/// of binary B. Returns a result whose matches are the `top_k` candidates,
+/// best first, or null if the target doesn't exist. Free it with
+/// `rust_diff_free_result`. Each binary is extracted once per engine and
+/// reused by later calls.
+///
+/// # Safety
+///
+/// `engine` must be null or a live pointer returned by `rust_diff_init`, and
+/// the names must be null or NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn rust_diff_locate_function_mock(
+    engine: *mut BinaryDiffEngine,
+    binary_a_name: *const c_char,
+    binary_b_name: *const c_char,
+    target_address: u64,
+    top_k: usize,
+) -> *mut DiffResult {
+    guard(std::ptr::null_mut(), || {
+        if engine.is_null() || binary_a_name.is_null() || binary_b_name.is_null() {
+            return std::ptr::null_mut();
+        }
+
+        let engine = unsafe { &mut *engine };
+        let (binary_a_name, binary_b_name) = unsafe {
+            match (CStr::from_ptr(binary_a_name).to_str(), CStr::from_ptr(binary_b_name).to_str()) {
+                (Ok(a), Ok(b)) => (a, b),
+                _ => return std::ptr::null_mut(),
+            }
+        };
+
+        for name in [binary_a_name, binary_b_name] {
+            if let Err(e) = engine.cache_function_info_mock(name) {
+                error!("Function extraction failed: {}", e);
+                return std::ptr::null_mut();
+            }
+        }
+        let functions_a = &engine.extracted_functions[binary_a_name];
+        let functions_b = &engine.extracted_functions[binary_b_name];
+
+        let target = match functions_a.iter().find(|f| f.address == target_address) {
+            Some(target) => target,
+            None => {
+                error!("No function at 0x{:x} in {}", target_address, binary_a_name);
+                return std::ptr::null_mut();
+            }
+        };
+
+        let matched_functions = match engine.locate_function(target, functions_b, top_k) {
+            Ok(matched_functions) => matched_functions,
+            Err(e) => {
+                error!("Locating 0x{:x} failed: {}", target_address, e);
+                return std::ptr::null_mut();
+            }
+        };
+        let similarity_score = matched_functions.first().map_or(0.0, |m| m.similarity);
+        Box::into_raw(Box::new(DiffResult {
+            matched_functions,
+            similarity_score,
+            binary_a_name: binary_a_name.to_string(),
+            binary_b_name: binary_b_name.to_string(),
+            ..Default::default()
+        }))
+    })
+}
+
 #[no_mangle]
 pub extern "C" fn rust_diff_free_result(result: *mut DiffResult) {
     guard((), || {
@@ -102,6 +228,98 @@ pub extern "C" fn rust_diff_get_similarity_score(result: *const DiffResult) -> f
     })
 }
 
+/// Number of functions of binary A left unmatched, or 0 if `result` is null.
+///
+/// # Safety
+///
+/// `result` must be null or a live pointer returned by this library.
+#[no_mangle]
+pub unsafe extern "C" fn rust_diff_get_unmatched_a_count(result: *const DiffResult) -> usize {
+    guard(0, || {
+        if result.is_null() {
+            return 0;
+        }
+        let result = unsafe { &*result };
+        result.unmatched_functions_a.len()
+    })
+}
+
+/// Number of functions of binary B left unmatched; see
+/// `rust_diff_get_unmatched_a_count`.
+///
+/// # Safety
+///
+/// `result` must be null or a live pointer returned by this library.
+#[no_mangle]
+pub unsafe extern "C" fn rust_diff_get_unmatched_b_count(result: *const DiffResult) -> usize {
+    guard(0, || {
+        if result.is_null() {
+            return 0;
+        }
+        let result = unsafe { &*result };
+        result.unmatched_functions_b.len()
+    })
+}
+
+/// Name of the `index`-th function of binary A left unmatched, or null if
+/// `result` is null or `index` is out of bounds. Free the returned string
+/// with `rust_diff_free_string`.
+///
+/// # Safety
+///
+/// `result` must be null or a live pointer returned by this library.
+#[no_mangle]
+pub unsafe extern "C" fn rust_diff_get_unmatched_a_name(result: *const DiffResult, index: usize) -> *mut c_char {
+    guard(std::ptr::null_mut(), || {
+        if result.is_null() {
+            return std::ptr::null_mut();
+        }
+        let result = unsafe { &*result };
+        unmatched_name(&result.unmatched_functions_a, index)
+    })
+}
+
+/// Name of the `index`-th function of binary B left unmatched; see
+/// `rust_diff_get_unmatched_a_name`.
+///
+/// # Safety
+///
+/// `result` must be null or a live pointer returned by this library.
+#[no_mangle]
+pub unsafe extern "C" fn rust_diff_get_unmatched_b_name(result: *const DiffResult, index: usize) -> *mut c_char {
+    guard(std::ptr::null_mut(), || {
+        if result.is_null() {
+            return std::ptr::null_mut();
+        }
+        let result = unsafe { &*result };
+        unmatched_name(&result.unmatched_functions_b, index)
+    })
+}
+
+fn unmatched_name(functions: &[FunctionInfo], index: usize) -> *mut c_char {
+    functions
+        .get(index)
+        .and_then(|func| CString::new(func.name.as_str()).ok())
+        .map_or(std::ptr::null_mut(), CString::into_raw)
+}
+
+/// Free a string returned by this library. Null is ignored.
+///
+/// # Safety
+///
+/// `s` must be null or a string returned by this library that hasn't
+/// already been freed.
+#[no_mangle]
+pub unsafe extern "C" fn rust_diff_free_string(s: *mut c_char) {
+    guard((), || {
+        if !s.is_null() {
+            unsafe {
+                let _ = CString::from_raw(s);
+            }
+        }
+    })
+}
+
 #[no_mangle]
 pub extern "C" fn rust_diff_save_results(
     result: *const DiffResult,
@@ -126,3 +344,89 @@ pub extern "C" fn rust_diff_save_results(
         }
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MatchPass;
+
+    extern "C" fn record_progress(done: usize, total: usize, user_data: *mut c_void) {
+        let calls = unsafe { &mut *(user_data as *mut Vec<(usize, usize)>) };
+        calls.push((done, total));
+    }
+
+    #[test]
+    fn progress_callback_reports_every_pass_until_unregistered() {
+        let engine = rust_diff_init();
+        let name = CString::new("binary").unwrap();
+        let mut calls: Vec<(usize, usize)> = Vec::new();
+
+        unsafe { rust_diff_set_progress_callback(engine, Some(record_progress), &mut calls as *mut _ as *mut c_void) };
+        let result = rust_diff_perform_diff_mock(engine, name.as_ptr(), name.as_ptr());
+        assert!(!result.is_null());
+        assert_eq!(calls.len(), MatchPass::DEFAULT_ORDER.len() + 1);
+        let total = calls[0].1;
+        assert_eq!(calls.last(), Some(&(total, total)));
+        assert!(calls.windows(2).all(|w| w[0].0 <= w[1].0));
+
+        unsafe { rust_diff_set_progress_callback(engine, None, std::ptr::null_mut()) };
+        let second = rust_diff_perform_diff_mock(engine, name.as_ptr(), name.as_ptr());
+        assert_eq!(calls.len(), MatchPass::DEFAULT_ORDER.len() + 1);
+
+        unsafe { rust_diff_set_progress_callback(std::ptr::null_mut(), None, std::ptr::null_mut()) };
+        rust_diff_free_result(result);
+        rust_diff_free_result(second);
+        rust_diff_cleanup(engine);
+    }
+
+    #[test]
+    fn unmatched_getters_handle_null_and_out_of_bounds() {
+        let mut result = DiffResult::default();
+        result.unmatched_functions_a.push(FunctionInfo { name: "only_in_a".to_string(), ..Default::default() });
+        result.unmatched_functions_b.push(FunctionInfo { name: "b_one".to_string(), ..Default::default() });
+        result.unmatched_functions_b.push(FunctionInfo { name: "b_two".to_string(), ..Default::default() });
+        let result = Box::into_raw(Box::new(result));
+
+        unsafe {
+            assert_eq!(rust_diff_get_unmatched_a_count(result), 1);
+            assert_eq!(rust_diff_get_unmatched_b_count(result), 2);
+
+            let name = rust_diff_get_unmatched_b_name(result, 1);
+            assert_eq!(CStr::from_ptr(name).to_str().unwrap(), "b_two");
+            rust_diff_free_string(name);
+            let name = rust_diff_get_unmatched_a_name(result, 0);
+            assert_eq!(CStr::from_ptr(name).to_str().unwrap(), "only_in_a");
+            rust_diff_free_string(name);
+
+            assert!(rust_diff_get_unmatched_a_name(result, 1).is_null());
+            assert!(rust_diff_get_unmatched_b_name(result, usize::MAX).is_null());
+            assert!(rust_diff_get_unmatched_a_name(std::ptr::null(), 0).is_null());
+            assert!(rust_diff_get_unmatched_b_name(std::ptr::null(), 0).is_null());
+            assert_eq!(rust_diff_get_unmatched_a_count(std::ptr::null()), 0);
+            assert_eq!(rust_diff_get_unmatched_b_count(std::ptr::null()), 0);
+            rust_diff_free_string(std::ptr::null_mut());
+        }
+        rust_diff_free_result(result);
+    }
+
+    #[test]
+    fn locate_function_extracts_each_binary_once() {
+        let engine = rust_diff_init();
+        let (name_a, name_b) = (CString::new("binary_a").unwrap(), CString::new("binary_b").unwrap());
+        let functions_a = unsafe { &*engine }.extract_function_info_mock("binary_a").unwrap();
+        let target = &functions_a[2];
+
+        let result = unsafe { rust_diff_locate_function_mock(engine, name_a.as_ptr(), name_b.as_ptr(), target.address, 2) };
+        assert!(!result.is_null());
+        assert_eq!(rust_diff_get_match_count(result), 2);
+        assert_eq!(unsafe { &*result }.matched_functions[0].function_b.name, target.name);
+        assert_eq!(unsafe { &*engine }.extracted_functions.len(), 2);
+
+        let missing = unsafe { rust_diff_locate_function_mock(engine, name_a.as_ptr(), name_b.as_ptr(), 0xdead_beef, 2) };
+        assert!(missing.is_null());
+        assert_eq!(unsafe { &*engine }.extracted_functions.len(), 2);
+
+        rust_diff_free_result(result);
+        rust_diff_cleanup(engine);
+    }
+}
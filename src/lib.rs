@@ -1,9 +1,17 @@
 use std::collections::HashSet;
+use std::sync::{mpsc, Arc};
+use std::sync::atomic::AtomicBool;
 use std::time::Instant;
-use anyhow::{Result, Context};
-use log::info;
+use anyhow::{Result, Context, bail};
+use log::{info, warn};
+use rayon::prelude::*;
+use regex::Regex;
+use rustc_hash::FxHashMap;
+use sha2::{Digest, Sha256};
 
 pub mod types;
+pub mod config;
+pub mod normalize;
 pub mod algorithms;
 pub mod similarity;
 pub mod matching;
@@ -11,14 +19,69 @@ pub mod database;
 pub mod ui;
 pub mod ffi;
 pub mod mock;
+pub mod provider;
+pub mod calibration;
+pub mod session;
+#[cfg(feature = "bench")]
+pub mod bench;
+#[cfg(feature = "async")]
+pub mod async_diff;
 
 pub use types::*;
+pub use config::{DiffConfig, Preset, SimilarityWeights};
 pub use algorithms::*;
 pub use similarity::*;
+pub use provider::{FunctionProvider, JsonFileProvider};
+pub use mock::MockProvider;
 
+/// Set the maximum log level for the whole crate (and anything else using
+/// the `log` facade). Per-pass statistics are logged at `Info` with
+/// structured key-values `pass`, `matches`, `candidates_a`, `candidates_b`
+/// and `elapsed_ms`.
+pub fn set_log_level(level: log::LevelFilter) {
+    log::set_max_level(level);
+}
+
+/// Pull the functions at the (sorted) `unmatched` and `skipped` indices out of
+/// `functions`, dropping the rest.
+fn split_by_indices(
+    functions: Vec<FunctionInfo>,
+    unmatched: &[usize],
+    skipped: &[usize],
+) -> (Vec<FunctionInfo>, Vec<FunctionInfo>) {
+    let mut unmatched_functions = Vec::with_capacity(unmatched.len());
+    let mut skipped_functions = Vec::with_capacity(skipped.len());
+    for (idx, func) in functions.into_iter().enumerate() {
+        if unmatched.binary_search(&idx).is_ok() {
+            unmatched_functions.push(func);
+        } else if skipped.binary_search(&idx).is_ok() {
+            skipped_functions.push(func);
+        }
+    }
+    (unmatched_functions, skipped_functions)
+}
+
+/// Number of fingerprints kept in a whole-binary fuzzy hash sketch.
+const BINARY_FUZZY_HASH_SKETCH_SIZE: usize = 256;
+
+#[derive(Clone)]
 pub struct BinaryDiffEngine {
     pub similarity_threshold: f64,
     pub confidence_threshold: f64,
+    /// Optional calibration curve applied to match confidences after matching.
+    pub calibration: Option<calibration::ConfidenceCalibrator>,
+    pub config: DiffConfig,
+    /// Progress callback registered through the C API.
+    pub(crate) progress_callback: Option<ffi::ProgressCallback>,
+    /// Only functions whose name matches are diffed.
+    include_filter: Option<Regex>,
+    /// Functions whose name matches are left out of the diff.
+    exclude_filter: Option<Regex>,
+    /// Mock extractions by binary name, kept for repeated lookups through
+    /// `rust_diff_locate_function_mock`.
+    pub(crate) extracted_functions: FxHashMap<String, Vec<FunctionInfo>>,
+    /// Pool for `DiffConfig::num_threads`, reused across diffs.
+    thread_pool: matching::ThreadPoolCache,
 }
 
 impl BinaryDiffEngine {
@@ -26,6 +89,13 @@ impl BinaryDiffEngine {
         Self {
             similarity_threshold: 0.6,
             confidence_threshold: 0.5,
+            calibration: None,
+            config: DiffConfig::default(),
+            progress_callback: None,
+            include_filter: None,
+            exclude_filter: None,
+            extracted_functions: FxHashMap::default(),
+            thread_pool: matching::ThreadPoolCache::default(),
         }
     }
 
@@ -33,20 +103,161 @@ impl BinaryDiffEngine {
         Self {
             similarity_threshold: similarity,
             confidence_threshold: confidence,
+            calibration: None,
+            config: DiffConfig::default(),
+            progress_callback: None,
+            include_filter: None,
+            exclude_filter: None,
+            extracted_functions: FxHashMap::default(),
+            thread_pool: matching::ThreadPoolCache::default(),
         }
     }
 
+    /// Skip functions with fewer than `count` basic blocks; see
+    /// `DiffConfig::min_basic_blocks`.
+    pub fn with_min_basic_blocks(mut self, count: usize) -> Self {
+        self.config.min_basic_blocks = count;
+        self
+    }
+
+    /// Skip functions with fewer than `count` instructions; see
+    /// `DiffConfig::min_instruction_count`.
+    pub fn with_min_instructions(mut self, count: usize) -> Self {
+        self.config.min_instruction_count = count;
+        self
+    }
+
+    /// Skip functions smaller than `size` bytes; see
+    /// `DiffConfig::min_function_size`.
+    pub fn with_min_size(mut self, size: u64) -> Self {
+        self.config.min_function_size = size;
+        self
+    }
+
+    /// Pin the function at `address_a` in binary A to the one at
+    /// `address_b` in binary B for every later diff on this engine. The pair
+    /// is reported as a `Manual` match with confidence 1.0 whatever its
+    /// similarity, and the automatic passes won't rematch either function.
+    /// Any earlier pin involving either address is replaced.
+    pub fn add_manual_match(&mut self, address_a: u64, address_b: u64) {
+        self.config.manual_matches.retain(|&(a, b)| a != address_a && b != address_b);
+        self.config.manual_matches.push((address_a, address_b));
+    }
+
+    /// Restrict later diffs on this engine to functions whose name matches
+    /// `include` (if set) and doesn't match `exclude` (if set). Filtered-out
+    /// functions are dropped before matching, so they appear neither in the
+    /// matches nor in the unmatched or skipped lists.
+    pub fn set_function_filter(&mut self, include: Option<Regex>, exclude: Option<Regex>) {
+        self.include_filter = include;
+        self.exclude_filter = exclude;
+    }
+
     pub fn extract_function_info_mock(&self, binary_name: &str) -> Result<Vec<FunctionInfo>> {
-        mock::generate_mock_functions(binary_name)
+        MockProvider.functions(binary_name)
+    }
+
+    /// Mock-extract `binary_name` into the engine's cache unless it is
+    /// already there.
+    pub(crate) fn cache_function_info_mock(&mut self, binary_name: &str) -> Result<()> {
+        if !self.extracted_functions.contains_key(binary_name) {
+            let functions = self.extract_function_info_mock(binary_name)?;
+            self.extracted_functions.insert(binary_name.to_string(), functions);
+        }
+        Ok(())
     }
 
     pub fn perform_diff_mock(&self, binary_a_name: &str, binary_b_name: &str) -> Result<DiffResult> {
+        self.perform_diff_mock_with_observer(binary_a_name, binary_b_name, |_| {})
+    }
+
+    /// Like `perform_diff_mock`, but forwards each match into `sender` as
+    /// soon as it is found. A dropped receiver does not abort the diff.
+    pub fn perform_diff_mock_with_sender(
+        &self,
+        binary_a_name: &str,
+        binary_b_name: &str,
+        sender: mpsc::Sender<FunctionMatch>,
+    ) -> Result<DiffResult> {
+        self.perform_diff_mock_with_observer(binary_a_name, binary_b_name, |m| {
+            let _ = sender.send(m.clone());
+        })
+    }
+
+    /// Like `perform_diff_mock`, but invokes `on_match` for every match as
+    /// soon as the pass that found it completes, for live-updating UIs. The
+    /// observed matches are exactly those in the returned `DiffResult`.
+    pub fn perform_diff_mock_with_observer(
+        &self,
+        binary_a_name: &str,
+        binary_b_name: &str,
+        on_match: impl FnMut(&FunctionMatch),
+    ) -> Result<DiffResult> {
+        self.perform_diff_cancellable(&MockProvider, binary_a_name, binary_b_name, on_match, |_, _| {}, None)
+    }
+
+    /// Like `perform_diff_mock`, but calls `callback` with
+    /// `(functions_processed, total_functions)` of binary A after every
+    /// matching pass, ending with `(total, total)`. The callback runs on the
+    /// calling thread between passes, never inside a parallel region.
+    pub fn perform_diff_with_progress(
+        &self,
+        binary_a_name: &str,
+        binary_b_name: &str,
+        callback: impl Fn(usize, usize),
+    ) -> Result<DiffResult> {
+        self.perform_diff_cancellable(&MockProvider, binary_a_name, binary_b_name, |_| {}, callback, None)
+    }
+
+    /// Diff two binaries whose functions come from `provider`.
+    pub fn perform_diff_with<P: FunctionProvider>(&self, provider: &P, binary_a_name: &str, binary_b_name: &str) -> Result<DiffResult> {
+        self.perform_diff_cancellable(provider, binary_a_name, binary_b_name, |_| {}, |_, _| {}, None)
+    }
+
+    /// Diff the functions `provider` returns for both binaries, with an
+    /// optional cancellation flag checked before every matching pass; once
+    /// set, the diff fails with a cancellation error.
+    fn perform_diff_cancellable(
+        &self,
+        provider: &impl FunctionProvider,
+        binary_a_name: &str,
+        binary_b_name: &str,
+        mut on_match: impl FnMut(&FunctionMatch),
+        on_progress: impl FnMut(usize, usize),
+        cancel: Option<Arc<AtomicBool>>,
+    ) -> Result<DiffResult> {
         let start_time = Instant::now();
 
         info!("Starting binary diff analysis");
 
-        let functions_a = self.extract_function_info_mock(binary_a_name)?;
-        let functions_b = self.extract_function_info_mock(binary_b_name)?;
+        let mut functions_a = provider.functions(binary_a_name)?;
+        let mut functions_b = provider.functions(binary_b_name)?;
+
+        let provenance = Provenance {
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            config: self.config.clone(),
+            input_hash_a: DiffAlgorithms::calculate_input_hash(&functions_a),
+            input_hash_b: DiffAlgorithms::calculate_input_hash(&functions_b),
+        };
+
+        let mut warnings = Vec::new();
+        for (functions, binary_name) in [(&mut functions_a, binary_a_name), (&mut functions_b, binary_b_name)] {
+            if self.apply_function_filter(functions) && functions.is_empty() {
+                warnings.push(format!("Function filter removed every function of {}", binary_name));
+            }
+        }
+
+        if self.config.reconstruct_missing_edges {
+            Self::reconstruct_missing_edges(&mut functions_a, &mut functions_b);
+        }
+
+        warnings.extend(Self::input_warnings(&functions_a, &functions_b));
+        if self.config.exclude_unreachable_blocks {
+            Self::exclude_unreachable_blocks(&mut functions_a, &mut functions_b);
+        }
+        if self.enforce_memory_budget(&mut functions_a, &mut functions_b) {
+            warnings.push("Estimated working set exceeds the memory budget; instruction bytes were dropped".to_string());
+        }
 
         info!(
             "Extracted {} functions from binary A, {} from binary B",
@@ -57,21 +268,49 @@ impl BinaryDiffEngine {
         let engine = matching::MatchingEngine::with_thresholds(
             self.confidence_threshold,
             self.similarity_threshold,
-        );
-        let matches = engine.match_functions(&functions_a, &functions_b)?;
+        )
+        .with_config(self.config.clone())
+        .with_cancel_flag(cancel)
+        .with_thread_pool(self.thread_pool.clone());
+        let outcome = engine.match_functions_full_with_progress(
+            &functions_a,
+            &functions_b,
+            |m| match &self.calibration {
+                Some(calibrator) if !m.match_type.has_fixed_confidence() => {
+                    let mut calibrated = m.clone();
+                    calibrated.confidence = calibrator.calibrate(m.confidence);
+                    on_match(&calibrated);
+                }
+                _ => on_match(m),
+            },
+            on_progress,
+        )?;
+        let mut matches = outcome.matches;
+        warnings.extend(outcome.warnings);
 
-        let matched_a: HashSet<u64> = matches.iter().map(|m| m.function_a.address).collect();
-        let matched_b: HashSet<u64> = matches.iter().map(|m| m.function_b.address).collect();
+        if let Some(calibrator) = &self.calibration {
+            calibrator.apply(&mut matches);
+        }
 
-        let unmatched_a: Vec<FunctionInfo> = functions_a
-            .into_iter()
-            .filter(|f| !matched_a.contains(&f.address))
-            .collect();
+        DiffAlgorithms::assign_layout_positions(&mut matches, &functions_a, &functions_b);
+        let relocated_functions = DiffAlgorithms::find_relocated_functions(&matches, self.config.relocation_threshold);
+        let callee_changes = Self::diff_call_graphs(&functions_a, &functions_b, &matches);
 
-        let unmatched_b: Vec<FunctionInfo> = functions_b
-            .into_iter()
-            .filter(|f| !matched_b.contains(&f.address))
-            .collect();
+        // Unmatched and skipped indices are in ascending input order
+        let (unmatched_a, skipped_a) = split_by_indices(functions_a, &outcome.unmatched_a, &outcome.skipped_a);
+        let (unmatched_b, skipped_b) = split_by_indices(functions_b, &outcome.unmatched_b, &outcome.skipped_b);
+
+        let split_merges = if self.config.detect_split_merges {
+            engine.install(|| matching::MatchingEngine::detect_split_merge(&unmatched_a, &unmatched_b))?
+        } else {
+            Vec::new()
+        };
+
+        let near_misses = if self.config.collect_near_misses {
+            engine.install(|| matching::MatchingEngine::find_near_misses(&unmatched_a, &unmatched_b))?
+        } else {
+            Vec::new()
+        };
 
         let similarity_score = if !matches.is_empty() {
             matches.iter().map(|m| m.similarity).sum::<f64>() / matches.len() as f64
@@ -79,6 +318,10 @@ impl BinaryDiffEngine {
             0.0
         };
 
+        if self.config.strict && !warnings.is_empty() {
+            bail!("Strict mode: diff produced {} warning(s):\n  {}", warnings.len(), warnings.join("\n  "));
+        }
+
         let analysis_time = start_time.elapsed().as_secs_f64();
 
         info!(
@@ -96,9 +339,252 @@ impl BinaryDiffEngine {
             analysis_time,
             binary_a_name: binary_a_name.to_string(),
             binary_b_name: binary_b_name.to_string(),
+            near_misses,
+            skipped_functions_a: skipped_a,
+            skipped_functions_b: skipped_b,
+            warnings,
+            relocated_functions,
+            callee_changes,
+            split_merges,
+            provenance,
         })
     }
 
+    /// Build the call graph of both binaries and report the matched functions
+    /// whose callees changed, with callees in A mapped to B through `matches`.
+    pub fn diff_call_graphs(
+        functions_a: &[FunctionInfo],
+        functions_b: &[FunctionInfo],
+        matches: &[FunctionMatch],
+    ) -> Vec<CalleeChange> {
+        CallGraph::build(functions_a).diff(&CallGraph::build(functions_b), matches)
+    }
+
+    /// Find `target` among `candidates` without a full diff: every candidate
+    /// is scored against the target in parallel, as by
+    /// `MatchingEngine::match_single_function`, and the `top_k` best are
+    /// returned by descending similarity, then confidence, then address.
+    /// Thresholds are not applied, so a weak best guess is still returned.
+    pub fn locate_function(&self, target: &FunctionInfo, candidates: &[FunctionInfo], top_k: usize) -> Result<Vec<FunctionMatch>> {
+        let engine = matching::MatchingEngine::with_thresholds(self.confidence_threshold, self.similarity_threshold)
+            .with_config(self.config.clone())
+            .with_thread_pool(self.thread_pool.clone());
+
+        let mut ranked: Vec<FunctionMatch> = engine.install(|| {
+            candidates
+                .par_iter()
+                .map(|candidate| engine.match_single_function(target, candidate))
+                .collect()
+        })?;
+        ranked.sort_by(|a, b| {
+            b.similarity
+                .total_cmp(&a.similarity)
+                .then_with(|| b.confidence.total_cmp(&a.confidence))
+                .then_with(|| a.function_b.address.cmp(&b.function_b.address))
+        });
+        ranked.truncate(top_k);
+        Ok(ranked)
+    }
+
+    /// Whole-binary fuzzy hash for quick triage before a full diff: a
+    /// bottom-k sketch of the set of per-function fingerprints (MD-Index
+    /// plus size, block, instruction and complexity counts), encoded as
+    /// `bfh1:<k>:<hex>:<hex>...`. Compare two hashes with
+    /// [`Self::binary_fuzzy_similarity`].
+    pub fn binary_fuzzy_hash(functions: &[FunctionInfo]) -> String {
+        let mut fingerprints: Vec<u64> = functions
+            .iter()
+            .map(|f| {
+                let mut hasher = Sha256::new();
+                hasher.update(DiffAlgorithms::calculate_md_index_value(f).to_bits().to_le_bytes());
+                for count in [f.size, f.basic_blocks.len() as u64, f.instructions.len() as u64, f.cyclomatic_complexity as u64] {
+                    hasher.update(count.to_le_bytes());
+                }
+                let digest = hasher.finalize();
+                u64::from_be_bytes(digest[..8].try_into().expect("SHA-256 digest is 32 bytes"))
+            })
+            .collect();
+        fingerprints.sort_unstable();
+        fingerprints.dedup();
+        fingerprints.truncate(BINARY_FUZZY_HASH_SKETCH_SIZE);
+
+        let mut hash = format!("bfh1:{}", BINARY_FUZZY_HASH_SKETCH_SIZE);
+        for fingerprint in fingerprints {
+            hash.push_str(&format!(":{:016x}", fingerprint));
+        }
+        hash
+    }
+
+    /// Estimated Jaccard similarity (0.0 to 1.0) of the function sets behind
+    /// two [`Self::binary_fuzzy_hash`] values. Malformed hashes score 0.0.
+    pub fn binary_fuzzy_similarity(hash_a: &str, hash_b: &str) -> f64 {
+        fn parse(hash: &str) -> Option<(usize, Vec<u64>)> {
+            let mut parts = hash.split(':');
+            if parts.next()? != "bfh1" {
+                return None;
+            }
+            let k = parts.next()?.parse().ok()?;
+            let sketch = parts.map(|p| u64::from_str_radix(p, 16).ok()).collect::<Option<Vec<u64>>>()?;
+            Some((k, sketch))
+        }
+
+        let (Some((k_a, sketch_a)), Some((k_b, sketch_b))) = (parse(hash_a), parse(hash_b)) else {
+            return 0.0;
+        };
+        if sketch_a.is_empty() && sketch_b.is_empty() {
+            return 1.0;
+        }
+
+        // Bottom-k estimator: among the k smallest fingerprints of the union,
+        // the fraction present in both sketches estimates the Jaccard index.
+        let k = k_a.min(k_b);
+        let mut union: Vec<u64> = sketch_a.iter().chain(sketch_b.iter()).copied().collect();
+        union.sort_unstable();
+        union.dedup();
+        union.truncate(k);
+
+        let shared = union
+            .iter()
+            .filter(|fp| sketch_a.binary_search(fp).is_ok() && sketch_b.binary_search(fp).is_ok())
+            .count();
+        shared as f64 / union.len() as f64
+    }
+
+    /// Estimate the working set of a diff over the given functions. Every
+    /// function is held once as input and cloned once more into either a
+    /// match or an unmatched list.
+    pub fn estimate_working_set(functions_a: &[FunctionInfo], functions_b: &[FunctionInfo]) -> usize {
+        functions_a
+            .iter()
+            .chain(functions_b.iter())
+            .map(|f| f.estimated_memory_footprint())
+            .fold(0usize, |acc, n| acc.saturating_add(n))
+            .saturating_mul(2)
+    }
+
+    /// Check the extracted functions for problems that would silently degrade
+    /// the diff: an empty side, identical functions that make matching
+    /// ambiguous, CFG edges pointing at no block of the function, or blocks
+    /// unreachable from the entry block. Each problem is logged and returned as a warning.
+    fn input_warnings(functions_a: &[FunctionInfo], functions_b: &[FunctionInfo]) -> Vec<String> {
+        let mut warnings = Vec::new();
+
+        for (label, functions) in [("A", functions_a), ("B", functions_b)] {
+            if functions.is_empty() {
+                warnings.push(format!("Binary {} has no functions", label));
+            }
+
+            let duplicates = DiffAlgorithms::find_internal_duplicates(functions);
+            if !duplicates.is_empty() {
+                let cloned: usize = duplicates.iter().map(Vec::len).sum();
+                warnings.push(format!(
+                    "Binary {} has {} groups of identical functions ({} functions); matches among them may be ambiguous",
+                    label,
+                    duplicates.len(),
+                    cloned
+                ));
+            }
+
+            for func in functions {
+                let block_addrs: HashSet<u64> = func.basic_blocks.iter().map(|bb| bb.address).collect();
+                let dangling = func
+                    .basic_blocks
+                    .iter()
+                    .flat_map(|bb| &bb.edges)
+                    .filter(|target| !block_addrs.contains(target))
+                    .count();
+                if dangling > 0 {
+                    warnings.push(format!(
+                        "Function {} (0x{:x}) in binary {} has {} dangling CFG edge(s)",
+                        func.name, func.address, label, dangling
+                    ));
+                }
+
+                let unreachable = func.unreachable_blocks();
+                if !unreachable.is_empty() {
+                    warnings.push(format!(
+                        "Function {} (0x{:x}) in binary {} has {} unreachable basic block(s)",
+                        func.name, func.address, label, unreachable.len()
+                    ));
+                }
+            }
+        }
+
+        for warning in &warnings {
+            warn!("{}", warning);
+        }
+        warnings
+    }
+
+    /// Drop the functions rejected by the name filter. Returns whether a
+    /// filter is set.
+    fn apply_function_filter(&self, functions: &mut Vec<FunctionInfo>) -> bool {
+        if self.include_filter.is_none() && self.exclude_filter.is_none() {
+            return false;
+        }
+        functions.retain(|func| {
+            self.include_filter.as_ref().is_none_or(|include| include.is_match(&func.name))
+                && !self.exclude_filter.as_ref().is_some_and(|exclude| exclude.is_match(&func.name))
+        });
+        true
+    }
+
+    /// Rebuild missing CFG edges for imported functions that have branch
+    /// instructions but no block edges.
+    fn reconstruct_missing_edges(functions_a: &mut [FunctionInfo], functions_b: &mut [FunctionInfo]) {
+        let rebuilt = functions_a
+            .iter_mut()
+            .chain(functions_b.iter_mut())
+            .map(|func| func.reconstruct_missing_edges())
+            .filter(|&rebuilt| rebuilt)
+            .count();
+        if rebuilt > 0 {
+            info!("Reconstructed CFG edges for {} functions imported without edges", rebuilt);
+        }
+    }
+
+    /// Remove basic blocks unreachable from the entry block.
+    fn exclude_unreachable_blocks(functions_a: &mut [FunctionInfo], functions_b: &mut [FunctionInfo]) {
+        let removed: usize = functions_a
+            .iter_mut()
+            .chain(functions_b.iter_mut())
+            .map(|func| func.remove_unreachable_blocks())
+            .sum();
+        if removed > 0 {
+            info!("Excluded {} unreachable basic blocks from matching", removed);
+        }
+    }
+
+    /// Drop instruction bytes from every function when the estimated working
+    /// set exceeds the configured budget. Only the bytes go; names, mnemonics
+    /// and operands are kept, and matches still hold their own copies of
+    /// both functions. Returns true if bytes were dropped.
+    fn enforce_memory_budget(&self, functions_a: &mut [FunctionInfo], functions_b: &mut [FunctionInfo]) -> bool {
+        let budget = match self.config.memory_budget_bytes {
+            Some(budget) => budget,
+            None => return false,
+        };
+
+        let estimate = Self::estimate_working_set(functions_a, functions_b);
+        if estimate <= budget {
+            return false;
+        }
+
+        warn!(
+            "Estimated working set of {} bytes exceeds memory budget of {} bytes; \
+             dropping instruction bytes from {} functions",
+            estimate,
+            budget,
+            functions_a.len() + functions_b.len()
+        );
+
+        for func in functions_a.iter_mut().chain(functions_b.iter_mut()) {
+            func.strip_instruction_bytes();
+        }
+
+        true
+    }
+
     pub fn save_results(&self, diff_result: &DiffResult, output_path: &str) -> Result<()> {
         let json_data = serde_json::to_string_pretty(diff_result)
             .context("Failed to serialize diff results")?;
@@ -110,3 +596,358 @@ impl BinaryDiffEngine {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn engine_and_matching_paths_score_pairs_identically() {
+        let functions_a = mock::generate_mock_functions("binary_a").unwrap();
+        let functions_b = mock::generate_mock_functions("binary_b").unwrap();
+
+        let matches = matching::MatchingEngine::new().match_functions(&functions_a, &functions_b).unwrap();
+        let result = BinaryDiffEngine::new().perform_diff_mock("binary_a", "binary_b").unwrap();
+        assert!(!matches.is_empty());
+        for m in matches.iter().chain(&result.matched_functions) {
+            assert_eq!(m.similarity, DiffAlgorithms::calculate_function_similarity(&m.function_a, &m.function_b));
+        }
+    }
+
+    #[test]
+    fn locate_function_ranks_the_target_first() {
+        let engine = BinaryDiffEngine::new();
+        let functions_a = engine.extract_function_info_mock("binary_a").unwrap();
+        let functions_b = engine.extract_function_info_mock("binary_b").unwrap();
+        let target = &functions_a[2];
+
+        let ranked = engine.locate_function(target, &functions_b, 3).unwrap();
+        assert_eq!(ranked.len(), 3);
+        assert_eq!(ranked[0].function_b.name, target.name);
+        assert!(ranked.windows(2).all(|w| w[0].similarity >= w[1].similarity));
+    }
+
+    #[test]
+    fn single_thread_pool_matches_the_default_pool() {
+        let diff = |num_threads: Option<usize>| {
+            let mut engine = BinaryDiffEngine::new();
+            engine.config.num_threads = num_threads;
+            engine.config.collect_near_misses = true;
+            engine.config.detect_split_merges = true;
+            let result = engine.perform_diff_mock("binary_a", "binary_b").unwrap();
+            let matches: Vec<_> = result
+                .matched_functions
+                .iter()
+                .map(|m| (m.function_a.address, m.function_b.address, m.similarity.to_bits(), m.confidence.to_bits()))
+                .collect();
+            let near_misses: Vec<_> = result.near_misses.iter().map(|n| (n.address, n.candidate_address)).collect();
+            (matches, near_misses, result.split_merges.len())
+        };
+        assert_eq!(diff(Some(1)), diff(None));
+
+        let config = DiffConfig { num_threads: Some(1), ..DiffConfig::default() };
+        let engine = matching::MatchingEngine::new().with_config(config);
+        assert_eq!(engine.install(rayon::current_num_threads).unwrap(), 1);
+    }
+
+    #[test]
+    fn memory_budget_drops_instruction_bytes() {
+        let footprint = |result: &DiffResult| -> usize {
+            result
+                .matched_functions
+                .iter()
+                .flat_map(|m| [&m.function_a, &m.function_b])
+                .chain(&result.unmatched_functions_a)
+                .chain(&result.unmatched_functions_b)
+                .map(FunctionInfo::estimated_memory_footprint)
+                .sum()
+        };
+        let mut engine = BinaryDiffEngine::new();
+        engine.config.memory_budget_bytes = Some(1);
+        let reduced = engine.perform_diff_mock("binary_a", "binary_b").unwrap();
+        assert!(!reduced.matched_functions.is_empty());
+        assert!(reduced.matched_functions.iter().all(|m| m.function_a.instructions.iter().all(|i| i.bytes.is_empty())));
+        assert!(reduced.warnings.iter().any(|w| w.contains("memory budget")));
+
+        let full = BinaryDiffEngine::new().perform_diff_mock("binary_a", "binary_b").unwrap();
+        assert!(!full.matched_functions[0].function_a.instructions[0].bytes.is_empty());
+        assert_eq!(reduced.matched_functions.len(), full.matched_functions.len());
+        assert!(footprint(&reduced) < footprint(&full), "{} {}", footprint(&reduced), footprint(&full));
+    }
+
+    #[test]
+    fn observers_see_every_match_in_result_order() {
+        let engine = BinaryDiffEngine::new();
+        let mut seen = Vec::new();
+        let result = engine
+            .perform_diff_mock_with_observer("binary_a", "binary_b", |m| seen.push((m.function_a.address, m.function_b.address)))
+            .unwrap();
+        let expected: Vec<_> = result.matched_functions.iter().map(|m| (m.function_a.address, m.function_b.address)).collect();
+        assert_eq!(seen, expected);
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let result = engine.perform_diff_mock_with_sender("binary_a", "binary_b", sender).unwrap();
+        assert_eq!(receiver.iter().count(), result.matched_functions.len());
+    }
+
+    #[test]
+    fn binary_fuzzy_hash_tracks_function_set_overlap() {
+        let binary = |sizes: std::ops::Range<u64>| -> Vec<FunctionInfo> {
+            sizes.map(|size| FunctionInfo { size, ..Default::default() }).collect()
+        };
+        let original = binary(0..300);
+        let mut patched = original.clone();
+        for function in patched.iter_mut().step_by(20) {
+            function.size += 1_000;
+        }
+        let unrelated = binary(5_000..5_300);
+
+        let hash = BinaryDiffEngine::binary_fuzzy_hash(&original);
+        assert_eq!(BinaryDiffEngine::binary_fuzzy_similarity(&hash, &hash), 1.0);
+        assert!(BinaryDiffEngine::binary_fuzzy_similarity(&hash, &BinaryDiffEngine::binary_fuzzy_hash(&patched)) > 0.8);
+        assert!(BinaryDiffEngine::binary_fuzzy_similarity(&hash, &BinaryDiffEngine::binary_fuzzy_hash(&unrelated)) < 0.3);
+        assert_eq!(BinaryDiffEngine::binary_fuzzy_similarity(&hash, "not a hash"), 0.0);
+    }
+
+    #[test]
+    fn size_filters_report_functions_as_skipped() {
+        let mut engine = BinaryDiffEngine::new();
+        let baseline = engine.perform_diff_mock("binary_a", "binary_b").unwrap();
+        assert!(baseline.skipped_functions_a.is_empty());
+
+        engine.config.min_instruction_count = 1_000_000;
+        let result = engine.perform_diff_mock("binary_a", "binary_b").unwrap();
+        assert!(result.matched_functions.is_empty());
+        assert!(result.unmatched_functions_a.is_empty());
+        assert_eq!(
+            result.skipped_functions_a.len(),
+            baseline.matched_functions.len() + baseline.unmatched_functions_a.len()
+        );
+    }
+
+    #[test]
+    fn strict_mode_turns_warnings_into_errors() {
+        let mut engine = BinaryDiffEngine::new();
+        engine.config.memory_budget_bytes = Some(1);
+        assert!(!engine.perform_diff_mock("binary_a", "binary_b").unwrap().warnings.is_empty());
+
+        engine.config.strict = true;
+        let error = engine.perform_diff_mock("binary_a", "binary_b").unwrap_err();
+        assert!(error.to_string().contains("memory budget"));
+    }
+
+    #[test]
+    fn coverage_counts_functions_and_instructions() {
+        let result = BinaryDiffEngine::with_thresholds(0.99, 0.99).perform_diff_mock("binary_a", "binary_b").unwrap();
+        let coverage = result.coverage();
+
+        let total_a = result.matched_functions.len() + result.unmatched_functions_a.len();
+        assert_eq!(coverage.functions_a, result.matched_functions.len() as f64 / total_a as f64);
+        let matched_instructions: usize = result.matched_functions.iter().map(|m| m.function_a.instructions.len()).sum();
+        let unmatched_instructions: usize = result.unmatched_functions_a.iter().map(|f| f.instructions.len()).sum();
+        assert_eq!(coverage.instructions_a, matched_instructions as f64 / (matched_instructions + unmatched_instructions) as f64);
+        assert!(crate::ui::DiffUI::generate_text_report(&result).contains("Coverage A"));
+    }
+
+    #[test]
+    fn manual_matches_are_pinned_and_replace_earlier_pins() {
+        let mut engine = BinaryDiffEngine::new();
+        let functions_a = engine.extract_function_info_mock("binary_a").unwrap();
+        let functions_b = engine.extract_function_info_mock("binary_b").unwrap();
+        let (address_a, address_b) = (functions_a[0].address, functions_b[functions_b.len() - 1].address);
+        engine.add_manual_match(address_a, 0xdead);
+        engine.add_manual_match(address_a, address_b);
+        assert_eq!(engine.config.manual_matches, vec![(address_a, address_b)]);
+
+        let result = engine.perform_diff_mock("binary_a", "binary_b").unwrap();
+        let pinned: Vec<&FunctionMatch> = result
+            .matched_functions
+            .iter()
+            .filter(|m| m.function_a.address == address_a || m.function_b.address == address_b)
+            .collect();
+        assert_eq!(pinned.len(), 1);
+        assert_eq!(pinned[0].match_type, MatchType::Manual);
+        assert_eq!(pinned[0].confidence, 1.0);
+        assert_eq!(pinned[0].function_b.address, address_b);
+
+        // Manual matches aren't a pass that can be scheduled
+        let mut config = DiffConfig::default();
+        config.pass_order.push(MatchPass::Manual);
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn name_filters_drop_functions_before_matching() {
+        let total = |r: &DiffResult| {
+            r.matched_functions.len() * 2
+                + r.unmatched_functions_a.len()
+                + r.unmatched_functions_b.len()
+                + r.skipped_functions_a.len()
+                + r.skipped_functions_b.len()
+        };
+        let mut engine = BinaryDiffEngine::new();
+        let unfiltered = engine.perform_diff_mock("binary_a", "binary_b").unwrap();
+
+        engine.set_function_filter(Some(Regex::new("^main$").unwrap()), None);
+        let included = engine.perform_diff_mock("binary_a", "binary_b").unwrap();
+        assert!(!included.matched_functions.is_empty());
+        assert!(included.matched_functions.iter().all(|m| m.function_a.name == "main"));
+        assert!(total(&included) < total(&unfiltered));
+
+        engine.set_function_filter(None, Some(Regex::new("^main$").unwrap()));
+        let excluded = engine.perform_diff_mock("binary_a", "binary_b").unwrap();
+        assert!(excluded.matched_functions.iter().all(|m| m.function_a.name != "main" && m.function_b.name != "main"));
+        assert!(excluded.unmatched_functions_a.iter().all(|f| f.name != "main"));
+
+        // Both together keep what the include matches minus what the exclude matches
+        engine.set_function_filter(Some(Regex::new("^(main|process_)").unwrap()), Some(Regex::new("^main$").unwrap()));
+        let combined = engine.perform_diff_mock("binary_a", "binary_b").unwrap();
+        let names: Vec<&str> = combined
+            .matched_functions
+            .iter()
+            .flat_map(|m| [m.function_a.name.as_str(), m.function_b.name.as_str()])
+            .chain(combined.unmatched_functions_a.iter().chain(&combined.unmatched_functions_b).map(|f| f.name.as_str()))
+            .collect();
+        assert!(!names.is_empty());
+        assert!(names.iter().all(|&name| name == "process_data"), "{:?}", names);
+
+        // Filtering out everything leaves nothing to diff, with a warning
+        engine.set_function_filter(Some(Regex::new(".").unwrap()), Some(Regex::new(".").unwrap()));
+        let empty = engine.perform_diff_mock("binary_a", "binary_b").unwrap();
+        assert_eq!(total(&empty), 0);
+        assert!(empty.warnings.iter().any(|w| w.contains("filter")));
+    }
+
+    #[test]
+    fn functions_below_the_block_minimum_are_skipped() {
+        let instructions: Vec<InstructionInfo> = (0..3)
+            .map(|i| InstructionInfo { address: 0x1000 + i, mnemonic: "nop".to_string(), length: 1, bytes: vec![0x90], ..Default::default() })
+            .collect();
+        let tiny = FunctionInfo {
+            name: "tiny".to_string(),
+            address: 0x1000,
+            size: 3,
+            instruction_count: 3,
+            basic_blocks: vec![BasicBlockInfo {
+                address: 0x1000,
+                size: 3,
+                instructions: instructions.clone(),
+                instruction_count: 3,
+                ..Default::default()
+            }],
+            instructions,
+            ..Default::default()
+        };
+
+        let engine = BinaryDiffEngine::new().with_min_basic_blocks(2).with_min_instructions(0).with_min_size(0);
+        let outcome = matching::MatchingEngine::new()
+            .with_config(engine.config.clone())
+            .match_functions_full(std::slice::from_ref(&tiny), std::slice::from_ref(&tiny))
+            .unwrap();
+        assert!(outcome.matches.is_empty());
+        assert_eq!(outcome.skipped_a, vec![0]);
+        assert_eq!(outcome.skipped_b, vec![0]);
+
+        let result = engine.perform_diff_mock("binary_a", "binary_b").unwrap();
+        assert!(!result.skipped_functions_a.is_empty());
+        assert!(result.skipped_functions_a.iter().all(|f| f.basic_blocks.len() < 2));
+        assert!(ui::DiffUI::generate_text_report(&result).contains("Skipped"));
+    }
+
+    #[test]
+    fn call_graph_diff_reports_added_callees() {
+        let function = |name: &str, address: u64, callees: &[&str]| {
+            let mut instructions: Vec<InstructionInfo> = ["push", "mov", "add", "sub", "xor"]
+                .iter()
+                .enumerate()
+                .map(|(i, m)| InstructionInfo {
+                    address: address + i as u64,
+                    mnemonic: m.to_string(),
+                    operands: vec!["eax".to_string()],
+                    length: 1,
+                    bytes: vec![0x90],
+                })
+                .collect();
+            instructions.extend(callees.iter().map(|callee| InstructionInfo {
+                address: address + 9,
+                mnemonic: "call".to_string(),
+                operands: vec![callee.to_string()],
+                length: 5,
+                bytes: vec![0xe8, 0, 0, 0, 0],
+            }));
+            FunctionInfo {
+                name: name.to_string(),
+                address,
+                size: 0x20,
+                instruction_count: instructions.len(),
+                instructions,
+                ..Default::default()
+            }
+        };
+        // A's main calls work by address, B's by name
+        let functions_a = vec![function("main", 0x1000, &["puts", "0x2000"]), function("work", 0x2000, &[])];
+        let functions_b = vec![
+            function("main", 0x5000, &["puts", "work", "helper"]),
+            function("work", 0x6000, &[]),
+            function("helper", 0x7000, &[]),
+        ];
+        let matches: Vec<FunctionMatch> = functions_a
+            .iter()
+            .zip(&functions_b)
+            .map(|(a, b)| FunctionMatch { function_a: a.clone(), function_b: b.clone(), ..Default::default() })
+            .collect();
+
+        let changes = BinaryDiffEngine::diff_call_graphs(&functions_a, &functions_b, &matches);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].added, vec!["helper".to_string()]);
+        assert!(changes[0].removed.is_empty());
+        assert_eq!(CallGraph::build(&functions_b).edge_count(), 3);
+    }
+
+    #[test]
+    fn split_detection_is_opt_in_and_ignores_addresses() {
+        // Every function at address 0, as in dumps without addresses
+        struct Dump;
+        impl FunctionProvider for Dump {
+            fn functions(&self, binary_name: &str) -> Result<Vec<FunctionInfo>> {
+                let function = |name: &str, mnemonics: &[&str]| {
+                    let instructions: Vec<InstructionInfo> = mnemonics
+                        .iter()
+                        .enumerate()
+                        .map(|(i, m)| InstructionInfo {
+                            mnemonic: m.to_string(),
+                            bytes: vec![i as u8],
+                            length: 1,
+                            ..Default::default()
+                        })
+                        .collect();
+                    FunctionInfo {
+                        name: name.to_string(),
+                        size: instructions.len() as u64,
+                        instruction_count: instructions.len(),
+                        instructions,
+                        cfg_hash: mnemonics.join(" "),
+                        ..Default::default()
+                    }
+                };
+                let first_half = ["push", "mov", "sub", "lea", "call", "test", "jz", "xor"];
+                let second_half = ["imul", "shl", "sar", "and", "or", "cmp", "jne", "ret"];
+                let shared = function("shared", &["push", "mov", "pop", "ret"]);
+                Ok(match binary_name {
+                    "a" => vec![shared, function("foo", &[first_half, second_half].concat())],
+                    _ => vec![shared, function("foo_part1", &first_half), function("foo_part2", &second_half)],
+                })
+            }
+        }
+
+        // Strict enough that foo matches neither half on its own
+        let mut engine = BinaryDiffEngine::with_thresholds(0.9, 0.9);
+        assert!(engine.perform_diff_with(&Dump, "a", "b").unwrap().split_merges.is_empty());
+
+        engine.config.detect_split_merges = true;
+        let result = engine.perform_diff_with(&Dump, "a", "b").unwrap();
+        assert_eq!(result.matched_functions.len(), 1);
+        assert_eq!(result.split_merges.len(), 1, "{:?}", result.split_merges);
+        assert_eq!(result.split_merges[0].name, "foo");
+    }
+}
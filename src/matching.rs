@@ -1,9 +1,34 @@
-use crate::types::{FunctionInfo, FunctionMatch, MatchType, MatchDetails};
-use crate::algorithms::DiffAlgorithms;
-use crate::similarity::SimilarityAnalyzer;
-use anyhow::Result;
+use crate::types::{AuditEntry, BlockMatch, EvalReport, FunctionInfo, FunctionMatch, MatchAudit, MatchDetails, MatchOutcome, MatchPass, MatchType, NearMiss, SplitMerge, SplitMergeKind, StructuralFingerprint};
+use crate::algorithms::{DiffAlgorithms, MnemonicPrimes};
+use crate::similarity::{ReferenceIndex, SimilarityAnalyzer};
+use crate::config::{DiffConfig, ThunkHandling};
+use anyhow::{Context, Result, bail};
 use rustc_hash::{FxHashMap, FxHashSet};
 use rayon::prelude::*;
+use log::{info, warn};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
+/// Similarity lead over the runner-up candidate above which
+/// `recalibrate_confidence` treats a match as a clear winner.
+const CLEAR_WINNER_GAP: f64 = 0.2;
+
+/// Minimum share of the single function's mnemonics the two parts must
+/// cover, and of the parts' mnemonics found in the single function, for a
+/// split or merge.
+const SPLIT_MERGE_MIN_COVERAGE: f64 = 0.8;
+
+/// Smallest function considered as the single side of a split or merge;
+/// parts need half as many instructions.
+const SPLIT_MERGE_MIN_INSTRUCTIONS: usize = 8;
+
+/// Most part candidates paired up per function when looking for a split.
+const SPLIT_MERGE_MAX_PARTS: usize = 16;
+
+/// Confidence multiplier for matches found A→B that the B→A run left
+/// unmatched, in `match_functions_symmetric`.
+const ASYMMETRIC_CONFIDENCE_FACTOR: f64 = 0.5;
 
 /// Deterministic tie-breaker: higher confidence wins; then higher similarity;
 /// then lower index_b (stable for identical scores).
@@ -36,9 +61,75 @@ fn is_auto_generated_name(name: &str) -> bool {
         || n.is_empty()
 }
 
+/// Mutable state threaded through the matching passes.
+struct MatchState {
+    matches: Vec<FunctionMatch>,
+    used_a: FxHashSet<usize>,
+    used_b: FxHashSet<usize>,
+    /// (pass, index_a, index_b) for each entry of `matches`, in order.
+    claims: Vec<(MatchPass, usize, usize)>,
+    current_pass: MatchPass,
+    /// Degradations noticed while matching, surfaced to callers.
+    warnings: Vec<String>,
+}
+
+impl MatchState {
+    fn new(used_a: FxHashSet<usize>, used_b: FxHashSet<usize>) -> Self {
+        Self {
+            matches: Vec::new(),
+            used_a,
+            used_b,
+            claims: Vec::new(),
+            current_pass: MatchPass::ExactHash,
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Record a match and mark both functions as used.
+    fn claim(&mut self, idx_a: usize, idx_b: usize, function_match: FunctionMatch) {
+        self.matches.push(function_match);
+        self.used_a.insert(idx_a);
+        self.used_b.insert(idx_b);
+        self.claims.push((self.current_pass, idx_a, idx_b));
+    }
+}
+
+/// Dedicated thread pool for `DiffConfig::num_threads`, built on first use
+/// and shared by every engine handed a clone of it.
+#[derive(Clone, Default)]
+pub(crate) struct ThreadPoolCache(Arc<Mutex<Option<Arc<rayon::ThreadPool>>>>);
+
+impl ThreadPoolCache {
+    /// Run `op` on a pool of `num_threads` threads, so its parallel
+    /// iterators use that pool, or directly on rayon's global pool for
+    /// `None`. The pool is only rebuilt when `num_threads` changes.
+    pub(crate) fn install<R: Send>(&self, num_threads: Option<usize>, op: impl FnOnce() -> R + Send) -> Result<R> {
+        let Some(num_threads) = num_threads else {
+            return Ok(op());
+        };
+        let pool = {
+            let mut cached = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+            match cached.as_ref() {
+                Some(pool) if pool.current_num_threads() == num_threads => Arc::clone(pool),
+                _ => {
+                    let pool = rayon::ThreadPoolBuilder::new()
+                        .num_threads(num_threads)
+                        .build()
+                        .context("Failed to build matching thread pool")?;
+                    Arc::clone(cached.insert(Arc::new(pool)))
+                }
+            }
+        };
+        Ok(pool.install(op))
+    }
+}
+
 pub struct MatchingEngine {
     confidence_threshold: f64,
     similarity_threshold: f64,
+    config: DiffConfig,
+    cancel: Option<Arc<AtomicBool>>,
+    thread_pool: ThreadPoolCache,
 }
 
 impl MatchingEngine {
@@ -46,6 +137,9 @@ impl MatchingEngine {
         Self {
             confidence_threshold: 0.5,
             similarity_threshold: 0.6,
+            config: DiffConfig::default(),
+            cancel: None,
+            thread_pool: ThreadPoolCache::default(),
         }
     }
 
@@ -53,38 +147,606 @@ impl MatchingEngine {
         Self {
             confidence_threshold: confidence,
             similarity_threshold: similarity,
+            config: DiffConfig::default(),
+            cancel: None,
+            thread_pool: ThreadPoolCache::default(),
         }
     }
 
+    pub fn with_config(mut self, config: DiffConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Abort the pipeline with an error before the next pass once `cancel`
+    /// is set.
+    pub fn with_cancel_flag(mut self, cancel: Option<Arc<AtomicBool>>) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Share `thread_pool` instead of building a pool per engine.
+    pub(crate) fn with_thread_pool(mut self, thread_pool: ThreadPoolCache) -> Self {
+        self.thread_pool = thread_pool;
+        self
+    }
+
+    pub(crate) fn config(&self) -> &DiffConfig {
+        &self.config
+    }
+
+    /// Run `op` on the pool sized by `DiffConfig::num_threads`, or on
+    /// rayon's global pool if unset.
+    pub(crate) fn install<R: Send>(&self, op: impl FnOnce() -> R + Send) -> Result<R> {
+        self.thread_pool.install(self.config.num_threads, op)
+    }
+
     /// Primary matching function using multiple heuristics
     pub fn match_functions(
         &self,
         functions_a: &[FunctionInfo],
         functions_b: &[FunctionInfo],
     ) -> Result<Vec<FunctionMatch>> {
-        let mut matches = Vec::new();
-        let mut used_a = FxHashSet::default();
-        let mut used_b = FxHashSet::default();
+        self.match_functions_with_observer(functions_a, functions_b, |_| {})
+    }
 
-        // 1. Exact hash matching (highest confidence)
-        self.exact_hash_matching(functions_a, functions_b, &mut matches, &mut used_a, &mut used_b)?;
+    /// Run the matching pipeline, invoking `on_match` for every match as soon
+    /// as its pass completes. Matches found by the parallel fuzzy pass are
+    /// buffered and delivered from the calling thread once conflicts are
+    /// resolved, so the observer is never called concurrently.
+    pub fn match_functions_with_observer(
+        &self,
+        functions_a: &[FunctionInfo],
+        functions_b: &[FunctionInfo],
+        on_match: impl FnMut(&FunctionMatch),
+    ) -> Result<Vec<FunctionMatch>> {
+        Ok(self.run_pipeline(functions_a, functions_b, on_match, |_, _| {})?.matches)
+    }
 
-        // 2. Name matching (high confidence)
-        self.name_matching(functions_a, functions_b, &mut matches, &mut used_a, &mut used_b)?;
+    /// Match functions by solving the assignment problem over the full
+    /// similarity matrix, maximizing total similarity instead of claiming
+    /// pairs greedily pass by pass. Pairs below either threshold are never
+    /// matched; match types come from `DiffConfig::match_type_thresholds`.
+    /// Builds an |A|×|B| matrix, so it suits small to medium inputs.
+    pub fn match_functions_optimal(
+        &self,
+        functions_a: &[FunctionInfo],
+        functions_b: &[FunctionInfo],
+    ) -> Result<Vec<FunctionMatch>> {
+        self.config.validate()?;
+        let scores = self.similarity_matrix(functions_a, functions_b)?;
 
-        // 3. MD-Index matching (medium confidence)
-        self.md_index_matching(functions_a, functions_b, &mut matches, &mut used_a, &mut used_b)?;
+        let matches = DiffAlgorithms::optimal_assignment(&scores)
+            .into_iter()
+            .enumerate()
+            .filter_map(|(idx_a, idx_b)| idx_b.map(|idx_b| (idx_a, idx_b)))
+            .filter(|&(idx_a, idx_b)| scores[idx_a][idx_b] > 0.0)
+            .map(|(idx_a, idx_b)| self.match_single_function(&functions_a[idx_a], &functions_b[idx_b]))
+            .collect();
 
-        // 4. Small primes product matching (medium confidence)
-        self.small_primes_matching(functions_a, functions_b, &mut matches, &mut used_a, &mut used_b)?;
+        Ok(matches)
+    }
 
-        // 5. Structural matching (lower confidence)
-        self.structural_matching(functions_a, functions_b, &mut matches, &mut used_a, &mut used_b)?;
+    /// Run the pipeline A→B and B→A and reconcile the two. Greedy passes
+    /// consume B in A's order, so the directions can disagree. Pairs found
+    /// both ways are kept unchanged; a pair whose functions are matched to
+    /// different partners in the reverse run is dropped; a pair the reverse
+    /// run left unmatched on both sides is kept with its confidence scaled by
+    /// `ASYMMETRIC_CONFIDENCE_FACTOR` and a note.
+    pub fn match_functions_symmetric(
+        &self,
+        functions_a: &[FunctionInfo],
+        functions_b: &[FunctionInfo],
+    ) -> Result<Vec<FunctionMatch>> {
+        let forward = self.match_functions(functions_a, functions_b)?;
+
+        let mut reverse_config = self.config.clone();
+        for pin in &mut reverse_config.manual_matches {
+            *pin = (pin.1, pin.0);
+        }
+        let reverse_engine = Self {
+            config: reverse_config,
+            cancel: self.cancel.clone(),
+            thread_pool: self.thread_pool.clone(),
+            ..*self
+        };
+        let reverse = reverse_engine.match_functions(functions_b, functions_a)?;
 
-        // 6. Fuzzy matching (lowest confidence)
-        self.fuzzy_matching(functions_a, functions_b, &mut matches, &mut used_a, &mut used_b)?;
+        // Partner addresses chosen by the reverse run, keyed by address.
+        let reverse_of_b: FxHashMap<u64, u64> =
+            reverse.iter().map(|m| (m.function_a.address, m.function_b.address)).collect();
+        let reverse_of_a: FxHashMap<u64, u64> =
+            reverse.iter().map(|m| (m.function_b.address, m.function_a.address)).collect();
 
-        Ok(matches)
+        let mut dropped = 0;
+        let reconciled: Vec<FunctionMatch> = forward
+            .into_iter()
+            .filter_map(|mut m| {
+                let (address_a, address_b) = (m.function_a.address, m.function_b.address);
+                match (reverse_of_b.get(&address_b), reverse_of_a.get(&address_a)) {
+                    (Some(&partner), _) if partner == address_a => Some(m),
+                    (None, None) => {
+                        m.confidence *= ASYMMETRIC_CONFIDENCE_FACTOR;
+                        m.notes.push("Not confirmed by B→A matching".to_string());
+                        Some(m)
+                    }
+                    _ => {
+                        dropped += 1;
+                        None
+                    }
+                }
+            })
+            .collect();
+
+        if dropped > 0 {
+            info!("Symmetric matching dropped {} non-reciprocal matches", dropped);
+        }
+        Ok(reconciled)
+    }
+
+    /// Similarity of every A×B pair, as used by `match_functions_optimal`:
+    /// 0.0 for excluded functions and for pairs below either threshold.
+    pub(crate) fn similarity_matrix(&self, functions_a: &[FunctionInfo], functions_b: &[FunctionInfo]) -> Result<Vec<Vec<f64>>> {
+        let excluded_a = self.excluded_functions(functions_a);
+        let excluded_b = self.excluded_functions(functions_b);
+
+        self.install(|| {
+            functions_a
+                .par_iter()
+                .enumerate()
+                .map(|(idx_a, func_a)| {
+                    functions_b
+                        .iter()
+                        .enumerate()
+                        .map(|(idx_b, func_b)| {
+                            if excluded_a.contains(&idx_a) || excluded_b.contains(&idx_b) {
+                                return 0.0;
+                            }
+                            let similarity = DiffAlgorithms::calculate_function_similarity_weighted(func_a, func_b, &self.config.weights);
+                            let confidence = DiffAlgorithms::calculate_confidence(func_a, func_b, similarity);
+                            if similarity >= self.similarity_threshold && confidence >= self.confidence_threshold {
+                                similarity
+                            } else {
+                                0.0
+                            }
+                        })
+                        .collect()
+                })
+                .collect()
+        })
+    }
+
+    /// A user-pinned match: `Manual` with confidence 1.0, whatever the
+    /// pair's similarity.
+    pub(crate) fn manual_match(&self, func_a: &FunctionInfo, func_b: &FunctionInfo) -> FunctionMatch {
+        let (similarity, details) = DiffAlgorithms::compute_match_details_weighted(func_a, func_b, &self.config.weights);
+        FunctionMatch {
+            function_a: func_a.clone(),
+            function_b: func_b.clone(),
+            similarity,
+            confidence: 1.0,
+            match_type: MatchType::Manual,
+            details,
+            ..Default::default()
+        }
+    }
+
+    /// Run the matching pipeline and return the matches together with the
+    /// indices of functions on each side that were left unmatched. Thunks
+    /// excluded up front are reported as unmatched; functions below the
+    /// minimum size filters are reported as skipped.
+    pub fn match_functions_full(
+        &self,
+        functions_a: &[FunctionInfo],
+        functions_b: &[FunctionInfo],
+    ) -> Result<MatchOutcome> {
+        self.match_functions_full_with_observer(functions_a, functions_b, |_| {})
+    }
+
+    /// [`Self::match_functions_full`] with a per-match observer, see
+    /// [`Self::match_functions_with_observer`].
+    pub fn match_functions_full_with_observer(
+        &self,
+        functions_a: &[FunctionInfo],
+        functions_b: &[FunctionInfo],
+        on_match: impl FnMut(&FunctionMatch),
+    ) -> Result<MatchOutcome> {
+        self.match_functions_full_with_progress(functions_a, functions_b, on_match, |_, _| {})
+    }
+
+    /// [`Self::match_functions_full_with_observer`] that also reports
+    /// progress as `(functions_processed, total_functions)` over binary A
+    /// after every pass, ending with `(total, total)`. A function counts as
+    /// processed once it is matched or excluded. Like `on_match`,
+    /// `on_progress` is only called from the calling thread, between passes.
+    pub fn match_functions_full_with_progress(
+        &self,
+        functions_a: &[FunctionInfo],
+        functions_b: &[FunctionInfo],
+        on_match: impl FnMut(&FunctionMatch),
+        on_progress: impl FnMut(usize, usize),
+    ) -> Result<MatchOutcome> {
+        let state = self.run_pipeline(functions_a, functions_b, on_match, on_progress)?;
+
+        let mut matched_a = vec![false; functions_a.len()];
+        let mut matched_b = vec![false; functions_b.len()];
+        for &(_, index_a, index_b) in &state.claims {
+            matched_a[index_a] = true;
+            matched_b[index_b] = true;
+        }
+        let skipped_a = self.skipped_functions(functions_a);
+        let skipped_b = self.skipped_functions(functions_b);
+        let unmatched = |matched: Vec<bool>, skipped: &FxHashSet<usize>| -> Vec<usize> {
+            (0..matched.len()).filter(|idx| !matched[*idx] && !skipped.contains(idx)).collect()
+        };
+        let sorted = |skipped: &FxHashSet<usize>| -> Vec<usize> {
+            let mut indices: Vec<usize> = skipped.iter().copied().collect();
+            indices.sort_unstable();
+            indices
+        };
+
+        Ok(MatchOutcome {
+            matches: state.matches,
+            unmatched_a: unmatched(matched_a, &skipped_a),
+            unmatched_b: unmatched(matched_b, &skipped_b),
+            skipped_a: sorted(&skipped_a),
+            skipped_b: sorted(&skipped_b),
+            warnings: state.warnings,
+        })
+    }
+
+    /// Score a single pair of functions outside the pipeline, e.g. for an
+    /// interactive "compare these two" query. The match carries the same
+    /// details as pipeline matches; its type comes from
+    /// `DiffConfig::match_type_thresholds`.
+    pub fn match_single_function(&self, func_a: &FunctionInfo, func_b: &FunctionInfo) -> FunctionMatch {
+        let (similarity, details) = DiffAlgorithms::compute_match_details_weighted(func_a, func_b, &self.config.weights);
+        let confidence = DiffAlgorithms::calculate_confidence(func_a, func_b, similarity);
+        let match_type = self.config.match_type_thresholds.classify(similarity, confidence);
+
+        FunctionMatch {
+            function_a: func_a.clone(),
+            function_b: func_b.clone(),
+            similarity,
+            confidence: if match_type.has_fixed_confidence() { 1.0 } else { confidence },
+            match_type,
+            details,
+            ..Default::default()
+        }
+    }
+
+    /// Run the matching pipeline and also return an audit log recording,
+    /// for every match, which pass claimed it and the input indices of both
+    /// functions. Answers "why did/didn't X match Y".
+    pub fn match_functions_audited(
+        &self,
+        functions_a: &[FunctionInfo],
+        functions_b: &[FunctionInfo],
+    ) -> Result<(Vec<FunctionMatch>, MatchAudit)> {
+        let state = self.run_pipeline(functions_a, functions_b, |_| {}, |_, _| {})?;
+
+        let entries = state
+            .claims
+            .iter()
+            .map(|&(pass, index_a, index_b)| AuditEntry {
+                pass,
+                index_a,
+                index_b,
+                address_a: functions_a[index_a].address,
+                address_b: functions_b[index_b].address,
+                name_a: functions_a[index_a].name.clone(),
+                name_b: functions_b[index_b].name.clone(),
+            })
+            .collect();
+
+        Ok((state.matches, MatchAudit { entries }))
+    }
+
+    /// Score `matches` against ground-truth `(address_a, address_b)` pairs,
+    /// e.g. from symbols of a debug build, for precision/recall evaluation.
+    pub fn evaluate(matches: &[FunctionMatch], ground_truth: &[(u64, u64)]) -> EvalReport {
+        let truth: FxHashSet<(u64, u64)> = ground_truth.iter().copied().collect();
+        let mut predicted: FxHashSet<(u64, u64)> = FxHashSet::default();
+        let mut false_positives = Vec::new();
+
+        for m in matches {
+            let pair = (m.function_a.address, m.function_b.address);
+            if predicted.insert(pair) && !truth.contains(&pair) {
+                false_positives.push(pair);
+            }
+        }
+
+        let mut seen: FxHashSet<(u64, u64)> = FxHashSet::default();
+        let false_negatives: Vec<(u64, u64)> = ground_truth
+            .iter()
+            .copied()
+            .filter(|pair| seen.insert(*pair) && !predicted.contains(pair))
+            .collect();
+
+        let true_positives = predicted.len() - false_positives.len();
+        let ratio = |n: usize, d: usize| if d == 0 { 1.0 } else { n as f64 / d as f64 };
+        let precision = ratio(true_positives, predicted.len());
+        let recall = ratio(true_positives, truth.len());
+        let f1 = if precision + recall > 0.0 {
+            2.0 * precision * recall / (precision + recall)
+        } else {
+            0.0
+        };
+
+        EvalReport { precision, recall, f1, true_positives, false_positives, false_negatives }
+    }
+
+    /// For every unmatched function on either side, find the most similar
+    /// unmatched function in the other binary regardless of thresholds.
+    /// Entries for A come first, then B, each in input order.
+    pub fn find_near_misses(unmatched_a: &[FunctionInfo], unmatched_b: &[FunctionInfo]) -> Vec<NearMiss> {
+        fn best_candidates(from: &[FunctionInfo], to: &[FunctionInfo], in_binary_a: bool) -> Vec<NearMiss> {
+            from.par_iter()
+                .map(|func| {
+                    let best = to
+                        .iter()
+                        .map(|candidate| (candidate, DiffAlgorithms::calculate_function_similarity(func, candidate)))
+                        .max_by(|(_, x), (_, y)| x.total_cmp(y));
+
+                    NearMiss {
+                        in_binary_a,
+                        name: func.name.clone(),
+                        address: func.address,
+                        candidate_name: best.map(|(c, _)| c.name.clone()),
+                        candidate_address: best.map(|(c, _)| c.address),
+                        similarity: best.map_or(0.0, |(_, s)| s),
+                    }
+                })
+                .collect()
+        }
+
+        let mut near_misses = best_candidates(unmatched_a, unmatched_b, true);
+        near_misses.extend(best_candidates(unmatched_b, unmatched_a, false));
+        near_misses
+    }
+
+    /// Among the unmatched functions, find those whose mnemonic multiset is
+    /// mostly covered by the union of two unmatched functions of the other
+    /// binary (and is most of that union): splits from A into B, then merges
+    /// from A into B among what is left. Each function takes part in at most
+    /// one relation.
+    pub fn detect_split_merge(unmatched_a: &[FunctionInfo], unmatched_b: &[FunctionInfo]) -> Vec<SplitMerge> {
+        let mut unmatched_a: Vec<&FunctionInfo> = unmatched_a.iter().collect();
+        let mut unmatched_b: Vec<&FunctionInfo> = unmatched_b.iter().collect();
+
+        let mut relations = Self::find_splits(&mut unmatched_a, &mut unmatched_b, SplitMergeKind::Split);
+        relations.extend(Self::find_splits(&mut unmatched_b, &mut unmatched_a, SplitMergeKind::Merge));
+        relations
+    }
+
+    /// Pair each function of `singles` with two of `others` covering it,
+    /// greedily by coverage, removing the functions involved from both lists.
+    fn find_splits(singles: &mut Vec<&FunctionInfo>, others: &mut Vec<&FunctionInfo>, kind: SplitMergeKind) -> Vec<SplitMerge> {
+        fn mnemonics(func: &FunctionInfo) -> FxHashMap<String, usize> {
+            let mut counts = FxHashMap::default();
+            for instr in &func.instructions {
+                *counts.entry(instr.mnemonic.trim().to_lowercase()).or_insert(0) += 1;
+            }
+            counts
+        }
+        fn overlap(a: &FxHashMap<String, usize>, b: &FxHashMap<String, usize>) -> usize {
+            a.iter().map(|(m, &count)| count.min(b.get(m).copied().unwrap_or(0))).sum()
+        }
+
+        let single_counts: Vec<_> = singles.iter().map(|f| mnemonics(f)).collect();
+        let other_counts: Vec<_> = others.iter().map(|f| mnemonics(f)).collect();
+
+        // (coverage, single index, part indices)
+        let mut candidates: Vec<(f64, usize, usize, usize)> = singles
+            .par_iter()
+            .enumerate()
+            .filter(|(_, single)| single.instructions.len() >= SPLIT_MERGE_MIN_INSTRUCTIONS)
+            .filter_map(|(s, single)| {
+                let total = single.instructions.len();
+                // Parts mostly contained in the single function and smaller than it
+                let mut parts: Vec<(usize, usize)> = others
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, other)| other.instructions.len() >= SPLIT_MERGE_MIN_INSTRUCTIONS / 2)
+                    .filter(|(_, other)| other.instructions.len() < total)
+                    .map(|(o, other)| (o, overlap(&other_counts[o], &single_counts[s]), other.instructions.len()))
+                    .filter(|&(_, shared, len)| shared as f64 >= SPLIT_MERGE_MIN_COVERAGE * len as f64)
+                    .map(|(o, shared, _)| (o, shared))
+                    .collect();
+                parts.sort_by(|x, y| y.1.cmp(&x.1).then(x.0.cmp(&y.0)));
+                parts.truncate(SPLIT_MERGE_MAX_PARTS);
+
+                let mut best: Option<(f64, usize, usize, usize)> = None;
+                for (i, &(p1, _)) in parts.iter().enumerate() {
+                    for &(p2, _) in &parts[i + 1..] {
+                        let mut union = other_counts[p1].clone();
+                        for (m, &count) in &other_counts[p2] {
+                            *union.entry(m.clone()).or_insert(0) += count;
+                        }
+                        let union_len = others[p1].instructions.len() + others[p2].instructions.len();
+                        let shared = overlap(&single_counts[s], &union);
+                        let coverage = shared as f64 / total as f64;
+                        let precision = shared as f64 / union_len as f64;
+                        if coverage >= SPLIT_MERGE_MIN_COVERAGE
+                            && precision >= SPLIT_MERGE_MIN_COVERAGE
+                            && best.is_none_or(|(c, ..)| coverage > c)
+                        {
+                            best = Some((coverage, s, p1.min(p2), p1.max(p2)));
+                        }
+                    }
+                }
+                best
+            })
+            .collect();
+        candidates.sort_by(|x, y| y.0.total_cmp(&x.0).then(x.1.cmp(&y.1)));
+
+        let mut used_singles = FxHashSet::default();
+        let mut used_others = FxHashSet::default();
+        let mut relations = Vec::new();
+        for (coverage, s, p1, p2) in candidates {
+            if used_singles.contains(&s) || used_others.contains(&p1) || used_others.contains(&p2) {
+                continue;
+            }
+            used_singles.insert(s);
+            used_others.insert(p1);
+            used_others.insert(p2);
+            relations.push(SplitMerge {
+                kind,
+                name: singles[s].name.clone(),
+                address: singles[s].address,
+                parts: vec![(others[p1].name.clone(), others[p1].address), (others[p2].name.clone(), others[p2].address)],
+                coverage,
+            });
+        }
+
+        *singles = singles.iter().enumerate().filter(|(i, _)| !used_singles.contains(i)).map(|(_, f)| *f).collect();
+        *others = others.iter().enumerate().filter(|(i, _)| !used_others.contains(i)).map(|(_, f)| *f).collect();
+        relations
+    }
+
+    fn run_pipeline(
+        &self,
+        functions_a: &[FunctionInfo],
+        functions_b: &[FunctionInfo],
+        mut on_match: impl FnMut(&FunctionMatch),
+        mut on_progress: impl FnMut(usize, usize),
+    ) -> Result<MatchState> {
+        self.config.validate()?;
+
+        // Excluded functions are pre-claimed so no pass considers them.
+        let excluded_a = self.excluded_functions(functions_a);
+        let excluded_b = self.excluded_functions(functions_b);
+        let mut state = MatchState::new(excluded_a.clone(), excluded_b.clone());
+
+        state.current_pass = MatchPass::Manual;
+        self.manual_matching(functions_a, functions_b, &mut state);
+        for m in &state.matches {
+            on_match(m);
+        }
+        let mut emitted = state.matches.len();
+
+        for &pass in &self.config.pass_order {
+            if self.cancel.as_ref().is_some_and(|cancel| cancel.load(Ordering::Relaxed)) {
+                bail!("Diff cancelled before the {:?} pass", pass);
+            }
+            state.current_pass = pass;
+            let candidates_a = functions_a.len() - state.used_a.len();
+            let candidates_b = functions_b.len() - state.used_b.len();
+            let pass_start = Instant::now();
+
+            // Passes run on the configured pool; observers are called from here
+            self.install(|| self.run_pass(pass, functions_a, functions_b, &mut state))??;
+
+            let pass_matches = state.matches.len() - emitted;
+            let elapsed_ms = pass_start.elapsed().as_secs_f64() * 1000.0;
+            info!(
+                pass:? = pass,
+                matches = pass_matches,
+                candidates_a = candidates_a,
+                candidates_b = candidates_b,
+                elapsed_ms = elapsed_ms;
+                "{:?} pass matched {} functions in {:.2}ms", pass, pass_matches, elapsed_ms
+            );
+
+            if pass == MatchPass::ExactHash && self.config.verify_exact_bytes {
+                let downgraded = Self::verify_exact_matches(&mut state.matches, self.config.mask_relocations);
+                if downgraded > 0 {
+                    state.warnings.push(format!(
+                        "{} exact hash match(es) had differing instruction bytes and were downgraded",
+                        downgraded
+                    ));
+                }
+            }
+
+            // Before the observers see them, so they match the final result
+            if self.config.recalibrate_confidence {
+                let matched_b: Vec<usize> = state.claims[emitted..].iter().map(|&(_, _, idx_b)| idx_b).collect();
+                self.recalibrate_confidence(&mut state.matches[emitted..], &matched_b, functions_b)?;
+            }
+
+            for m in &state.matches[emitted..] {
+                on_match(m);
+            }
+            emitted = state.matches.len();
+            on_progress(state.used_a.len(), functions_a.len());
+        }
+        on_progress(functions_a.len(), functions_a.len());
+
+        // Every pass skips functions already claimed on either side, so each
+        // match consumes exactly one fresh index from A and one from B.
+        debug_assert_eq!(state.used_a.len() - excluded_a.len(), state.matches.len(), "function from A matched more than once");
+        debug_assert_eq!(state.used_b.len() - excluded_b.len(), state.matches.len(), "function from B matched more than once");
+
+        Ok(state)
+    }
+
+    fn run_pass(
+        &self,
+        pass: MatchPass,
+        functions_a: &[FunctionInfo],
+        functions_b: &[FunctionInfo],
+        state: &mut MatchState,
+    ) -> Result<()> {
+        match pass {
+            // Applied up front by run_pipeline
+            MatchPass::Manual => Ok(()),
+            // Highest confidence
+            MatchPass::ExactHash => self.exact_hash_matching(functions_a, functions_b, state),
+            // High confidence
+            MatchPass::Name => self.name_matching(functions_a, functions_b, state),
+            // Medium confidence
+            MatchPass::MdIndex => self.md_index_matching(functions_a, functions_b, state),
+            MatchPass::SmallPrimes => self.small_primes_matching(functions_a, functions_b, state),
+            MatchPass::MdIndexSmallPrimes => self.md_index_small_primes_matching(functions_a, functions_b, state),
+            MatchPass::PrologueEpilogue => self.prologue_epilogue_matching(functions_a, functions_b, state),
+            MatchPass::Reference => self.reference_matching(functions_a, functions_b, state),
+            // Lower confidence
+            MatchPass::Structural => self.structural_matching(functions_a, functions_b, state),
+            MatchPass::BlockEdgeBucket => self.block_edge_bucket_matching(functions_a, functions_b, state),
+            // Lowest confidence
+            MatchPass::Fuzzy => self.fuzzy_matching(functions_a, functions_b, state),
+        }
+    }
+
+    /// Indices of functions that should not take part in matching at all.
+    fn excluded_functions(&self, functions: &[FunctionInfo]) -> FxHashSet<usize> {
+        let mut excluded = self.excluded_thunks(functions);
+        excluded.extend(self.skipped_functions(functions));
+        excluded
+    }
+
+    /// Functions below the configured minimum size or instruction count.
+    fn skipped_functions(&self, functions: &[FunctionInfo]) -> FxHashSet<usize> {
+        functions
+            .iter()
+            .enumerate()
+            .filter(|(_, f)| self.config.is_below_minimum_size(f))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    fn excluded_thunks(&self, functions: &[FunctionInfo]) -> FxHashSet<usize> {
+        let jump_mnemonics = &self.config.thunk_jump_mnemonics;
+
+        match self.config.thunk_handling {
+            ThunkHandling::Keep => FxHashSet::default(),
+            ThunkHandling::Exclude => functions
+                .iter()
+                .enumerate()
+                .filter(|(_, f)| DiffAlgorithms::thunk_target(f, jump_mnemonics).is_some())
+                .map(|(i, _)| i)
+                .collect(),
+            ThunkHandling::Resolve => functions
+                .iter()
+                .enumerate()
+                .filter(|(i, f)| {
+                    DiffAlgorithms::thunk_target(f, jump_mnemonics)
+                        .and_then(|target| DiffAlgorithms::resolve_thunk_target(&target, functions))
+                        .is_some_and(|resolved| resolved != *i)
+                })
+                .map(|(i, _)| i)
+                .collect(),
+        }
     }
 
     /// Exact hash matching - functions with identical CFG and call graph hashes
@@ -92,47 +754,47 @@ impl MatchingEngine {
         &self,
         functions_a: &[FunctionInfo],
         functions_b: &[FunctionInfo],
-        matches: &mut Vec<FunctionMatch>,
-        used_a: &mut FxHashSet<usize>,
-        used_b: &mut FxHashSet<usize>,
+        state: &mut MatchState,
     ) -> Result<()> {
-        let mut hash_map_b: FxHashMap<String, Vec<usize>> = FxHashMap::default();
+        let mut hash_map_b: FxHashMap<u128, Vec<usize>> = FxHashMap::default();
 
         for (i, func_b) in functions_b.iter().enumerate() {
-            let combined_hash = format!("{}_{}", func_b.cfg_hash, func_b.call_graph_hash);
-            hash_map_b.entry(combined_hash).or_default().push(i);
+            hash_map_b.entry(func_b.combined_hash()).or_default().push(i);
         }
 
         for (idx_a, func_a) in functions_a.iter().enumerate() {
-            if used_a.contains(&idx_a) {
+            if state.used_a.contains(&idx_a) {
                 continue;
             }
-            let combined_hash = format!("{}_{}", func_a.cfg_hash, func_a.call_graph_hash);
-
-            if let Some(candidates) = hash_map_b.get(&combined_hash) {
-                // Take the lowest unused index deterministically for exact-hash ties.
-                let mut chosen: Option<usize> = None;
+            if let Some(candidates) = hash_map_b.get(&func_a.combined_hash()) {
+                // Several functions may share a (weak) hash: score every unused
+                // candidate and keep the most similar, lowest index on ties.
+                let mut best: Option<(usize, f64, f64, MatchDetails)> = None;
                 for &idx in candidates {
-                    if !used_b.contains(&idx) && chosen.map_or(true, |c| idx < c) {
-                        chosen = Some(idx);
+                    if state.used_b.contains(&idx) {
+                        continue;
                     }
-                }
-                if let Some(idx) = chosen {
                     let func_b = &functions_b[idx];
-                    let (similarity, details) = DiffAlgorithms::compute_match_details(func_a, func_b);
+                    let (similarity, details) = DiffAlgorithms::compute_match_details_weighted(func_a, func_b, &self.config.weights);
                     let confidence = DiffAlgorithms::calculate_confidence(func_a, func_b, similarity);
 
-                    matches.push(FunctionMatch {
+                    if best.as_ref().is_none_or(|(bi, bs, bc, _)| {
+                        better_candidate(similarity, confidence, idx, *bs, *bc, *bi)
+                    }) {
+                        best = Some((idx, similarity, confidence, details));
+                    }
+                }
+                if let Some((idx, similarity, _, details)) = best {
+                    // Identical hashes are taken as proof, like a manual pin
+                    state.claim(idx_a, idx, FunctionMatch {
                         function_a: func_a.clone(),
-                        function_b: func_b.clone(),
+                        function_b: functions_b[idx].clone(),
                         similarity,
-                        confidence,
+                        confidence: 1.0,
                         match_type: MatchType::Exact,
                         details,
+                        ..Default::default()
                     });
-
-                    used_a.insert(idx_a);
-                    used_b.insert(idx);
                 }
             }
         }
@@ -145,31 +807,35 @@ impl MatchingEngine {
         &self,
         functions_a: &[FunctionInfo],
         functions_b: &[FunctionInfo],
-        matches: &mut Vec<FunctionMatch>,
-        used_a: &mut FxHashSet<usize>,
-        used_b: &mut FxHashSet<usize>,
+        state: &mut MatchState,
     ) -> Result<()> {
         let mut name_map_b: FxHashMap<String, Vec<usize>> = FxHashMap::default();
 
         for (i, func_b) in functions_b.iter().enumerate() {
-            if !used_b.contains(&i) && !is_auto_generated_name(&func_b.name) {
+            if !state.used_b.contains(&i) && !is_auto_generated_name(&func_b.name) {
                 name_map_b.entry(func_b.name.clone()).or_default().push(i);
             }
         }
 
         for (idx_a, func_a) in functions_a.iter().enumerate() {
-            if used_a.contains(&idx_a) || is_auto_generated_name(&func_a.name) {
+            if state.used_a.contains(&idx_a) || is_auto_generated_name(&func_a.name) {
                 continue;
             }
             if let Some(candidates) = name_map_b.get(&func_a.name) {
                 // Pick the best candidate deterministically instead of first-match.
                 let mut best: Option<(usize, f64, f64, MatchDetails)> = None;
                 for &idx in candidates {
-                    if !used_b.contains(&idx) {
+                    if !state.used_b.contains(&idx) {
                         let func_b = &functions_b[idx];
-                        let (similarity, details) = DiffAlgorithms::compute_match_details(func_a, func_b);
+                        let (similarity, details) = DiffAlgorithms::compute_match_details_weighted(func_a, func_b, &self.config.weights);
                         let confidence = DiffAlgorithms::calculate_confidence(func_a, func_b, similarity);
 
+                        if self.config.require_structural_corroboration
+                            && details.structural_similarity() < self.config.structural_corroboration_threshold
+                        {
+                            continue;
+                        }
+
                         if confidence >= self.confidence_threshold && similarity >= self.similarity_threshold {
                             if best.as_ref().map_or(true, |(bi, bs, bc, _)| {
                                 better_candidate(confidence, similarity, idx, *bc, *bs, *bi)
@@ -180,16 +846,15 @@ impl MatchingEngine {
                     }
                 }
                 if let Some((idx, similarity, confidence, details)) = best {
-                    matches.push(FunctionMatch {
+                    state.claim(idx_a, idx, FunctionMatch {
                         function_a: func_a.clone(),
                         function_b: functions_b[idx].clone(),
                         similarity,
                         confidence,
                         match_type: MatchType::Structural,
                         details,
+                        ..Default::default()
                     });
-                    used_a.insert(idx_a);
-                    used_b.insert(idx);
                 }
             }
         }
@@ -197,60 +862,37 @@ impl MatchingEngine {
         Ok(())
     }
 
-    /// MD-Index based matching (similar to Diaphora)
+    /// MD-Index based matching (similar to Diaphora). Functions without CFG
+    /// edges all share an MD-Index of 0.0, which says nothing about them, so
+    /// they are left to later passes.
     fn md_index_matching(
         &self,
         functions_a: &[FunctionInfo],
         functions_b: &[FunctionInfo],
-        matches: &mut Vec<FunctionMatch>,
-        used_a: &mut FxHashSet<usize>,
-        used_b: &mut FxHashSet<usize>,
+        state: &mut MatchState,
     ) -> Result<()> {
-        let mut md_map_b: FxHashMap<String, Vec<usize>> = FxHashMap::default();
+        let mut md_map_b: FxHashMap<u64, Vec<usize>> = FxHashMap::default();
 
         for (i, func_b) in functions_b.iter().enumerate() {
-            if !used_b.contains(&i) {
-                let md_index = DiffAlgorithms::calculate_md_index(func_b);
-                md_map_b.entry(md_index).or_default().push(i);
+            if !state.used_b.contains(&i) {
+                let md_index = DiffAlgorithms::calculate_md_index_value(func_b);
+                if md_index != 0.0 {
+                    md_map_b.entry(md_index.to_bits()).or_default().push(i);
+                }
             }
         }
 
         for (idx_a, func_a) in functions_a.iter().enumerate() {
-            if used_a.contains(&idx_a) {
+            if state.used_a.contains(&idx_a) {
+                continue;
+            }
+            let md_index_a = DiffAlgorithms::calculate_md_index_value(func_a);
+            if md_index_a == 0.0 {
                 continue;
             }
-            let md_index_a = DiffAlgorithms::calculate_md_index(func_a);
-
-            if let Some(candidates) = md_map_b.get(&md_index_a) {
-                // Pick the best candidate by similarity
-                let mut best: Option<(usize, f64, f64, MatchDetails)> = None;
-                for &idx in candidates {
-                    if !used_b.contains(&idx) {
-                        let func_b = &functions_b[idx];
-                        let (similarity, details) = DiffAlgorithms::compute_match_details(func_a, func_b);
-                        let confidence = DiffAlgorithms::calculate_confidence(func_a, func_b, similarity);
 
-                        if confidence >= self.confidence_threshold && similarity >= self.similarity_threshold {
-                            if best.as_ref().map_or(true, |(bi, bs, bc, _)| {
-                                better_candidate(confidence, similarity, idx, *bc, *bs, *bi)
-                            }) {
-                                best = Some((idx, similarity, confidence, details));
-                            }
-                        }
-                    }
-                }
-                if let Some((idx, similarity, confidence, details)) = best {
-                    matches.push(FunctionMatch {
-                        function_a: func_a.clone(),
-                        function_b: functions_b[idx].clone(),
-                        similarity,
-                        confidence,
-                        match_type: MatchType::Heuristic,
-                        details,
-                    });
-                    used_a.insert(idx_a);
-                    used_b.insert(idx);
-                }
+            if let Some(candidates) = md_map_b.get(&md_index_a.to_bits()) {
+                self.claim_best_candidate(idx_a, candidates, functions_a, functions_b, state, MatchType::Heuristic);
             }
         }
 
@@ -262,193 +904,1053 @@ impl MatchingEngine {
         &self,
         functions_a: &[FunctionInfo],
         functions_b: &[FunctionInfo],
-        matches: &mut Vec<FunctionMatch>,
-        used_a: &mut FxHashSet<usize>,
-        used_b: &mut FxHashSet<usize>,
+        state: &mut MatchState,
     ) -> Result<()> {
-        let mut primes_map_b: FxHashMap<u64, Vec<usize>> = FxHashMap::default();
+        let primes = MnemonicPrimes::from_functions(functions_a.iter().chain(functions_b));
+        let mut primes_map_b: FxHashMap<(u64, usize), Vec<usize>> = FxHashMap::default();
 
         for (i, func_b) in functions_b.iter().enumerate() {
-            if !used_b.contains(&i) {
-                let primes_product = DiffAlgorithms::calculate_small_primes_product(func_b);
+            if !state.used_b.contains(&i) {
+                let primes_product = DiffAlgorithms::calculate_small_primes_product(func_b, &primes);
                 primes_map_b.entry(primes_product).or_default().push(i);
             }
         }
 
         for (idx_a, func_a) in functions_a.iter().enumerate() {
-            if used_a.contains(&idx_a) {
+            if state.used_a.contains(&idx_a) {
                 continue;
             }
-            let primes_product_a = DiffAlgorithms::calculate_small_primes_product(func_a);
+            let primes_product_a = DiffAlgorithms::calculate_small_primes_product(func_a, &primes);
 
             if let Some(candidates) = primes_map_b.get(&primes_product_a) {
-                let mut best: Option<(usize, f64, f64, MatchDetails)> = None;
-                for &idx in candidates {
-                    if !used_b.contains(&idx) {
-                        let func_b = &functions_b[idx];
-                        let (similarity, details) = DiffAlgorithms::compute_match_details(func_a, func_b);
-                        let confidence = DiffAlgorithms::calculate_confidence(func_a, func_b, similarity);
+                self.claim_best_candidate(idx_a, candidates, functions_a, functions_b, state, MatchType::Heuristic);
+            }
+        }
 
-                        if confidence >= self.confidence_threshold && similarity >= self.similarity_threshold {
-                            if best.as_ref().map_or(true, |(bi, bs, bc, _)| {
-                                better_candidate(confidence, similarity, idx, *bc, *bs, *bi)
-                            }) {
-                                best = Some((idx, similarity, confidence, details));
-                            }
-                        }
-                    }
+        Ok(())
+    }
+
+    /// Claim the user-pinned pairs from `DiffConfig::manual_matches`. Pins
+    /// naming a missing, excluded or already pinned function are skipped
+    /// with a warning.
+    fn manual_matching(&self, functions_a: &[FunctionInfo], functions_b: &[FunctionInfo], state: &mut MatchState) {
+        for &(address_a, address_b) in &self.config.manual_matches {
+            let idx_a = functions_a.iter().position(|f| f.address == address_a);
+            let idx_b = functions_b.iter().position(|f| f.address == address_b);
+            let (idx_a, idx_b) = match (idx_a, idx_b) {
+                (Some(idx_a), Some(idx_b)) if !state.used_a.contains(&idx_a) && !state.used_b.contains(&idx_b) => (idx_a, idx_b),
+                _ => {
+                    state.warnings.push(format!(
+                        "Manual match 0x{:x} <-> 0x{:x} ignored: function missing, excluded or already matched",
+                        address_a, address_b
+                    ));
+                    continue;
                 }
-                if let Some((idx, similarity, confidence, details)) = best {
-                    matches.push(FunctionMatch {
-                        function_a: func_a.clone(),
-                        function_b: functions_b[idx].clone(),
-                        similarity,
-                        confidence,
-                        match_type: MatchType::Heuristic,
-                        details,
-                    });
-                    used_a.insert(idx_a);
-                    used_b.insert(idx);
+            };
+
+            state.claim(idx_a, idx_b, self.manual_match(&functions_a[idx_a], &functions_b[idx_b]));
+        }
+    }
+
+    /// Combined MD-Index and small-primes matching. Keys are computed once
+    /// per unclaimed function; candidates sharing both keys are tried for
+    /// every function before falling back to candidates sharing only the
+    /// MD-Index, then only the small-primes product. As in the MD-Index
+    /// pass, functions without CFG edges have no MD-Index key.
+    fn md_index_small_primes_matching(
+        &self,
+        functions_a: &[FunctionInfo],
+        functions_b: &[FunctionInfo],
+        state: &mut MatchState,
+    ) -> Result<()> {
+        let primes = MnemonicPrimes::from_functions(functions_a.iter().chain(functions_b));
+        let keys = |func: &FunctionInfo| {
+            let md_index = DiffAlgorithms::calculate_md_index_value(func);
+            let md_key = (md_index != 0.0).then(|| md_index.to_bits());
+            (md_key, DiffAlgorithms::calculate_small_primes_product(func, &primes))
+        };
+
+        let mut both_map_b: FxHashMap<(u64, (u64, usize)), Vec<usize>> = FxHashMap::default();
+        let mut md_map_b: FxHashMap<u64, Vec<usize>> = FxHashMap::default();
+        let mut primes_map_b: FxHashMap<(u64, usize), Vec<usize>> = FxHashMap::default();
+        for (i, func_b) in functions_b.iter().enumerate() {
+            if state.used_b.contains(&i) {
+                continue;
+            }
+            let (md_key, primes_key) = keys(func_b);
+            if let Some(md_key) = md_key {
+                both_map_b.entry((md_key, primes_key)).or_default().push(i);
+                md_map_b.entry(md_key).or_default().push(i);
+            }
+            primes_map_b.entry(primes_key).or_default().push(i);
+        }
+
+        let keys_a: Vec<(usize, Option<u64>, (u64, usize))> = functions_a
+            .iter()
+            .enumerate()
+            .filter(|(idx_a, _)| !state.used_a.contains(idx_a))
+            .map(|(idx_a, func_a)| {
+                let (md_key, primes_key) = keys(func_a);
+                (idx_a, md_key, primes_key)
+            })
+            .collect();
+
+        for tier in 0..3 {
+            for &(idx_a, md_key, primes_key) in &keys_a {
+                if state.used_a.contains(&idx_a) {
+                    continue;
+                }
+                let candidates = match (tier, md_key) {
+                    (0, Some(md_key)) => both_map_b.get(&(md_key, primes_key)),
+                    (1, Some(md_key)) => md_map_b.get(&md_key),
+                    (2, _) => primes_map_b.get(&primes_key),
+                    _ => None,
+                };
+                if let Some(candidates) = candidates {
+                    self.claim_best_candidate(idx_a, candidates, functions_a, functions_b, state, MatchType::Heuristic);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Claim the unclaimed candidate from B scoring best against
+    /// `functions_a[idx_a]`, if any passes both thresholds, as a match of
+    /// `match_type`.
+    fn claim_best_candidate(
+        &self,
+        idx_a: usize,
+        candidates: &[usize],
+        functions_a: &[FunctionInfo],
+        functions_b: &[FunctionInfo],
+        state: &mut MatchState,
+        match_type: MatchType,
+    ) {
+        let func_a = &functions_a[idx_a];
+        let mut best: Option<(usize, f64, f64, MatchDetails)> = None;
+        for &idx in candidates {
+            if !state.used_b.contains(&idx) {
+                let func_b = &functions_b[idx];
+                let (similarity, details) = DiffAlgorithms::compute_match_details_weighted(func_a, func_b, &self.config.weights);
+                let confidence = DiffAlgorithms::calculate_confidence(func_a, func_b, similarity);
+
+                if confidence >= self.confidence_threshold
+                    && similarity >= self.similarity_threshold
+                    && best.as_ref().is_none_or(|(bi, bs, bc, _)| {
+                        better_candidate(confidence, similarity, idx, *bc, *bs, *bi)
+                    })
+                {
+                    best = Some((idx, similarity, confidence, details));
+                }
+            }
+        }
+        if let Some((idx, similarity, confidence, details)) = best {
+            state.claim(idx_a, idx, FunctionMatch {
+                function_a: func_a.clone(),
+                function_b: functions_b[idx].clone(),
+                similarity,
+                confidence,
+                match_type,
+                details,
+                ..Default::default()
+            });
+        }
+    }
+
+    /// Prologue/epilogue matching - functions whose normalized first and
+    /// last instructions agree. Compilers emit these stereotyped sequences
+    /// consistently across rebuilds, so an agreeing pair is a strong
+    /// structural hint even when the function body changed.
+    fn prologue_epilogue_matching(
+        &self,
+        functions_a: &[FunctionInfo],
+        functions_b: &[FunctionInfo],
+        state: &mut MatchState,
+    ) -> Result<()> {
+        let length = self.config.prologue_epilogue_length;
+        let mut signature_map_b: FxHashMap<String, Vec<usize>> = FxHashMap::default();
+
+        for (i, func_b) in functions_b.iter().enumerate() {
+            if !state.used_b.contains(&i) {
+                if let Some(signature) = DiffAlgorithms::calculate_prologue_epilogue_signature(func_b, length) {
+                    signature_map_b.entry(signature).or_default().push(i);
                 }
             }
         }
 
+        for (idx_a, func_a) in functions_a.iter().enumerate() {
+            if state.used_a.contains(&idx_a) {
+                continue;
+            }
+            let Some(signature_a) = DiffAlgorithms::calculate_prologue_epilogue_signature(func_a, length) else {
+                continue;
+            };
+
+            if let Some(candidates) = signature_map_b.get(&signature_a) {
+                self.claim_best_candidate(idx_a, candidates, functions_a, functions_b, state, MatchType::Structural);
+            }
+        }
+
         Ok(())
     }
 
-    /// Structural matching based on CFG isomorphism.
-    /// Pre-bucketed by basic-block count so we only run the isomorphism
-    /// check on plausibly-matching pairs (O(n²) over bucket size, not total).
+    /// Structural matching based on CFG shape.
+    /// Pre-bucketed by basic-block count so we only compare plausibly-matching
+    /// pairs: same-size candidates must be isomorphic (edge-degree
+    /// distribution), while nearby buckets are admitted within the configured
+    /// size-relative `StructuralTolerance`.
     fn structural_matching(
         &self,
         functions_a: &[FunctionInfo],
         functions_b: &[FunctionInfo],
-        matches: &mut Vec<FunctionMatch>,
-        used_a: &mut FxHashSet<usize>,
-        used_b: &mut FxHashSet<usize>,
+        state: &mut MatchState,
     ) -> Result<()> {
-        // Bucket unmatched functions_b by basic-block count (required for isomorphism).
+        let tolerance = &self.config.structural;
+
+        // Bucket unmatched functions_b by basic-block count.
         let mut bb_buckets: FxHashMap<usize, Vec<usize>> = FxHashMap::default();
         for (i, func_b) in functions_b.iter().enumerate() {
-            if !used_b.contains(&i) {
+            if !state.used_b.contains(&i) {
                 bb_buckets.entry(func_b.basic_blocks.len()).or_default().push(i);
             }
         }
+        let mut bucket_keys: Vec<usize> = bb_buckets.keys().copied().collect();
+        bucket_keys.sort_unstable();
+
+        let recursion_a = DiffAlgorithms::recursion_kinds(functions_a);
+        let recursion_b = DiffAlgorithms::recursion_kinds(functions_b);
+
+        // Dominator signatures admit same-dominance CFGs whose edge-degree
+        // distribution changed under block reordering.
+        let dom_sigs_b: FxHashMap<usize, String> = bb_buckets
+            .values()
+            .flatten()
+            .map(|&i| (i, DiffAlgorithms::calculate_dominator_signature(&functions_b[i])))
+            .collect();
+        // Structural fingerprints seed candidates that the exact shape checks
+        // reject, e.g. after block reordering plus a small edit.
+        let fingerprints_b: FxHashMap<usize, StructuralFingerprint> = bb_buckets
+            .values()
+            .flatten()
+            .map(|&i| (i, DiffAlgorithms::calculate_structural_fingerprint(&functions_b[i])))
+            .collect();
 
         for (idx_a, func_a) in functions_a.iter().enumerate() {
-            if used_a.contains(&idx_a) {
+            if state.used_a.contains(&idx_a) {
                 continue;
             }
             let mut best_match: Option<(usize, f64, f64, MatchDetails)> = None;
+            let bb_count_a = func_a.basic_blocks.len();
+            let dom_sig_a = DiffAlgorithms::calculate_dominator_signature(func_a);
+            let fingerprint_a = DiffAlgorithms::calculate_structural_fingerprint(func_a);
 
-            let bucket = match bb_buckets.get(&func_a.basic_blocks.len()) {
-                Some(b) => b,
-                None => continue,
-            };
+            let nearby = bucket_keys.iter().filter(|&&key| {
+                key.abs_diff(bb_count_a) <= tolerance.allowed_block_diff(key.max(bb_count_a))
+            });
 
-            for &i in bucket {
-                if used_b.contains(&i) {
+            for &i in nearby.flat_map(|key| &bb_buckets[key]) {
+                if state.used_b.contains(&i) {
                     continue;
                 }
 
-                if DiffAlgorithms::is_isomorphic_subgraph(func_a, &functions_b[i]) {
-                    let func_b = &functions_b[i];
-                    let (similarity, details) = DiffAlgorithms::compute_match_details(func_a, func_b);
+                let func_b = &functions_b[i];
+                // Exception handling structure survives recompiles; a function
+                // with landing pads is not the same shape as one without.
+                if func_a.has_exception_handling != func_b.has_exception_handling {
+                    continue;
+                }
+                // Likewise recursion: a recursive function isn't restructured
+                // into a non-recursive one by a patch or recompile.
+                if recursion_a[idx_a] != recursion_b[i] {
+                    continue;
+                }
+                let shape_matches = DiffAlgorithms::is_isomorphic_subgraph(func_a, func_b)
+                    || DiffAlgorithms::is_structurally_similar(func_a, func_b, tolerance)
+                    || (!dom_sig_a.is_empty() && dom_sigs_b.get(&i) == Some(&dom_sig_a))
+                    || fingerprints_b.get(&i).is_some_and(|fingerprint_b| {
+                        fingerprint_a.distance(fingerprint_b) <= self.config.fingerprint_max_distance
+                    });
+
+                if shape_matches {
+                    let (similarity, details) = DiffAlgorithms::compute_match_details_weighted(func_a, func_b, &self.config.weights);
                     let confidence = DiffAlgorithms::calculate_confidence(func_a, func_b, similarity);
 
-                    if confidence >= self.confidence_threshold && similarity >= self.similarity_threshold {
-                        if best_match.as_ref().map_or(true, |(bi, bs, bc, _)| {
+                    if confidence >= self.confidence_threshold
+                        && similarity >= self.similarity_threshold
+                        && best_match.as_ref().is_none_or(|(bi, bs, bc, _)| {
                             better_candidate(confidence, similarity, i, *bc, *bs, *bi)
-                        }) {
-                            best_match = Some((i, similarity, confidence, details));
-                        }
+                        })
+                    {
+                        best_match = Some((i, similarity, confidence, details));
                     }
                 }
             }
 
             if let Some((idx, similarity, confidence, details)) = best_match {
-                matches.push(FunctionMatch {
+                state.claim(idx_a, idx, FunctionMatch {
                     function_a: func_a.clone(),
                     function_b: functions_b[idx].clone(),
                     similarity,
                     confidence,
                     match_type: MatchType::Structural,
                     details,
+                    ..Default::default()
                 });
-                used_a.insert(idx_a);
-                used_b.insert(idx);
             }
         }
 
         Ok(())
     }
 
-    /// Fuzzy matching for remaining functions, blending primary and comprehensive similarity
-    fn fuzzy_matching(
+    /// Bucketed matching - compares each A-function only against B-functions
+    /// whose (basic block count, edge count) lies within `bucket_tolerance`
+    /// of its own, scoring candidates like the fuzzy pass. Claims most
+    /// near-shape matches at a fraction of the fuzzy pass's all-pairs cost.
+    fn block_edge_bucket_matching(
         &self,
         functions_a: &[FunctionInfo],
         functions_b: &[FunctionInfo],
-        matches: &mut Vec<FunctionMatch>,
-        used_a: &mut FxHashSet<usize>,
-        used_b: &mut FxHashSet<usize>,
+        state: &mut MatchState,
     ) -> Result<()> {
-        let mut candidates: Vec<_> = functions_a
-            .iter()
-            .enumerate()
-            .filter(|(idx_a, _)| !used_a.contains(idx_a))
-            .par_bridge()
-            .filter_map(|(idx_a, func_a)| {
-                let mut best_match: Option<(usize, f64, f64, MatchDetails)> = None;
+        fn bucket_key(func: &FunctionInfo) -> (usize, usize) {
+            let edges = func.basic_blocks.iter().map(|bb| bb.edges.len()).sum();
+            (func.basic_blocks.len(), edges)
+        }
 
-                for (i, func_b) in functions_b.iter().enumerate() {
-                    if used_b.contains(&i) {
-                        continue;
+        let mut buckets: FxHashMap<(usize, usize), Vec<usize>> = FxHashMap::default();
+        for (i, func_b) in functions_b.iter().enumerate() {
+            if !state.used_b.contains(&i) {
+                buckets.entry(bucket_key(func_b)).or_default().push(i);
+            }
+        }
+
+        let tolerance = self.config.bucket_tolerance;
+        self.scored_matching(functions_a, functions_b, state, |func_a| {
+            let (blocks, edges) = bucket_key(func_a);
+            let mut candidates = Vec::new();
+            for b in blocks.saturating_sub(tolerance)..=blocks.saturating_add(tolerance) {
+                for e in edges.saturating_sub(tolerance)..=edges.saturating_add(tolerance) {
+                    if let Some(bucket) = buckets.get(&(b, e)) {
+                        candidates.extend_from_slice(bucket);
                     }
+                }
+            }
+            candidates
+        })
+    }
 
-                    let (primary, details) = DiffAlgorithms::compute_match_details(func_a, func_b);
-                    let comprehensive = SimilarityAnalyzer::comprehensive_similarity(func_a, func_b);
-                    let similarity = (primary * 0.6 + comprehensive * 0.4).clamp(0.0, 1.0);
-                    let confidence = DiffAlgorithms::calculate_confidence(func_a, func_b, similarity);
+    /// Reference matching - compares each A-function only against the
+    /// B-functions sharing a rare string or constant reference with it,
+    /// found through an inverted index, scoring candidates like the fuzzy
+    /// pass.
+    fn reference_matching(
+        &self,
+        functions_a: &[FunctionInfo],
+        functions_b: &[FunctionInfo],
+        state: &mut MatchState,
+    ) -> Result<()> {
+        let index = ReferenceIndex::build(functions_b);
+        let max_frequency = self.config.max_reference_frequency;
+        self.scored_matching(functions_a, functions_b, state, |func_a| index.candidates(func_a, max_frequency))
+    }
 
-                    if confidence >= self.confidence_threshold
-                        && similarity >= self.similarity_threshold
-                    {
-                        if best_match.as_ref().map_or(true, |(bi, bs, bc, _)| {
-                            better_candidate(confidence, similarity, i, *bc, *bs, *bi)
-                        }) {
+    /// Fuzzy matching for remaining functions against every unclaimed B-function
+    fn fuzzy_matching(
+        &self,
+        functions_a: &[FunctionInfo],
+        functions_b: &[FunctionInfo],
+        state: &mut MatchState,
+    ) -> Result<()> {
+        self.scored_matching(functions_a, functions_b, state, |_| 0..functions_b.len())
+    }
+
+    /// Score every unclaimed A-function against the B-indices returned by
+    /// `candidates_for` (in parallel), keep each A-function's best candidate
+    /// above both thresholds, then claim pairs in order of confidence.
+    /// A-functions whose best candidate was claimed by a stronger pair are
+    /// re-scored against the B-functions still free, round after round,
+    /// until no further claims are made.
+    fn scored_matching<I: IntoIterator<Item = usize>>(
+        &self,
+        functions_a: &[FunctionInfo],
+        functions_b: &[FunctionInfo],
+        state: &mut MatchState,
+        candidates_for: impl Fn(&FunctionInfo) -> I + Sync,
+    ) -> Result<()> {
+        let mut pending: Vec<usize> = (0..functions_a.len()).filter(|idx_a| !state.used_a.contains(idx_a)).collect();
+
+        while !pending.is_empty() {
+            let shared: &MatchState = state;
+            // Scored in parallel; claims below stay sequential
+            let mut candidates: Vec<_> = pending
+                .par_iter()
+                .filter_map(|&idx_a| {
+                    let func_a = &functions_a[idx_a];
+                    let mut best_match: Option<(usize, f64, f64, MatchDetails)> = None;
+
+                    for i in candidates_for(func_a) {
+                        if shared.used_b.contains(&i) {
+                            continue;
+                        }
+                        let func_b = &functions_b[i];
+
+                        let (similarity, details) = DiffAlgorithms::compute_match_details_weighted(func_a, func_b, &self.config.weights);
+                        let confidence = DiffAlgorithms::calculate_confidence(func_a, func_b, similarity);
+
+                        if confidence >= self.confidence_threshold
+                            && similarity >= self.similarity_threshold
+                            && best_match.as_ref().is_none_or(|(bi, bs, bc, _)| {
+                                better_candidate(confidence, similarity, i, *bc, *bs, *bi)
+                            })
+                        {
                             best_match = Some((i, similarity, confidence, details));
                         }
                     }
-                }
 
-                best_match.map(|(idx, similarity, confidence, details)| {
-                    (idx_a, func_a.clone(), idx, similarity, confidence, details)
+                    best_match.map(|(idx, similarity, confidence, details)| {
+                        (idx_a, idx, similarity, confidence, details)
+                    })
                 })
-            })
-            .collect();
+                .collect();
 
-        // Deterministic conflict resolution: prefer higher confidence, then
-        // higher similarity, then lowest idx_a for stable tie-breaking.
-        candidates.sort_by(|a, b| {
-            b.4.total_cmp(&a.4)
-                .then_with(|| b.3.total_cmp(&a.3))
-                .then_with(|| a.0.cmp(&b.0))
-        });
-        for (idx_a, func_a, idx_b, similarity, confidence, details) in candidates {
-            if !used_a.contains(&idx_a) && !used_b.contains(&idx_b) {
-                matches.push(FunctionMatch {
-                    function_a: func_a,
+            // Deterministic conflict resolution: prefer higher confidence, then
+            // higher similarity, then lowest idx_a for stable tie-breaking.
+            candidates.sort_by(|a, b| {
+                b.3.total_cmp(&a.3)
+                    .then_with(|| b.2.total_cmp(&a.2))
+                    .then_with(|| a.0.cmp(&b.0))
+            });
+
+            // Only A-functions that lost their best candidate to a stronger
+            // claimant get another round; the rest have no candidate left,
+            // since the free B-functions only shrink.
+            pending.clear();
+            for (idx_a, idx_b, similarity, confidence, details) in candidates {
+                if state.used_b.contains(&idx_b) {
+                    pending.push(idx_a);
+                    continue;
+                }
+                state.claim(idx_a, idx_b, FunctionMatch {
+                    function_a: functions_a[idx_a].clone(),
                     function_b: functions_b[idx_b].clone(),
                     similarity,
                     confidence,
                     match_type: MatchType::Heuristic,
                     details,
+                    ..Default::default()
                 });
-                used_a.insert(idx_a);
-                used_b.insert(idx_b);
             }
+            pending.sort_unstable();
         }
 
         Ok(())
     }
+
+    /// Confirm each `Exact` match by comparing the concatenated instruction
+    /// bytes of both functions. Matches whose bytes differ (typically a
+    /// collision in the weak CFG/call-graph hashes) are downgraded to
+    /// `Structural` with an explanatory note, and lose the 1.0 confidence
+    /// the hash match implied. Returns the number downgraded.
+    pub fn verify_exact_matches(matches: &mut [FunctionMatch], mask_relocations: bool) -> usize {
+        let mut downgraded = 0;
+
+        for m in matches.iter_mut().filter(|m| m.match_type == MatchType::Exact) {
+            let bytes_a = Self::function_bytes(&m.function_a, mask_relocations);
+            let bytes_b = Self::function_bytes(&m.function_b, mask_relocations);
+
+            if bytes_a != bytes_b {
+                warn!(
+                    "Exact match {} <-> {} has differing instruction bytes; downgrading to Structural",
+                    m.function_a.name, m.function_b.name
+                );
+                m.match_type = MatchType::Structural;
+                m.confidence = DiffAlgorithms::calculate_confidence(&m.function_a, &m.function_b, m.similarity);
+                m.notes.push("Downgraded from Exact: instruction bytes differ".to_string());
+                downgraded += 1;
+            }
+        }
+
+        downgraded
+    }
+
+    /// Adjust confidences by how clearly each match beat the other
+    /// candidates in `functions_b`, where `matched_b[i]` is the index in
+    /// `functions_b` of `matches[i].function_b`. The other candidates are the
+    /// B-functions with the same basic block count as the match's A function
+    /// (the structural pass's bucket) or sharing a rare reference with it
+    /// (the reference pass's index), scored like the passes score a pair,
+    /// and the lead of the match's `similarity` over the best of them is
+    /// stored in `runner_up_gap`. A lead of `CLEAR_WINNER_GAP` or more closes
+    /// half the distance to 1.0; smaller leads scale confidence down,
+    /// halving it for a tie. `Exact` and `Manual` matches get a gap but keep
+    /// their confidence of 1.0.
+    ///
+    /// Thresholds are not applied again: a lowered confidence marks an
+    /// ambiguous match for review, but the pair is still the best one the
+    /// passes found, and dropping it would only leave both functions
+    /// unmatched.
+    pub fn recalibrate_confidence(
+        &self,
+        matches: &mut [FunctionMatch],
+        matched_b: &[usize],
+        functions_b: &[FunctionInfo],
+    ) -> Result<()> {
+        if matches.len() != matched_b.len() {
+            bail!("{} matches but {} B indices to recalibrate them with", matches.len(), matched_b.len());
+        }
+        let mut bb_buckets: FxHashMap<usize, Vec<usize>> = FxHashMap::default();
+        for (i, func_b) in functions_b.iter().enumerate() {
+            bb_buckets.entry(func_b.basic_blocks.len()).or_default().push(i);
+        }
+        let index = ReferenceIndex::build(functions_b);
+        let max_frequency = self.config.max_reference_frequency;
+
+        self.install(|| {
+            matches.par_iter_mut().zip(matched_b.par_iter()).for_each(|(m, &idx_b)| {
+                let mut candidates = index.candidates(&m.function_a, max_frequency);
+                candidates.extend(bb_buckets.get(&m.function_a.basic_blocks.len()).into_iter().flatten());
+                candidates.sort_unstable();
+                candidates.dedup();
+                let runner_up = candidates
+                    .into_iter()
+                    .filter(|&i| i != idx_b)
+                    .map(|i| DiffAlgorithms::compute_match_details_weighted(&m.function_a, &functions_b[i], &self.config.weights).0)
+                    .max_by(f64::total_cmp)
+                    .unwrap_or(0.0);
+                let gap = m.similarity - runner_up;
+                m.runner_up_gap = Some(gap);
+
+                if !m.match_type.has_fixed_confidence() {
+                    m.confidence = if gap >= CLEAR_WINNER_GAP {
+                        m.confidence + (1.0 - m.confidence) * 0.5
+                    } else {
+                        m.confidence * (0.5 + 0.5 * gap.max(0.0) / CLEAR_WINNER_GAP)
+                    };
+                }
+            });
+        })
+    }
+
+    /// Concatenate a function's instruction bytes, optionally zeroing the
+    /// trailing 4 bytes of long instructions (relocated displacement/address).
+    fn function_bytes(func: &FunctionInfo, mask_relocations: bool) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for instr in &func.instructions {
+            bytes.extend_from_slice(&instr.bytes);
+            if mask_relocations && instr.bytes.len() >= 5 {
+                let end = bytes.len();
+                bytes[end - 4..].fill(0);
+            }
+        }
+        bytes
+    }
+
+    /// Produce a whole-binary basic-block mapping. Functions are matched
+    /// first and used as anchors: blocks are only paired within a matched
+    /// function pair, never across unrelated functions.
+    pub fn diff_basic_blocks_global(
+        &self,
+        functions_a: &[FunctionInfo],
+        functions_b: &[FunctionInfo],
+    ) -> Result<Vec<BlockMatch>> {
+        let matches = self.match_functions(functions_a, functions_b)?;
+
+        self.install(|| {
+            matches
+                .par_iter()
+                .flat_map_iter(|m| Self::match_basic_blocks(&m.function_a, &m.function_b))
+                .collect()
+        })
+    }
+
+    /// Pair the basic blocks of two matched functions.
+    /// Candidate pairs are taken greedily by mnemonic similarity, preferring
+    /// blocks at the same relative position when scores tie; each block is
+    /// used at most once and pairs with zero similarity are not reported.
+    pub fn match_basic_blocks(func_a: &FunctionInfo, func_b: &FunctionInfo) -> Vec<BlockMatch> {
+        let matrix = SimilarityAnalyzer::basic_block_similarity_matrix(func_a, func_b);
+
+        let mut pairs: Vec<(usize, usize, f64)> = Vec::new();
+        for (i, row) in matrix.iter().enumerate() {
+            for (j, &score) in row.iter().enumerate() {
+                let bb_a = &func_a.basic_blocks[i];
+                let bb_b = &func_b.basic_blocks[j];
+                let score = if !bb_a.mnemonic_hash.is_empty() && bb_a.mnemonic_hash == bb_b.mnemonic_hash {
+                    1.0
+                } else {
+                    score
+                };
+                if score > 0.0 {
+                    pairs.push((i, j, score));
+                }
+            }
+        }
+
+        pairs.sort_by(|a, b| {
+            b.2.total_cmp(&a.2)
+                .then_with(|| a.0.abs_diff(a.1).cmp(&b.0.abs_diff(b.1)))
+                .then_with(|| a.0.cmp(&b.0))
+                .then_with(|| a.1.cmp(&b.1))
+        });
+
+        let mut used_a = FxHashSet::default();
+        let mut used_b = FxHashSet::default();
+        let mut block_matches = Vec::new();
+
+        for (i, j, similarity) in pairs {
+            if used_a.contains(&i) || used_b.contains(&j) {
+                continue;
+            }
+            used_a.insert(i);
+            used_b.insert(j);
+            block_matches.push(BlockMatch {
+                function_a_address: func_a.address,
+                function_b_address: func_b.address,
+                block_a_address: func_a.basic_blocks[i].address,
+                block_b_address: func_b.basic_blocks[j].address,
+                similarity,
+            });
+        }
+
+        block_matches.sort_by_key(|bm| bm.block_a_address);
+        block_matches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::generate_mock_functions;
+    use crate::types::InstructionInfo;
+
+    #[test]
+    fn optimal_matching_pairs_identical_binaries() {
+        let functions = generate_mock_functions("binary").unwrap();
+        let matches = MatchingEngine::new().match_functions_optimal(&functions, &functions).unwrap();
+        assert_eq!(matches.len(), functions.len());
+        assert!(matches.iter().all(|m| m.function_a.address == m.function_b.address));
+    }
+
+    #[test]
+    fn global_block_diff_stays_within_matched_functions() {
+        let functions_a = generate_mock_functions("a").unwrap();
+        let functions_b = generate_mock_functions("b").unwrap();
+        let engine = MatchingEngine::new();
+        let block_matches = engine.diff_basic_blocks_global(&functions_a, &functions_b).unwrap();
+        let matches = engine.match_functions(&functions_a, &functions_b).unwrap();
+
+        assert!(!block_matches.is_empty());
+        for block_match in &block_matches {
+            assert!(matches.iter().any(|m| {
+                m.function_a.address == block_match.function_a_address
+                    && m.function_b.address == block_match.function_b_address
+            }));
+        }
+        let blocks_a: usize = matches.iter().map(|m| m.function_a.basic_blocks.len()).sum();
+        assert_eq!(block_matches.len(), blocks_a);
+    }
+
+    #[test]
+    fn byte_verification_downgrades_colliding_exact_matches() {
+        let functions_a = generate_mock_functions("a").unwrap();
+        let mut functions_b = generate_mock_functions("b").unwrap();
+        functions_b[0].instructions[0].bytes = vec![0x90];
+        let config = DiffConfig { verify_exact_bytes: true, ..DiffConfig::default() };
+        let matches = MatchingEngine::new().with_config(config).match_functions(&functions_a, &functions_b).unwrap();
+
+        let main = matches.iter().find(|m| m.function_a.name == "main").unwrap();
+        assert_eq!(main.match_type, MatchType::Structural);
+        assert_eq!(main.notes.len(), 1);
+        assert!(main.confidence < 1.0);
+        assert_eq!(matches.iter().filter(|m| m.match_type == MatchType::Exact).count(), functions_a.len() - 1);
+    }
+
+    fn instruction(mnemonic: &str, operands: &[&str]) -> InstructionInfo {
+        InstructionInfo {
+            mnemonic: mnemonic.to_string(),
+            operands: operands.iter().map(|op| op.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn prologue_epilogue_pass_pairs_renamed_functions() {
+        let function = |name: &str, address: u64, body: Vec<InstructionInfo>, hash: &str| {
+            let mut instructions = vec![
+                instruction("push", &["rbp"]),
+                instruction("mov", &["rbp", "rsp"]),
+                instruction("sub", &["rsp", "0x20"]),
+            ];
+            instructions.extend(body);
+            instructions.extend([instruction("add", &["rsp", "0x20"]), instruction("pop", &["rbp"]), instruction("ret", &[])]);
+            FunctionInfo {
+                name: name.to_string(),
+                address,
+                size: 40,
+                instruction_count: instructions.len(),
+                instructions,
+                cfg_hash: hash.to_string(),
+                call_graph_hash: hash.to_string(),
+                ..Default::default()
+            }
+        };
+        let functions_a = vec![function("foo", 0x1000, vec![instruction("xor", &["eax", "eax"]), instruction("inc", &["eax"])], "a")];
+        let functions_b = vec![function("bar", 0x2000, vec![instruction("mov", &["eax", "1"]), instruction("nop", &[])], "b")];
+
+        let (matches, audit) = MatchingEngine::with_thresholds(0.0, 0.0).match_functions_audited(&functions_a, &functions_b).unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(audit.entries[0].pass, MatchPass::PrologueEpilogue);
+    }
+
+    #[test]
+    fn scored_matching_rescores_conflict_losers() {
+        let f = generate_mock_functions("binary").unwrap().remove(7);
+        let mut h = f.clone();
+        h.name = "h".to_string();
+        h.instructions[0].mnemonic = "nop".to_string();
+        let mut g = f.clone();
+        g.name = "g".to_string();
+        for instr in &mut g.instructions[..3] {
+            instr.mnemonic = "nop".to_string();
+        }
+        // h prefers f, which f itself claims; its second choice is g
+        assert!(
+            DiffAlgorithms::calculate_function_similarity(&h, &f) >= DiffAlgorithms::calculate_function_similarity(&h, &g)
+        );
+
+        let config = DiffConfig { pass_order: vec![MatchPass::Fuzzy], ..DiffConfig::default() };
+        let outcome = MatchingEngine::with_thresholds(0.1, 0.1)
+            .with_config(config)
+            .match_functions_full(&[f.clone(), h], &[f, g])
+            .unwrap();
+        assert_eq!(outcome.matches.len(), 2);
+        assert!(outcome.matches.iter().any(|m| m.function_a.name == "h" && m.function_b.name == "g"));
+    }
+
+    #[test]
+    fn exact_hash_matching_takes_the_most_similar_bucket_member() {
+        let functions = generate_mock_functions("binary").unwrap();
+        let target = functions[7].clone();
+        let mut colliding = functions[1].clone();
+        colliding.cfg_hash = target.cfg_hash.clone();
+        colliding.call_graph_hash = target.call_graph_hash.clone();
+
+        let matches = MatchingEngine::new().match_functions(std::slice::from_ref(&target), &[colliding, target.clone()]).unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].function_b.name, "process_data");
+    }
+
+    fn straight_line(name: &str, address: u64, mnemonics: &[&str]) -> FunctionInfo {
+        let instructions: Vec<InstructionInfo> = mnemonics
+            .iter()
+            .enumerate()
+            .map(|(i, mnemonic)| InstructionInfo {
+                address: address + i as u64,
+                length: 1,
+                bytes: vec![i as u8],
+                ..instruction(mnemonic, &["eax"])
+            })
+            .collect();
+        let block = crate::types::BasicBlockInfo {
+            address,
+            size: instructions.len() as u64,
+            instruction_count: instructions.len(),
+            instructions: instructions.clone(),
+            ..Default::default()
+        };
+        FunctionInfo {
+            name: name.to_string(),
+            address,
+            size: instructions.len() as u64,
+            instruction_count: instructions.len(),
+            instructions,
+            basic_blocks: vec![block],
+            cyclomatic_complexity: 1,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn recalibration_separates_unique_and_tied_matches() {
+        let unique = ["push", "mov", "imul", "shl", "ret"];
+        let tied = ["xor", "and", "or", "cmp", "jne", "lea"];
+        let a = [straight_line("unique", 0x1000, &unique), straight_line("tied", 0x2000, &tied)];
+        let b = [
+            straight_line("unique", 0x5000, &unique),
+            straight_line("tied_a", 0x6000, &tied),
+            // Same address as tied_a: the runner-up is told apart by index
+            straight_line("tied_b", 0x6000, &tied),
+        ];
+        // Keep identical pairs out of Exact, whose confidence is fixed
+        let config = DiffConfig {
+            match_type_thresholds: crate::config::MatchTypeThresholds { exact_similarity: 1.0, ..Default::default() },
+            ..DiffConfig::default()
+        };
+        let engine = MatchingEngine::new().with_config(config);
+
+        let mut matches = vec![engine.match_single_function(&a[0], &b[0]), engine.match_single_function(&a[1], &b[1])];
+        let before: Vec<f64> = matches.iter().map(|m| m.confidence).collect();
+        engine.recalibrate_confidence(&mut matches, &[0, 1], &b).unwrap();
+
+        assert!(matches[0].confidence >= before[0]);
+        assert!(matches[1].confidence < before[1]);
+        assert!(matches[0].confidence > matches[1].confidence + 0.2);
+        assert!(matches[1].runner_up_gap.unwrap().abs() < 1e-9);
+    }
+
+    #[test]
+    fn pipeline_recalibrates_when_enabled() {
+        let functions = generate_mock_functions("binary").unwrap();
+        let config = DiffConfig { recalibrate_confidence: true, ..DiffConfig::default() };
+        let matches = MatchingEngine::new().with_config(config).match_functions(&functions, &functions).unwrap();
+        assert!(!matches.is_empty());
+        assert!(matches.iter().all(|m| m.runner_up_gap.is_some()));
+    }
+
+    #[test]
+    fn each_a_function_is_matched_once_across_passes() {
+        let function = generate_mock_functions("binary").unwrap().remove(7);
+        // A second identical B-function stays free after the exact pass and
+        // would be claimed for the same A-function by every later pass
+        let mut copy = function.clone();
+        copy.address += 0x1000;
+        copy.name = "copy".to_string();
+
+        let matches = MatchingEngine::with_thresholds(0.1, 0.1).match_functions(std::slice::from_ref(&function), &[function.clone(), copy]).unwrap();
+        assert_eq!(matches.len(), 1);
+    }
+
+    #[test]
+    fn name_pass_requires_structural_corroboration_when_enabled() {
+        let functions = generate_mock_functions("binary").unwrap();
+        let mut a = functions[1].clone();
+        a.name = "foo".to_string();
+        let mut b = functions[7].clone();
+        b.name = "foo".to_string();
+
+        let name_only = DiffConfig { pass_order: vec![MatchPass::Name], ..DiffConfig::default() };
+        let engine = MatchingEngine::with_thresholds(0.0, 0.0).with_config(name_only.clone());
+        assert_eq!(engine.match_functions(&[a.clone()], &[b.clone()]).unwrap().len(), 1);
+
+        let corroborated = DiffConfig { require_structural_corroboration: true, ..name_only };
+        let engine = MatchingEngine::with_thresholds(0.0, 0.0).with_config(corroborated);
+        assert!(engine.match_functions(&[a], &[b]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn resolved_thunks_are_left_out_of_matching() {
+        let real = generate_mock_functions("binary").unwrap().remove(7);
+        let thunk = FunctionInfo {
+            name: "j_process_data".to_string(),
+            address: 0x9000,
+            size: 5,
+            instructions: vec![instruction("jmp", &["0x1800"])],
+            ..Default::default()
+        };
+        let functions = [thunk, real];
+
+        let config = DiffConfig { thunk_handling: ThunkHandling::Resolve, ..DiffConfig::default() };
+        let matches = MatchingEngine::new().with_config(config).match_functions(&functions, &functions).unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].function_a.name, "process_data");
+
+        assert_eq!(MatchingEngine::new().match_functions(&functions, &functions).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn audit_records_the_pass_behind_each_claim() {
+        let functions_a = generate_mock_functions("a").unwrap();
+        let mut functions_b = generate_mock_functions("b").unwrap();
+        // Breaks the exact hash match, leaving the pair to the name pass
+        functions_b[2].cfg_hash = "changed".to_string();
+
+        let (matches, audit) = MatchingEngine::new().match_functions_audited(&functions_a, &functions_b).unwrap();
+        assert_eq!(matches.len(), functions_a.len());
+        assert_eq!(audit.entries.len(), matches.len());
+        assert_eq!(audit.claim_for_b(0).unwrap().pass, MatchPass::ExactHash);
+        assert_eq!(audit.claim_for_b(2).unwrap().pass, MatchPass::Name);
+    }
+
+    #[test]
+    fn full_outcome_lists_the_unmatched_indices() {
+        let function = |name: &str, hash: &str| FunctionInfo {
+            name: name.to_string(),
+            address: 0x10,
+            cfg_hash: hash.to_string(),
+            call_graph_hash: hash.to_string(),
+            size: 10,
+            instruction_count: 1,
+            ..Default::default()
+        };
+        let functions_a = [function("x", "h1"), function("y", "h2")];
+        let functions_b = [function("x", "h1")];
+
+        let outcome = MatchingEngine::new().match_functions_full(&functions_a, &functions_b).unwrap();
+        assert_eq!(outcome.matches.len(), 1);
+        assert_eq!(outcome.unmatched_a, vec![1]);
+        assert!(outcome.unmatched_b.is_empty());
+    }
+
+    #[test]
+    fn block_edge_buckets_find_the_fuzzy_pairs() {
+        let functions_a = generate_mock_functions("binary_a").unwrap();
+        let mut functions_b = generate_mock_functions("binary_b").unwrap();
+        for function in &mut functions_b {
+            function.name = format!("sub_{:x}", function.address);
+            function.instructions[0].mnemonic = "nop".to_string();
+        }
+        let pairs = |pass: MatchPass| {
+            let config = DiffConfig { pass_order: vec![pass], ..DiffConfig::default() };
+            let matches = MatchingEngine::new().with_config(config).match_functions(&functions_a, &functions_b).unwrap();
+            let mut pairs: Vec<_> = matches.iter().map(|m| (m.function_a.address, m.function_b.address)).collect();
+            pairs.sort_unstable();
+            pairs
+        };
+        let bucketed = pairs(MatchPass::BlockEdgeBucket);
+        assert!(!bucketed.is_empty());
+        assert_eq!(bucketed, pairs(MatchPass::Fuzzy));
+    }
+
+    #[test]
+    fn custom_pass_order_is_followed_and_validated() {
+        let functions_a = generate_mock_functions("a").unwrap();
+        let functions_b = generate_mock_functions("b").unwrap();
+        let mut config = DiffConfig { pass_order: vec![MatchPass::Structural, MatchPass::ExactHash], ..DiffConfig::default() };
+
+        let (_, audit) = MatchingEngine::new().with_config(config.clone()).match_functions_audited(&functions_a, &functions_b).unwrap();
+        assert_eq!(audit.entries[0].pass, MatchPass::Structural);
+        assert!(audit.entries.iter().all(|e| matches!(e.pass, MatchPass::Structural | MatchPass::ExactHash)));
+
+        config.pass_order = vec![MatchPass::Name, MatchPass::Name];
+        assert!(MatchingEngine::new().with_config(config).match_functions(&functions_a, &functions_b).is_err());
+    }
+
+    #[test]
+    fn match_type_follows_the_configured_thresholds() {
+        let functions = generate_mock_functions("binary").unwrap();
+        let engine = MatchingEngine::new();
+        assert_eq!(engine.match_single_function(&functions[0], &functions[0]).match_type, MatchType::Exact);
+
+        let config = DiffConfig {
+            match_type_thresholds: crate::config::MatchTypeThresholds { exact_similarity: 1.5, structural_confidence: -1.0 },
+            ..DiffConfig::default()
+        };
+        let engine = MatchingEngine::new().with_config(config);
+        assert_eq!(engine.match_single_function(&functions[0], &functions[0]).match_type, MatchType::Structural);
+        assert_eq!(crate::config::MatchTypeThresholds::default().classify(0.5, 0.5), MatchType::Heuristic);
+    }
+
+    #[test]
+    fn evaluation_against_ground_truth() {
+        let pair = |address_a: u64, address_b: u64| FunctionMatch {
+            function_a: FunctionInfo { address: address_a, ..Default::default() },
+            function_b: FunctionInfo { address: address_b, ..Default::default() },
+            ..Default::default()
+        };
+        // The duplicate (1, 10) counts once
+        let matches = [pair(1, 10), pair(2, 20), pair(3, 99), pair(1, 10)];
+        let ground_truth = [(1, 10), (2, 20), (3, 30), (4, 40)];
+
+        let report = MatchingEngine::evaluate(&matches, &ground_truth);
+        assert_eq!(report.true_positives, 2);
+        assert!((report.precision - 2.0 / 3.0).abs() < 1e-12);
+        assert!((report.recall - 0.5).abs() < 1e-12);
+        assert!((report.f1 - 4.0 / 7.0).abs() < 1e-12);
+        assert_eq!(report.false_positives, vec![(3, 99)]);
+        assert_eq!(report.false_negatives, vec![(3, 30), (4, 40)]);
+
+        let empty = MatchingEngine::evaluate(&[], &[]);
+        assert_eq!((empty.precision, empty.recall, empty.f1), (1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn combined_md_index_pass_uses_the_primes_to_pick_the_pair() {
+        let diamond = |address: u64, mnemonics: &[&str]| {
+            let block = |offset: u64, edges: &[u64]| {
+                let function = straight_line("", address + offset, mnemonics);
+                crate::types::BasicBlockInfo {
+                    edges: edges.iter().map(|edge| address + edge).collect(),
+                    ..function.basic_blocks[0].clone()
+                }
+            };
+            let basic_blocks = vec![block(0, &[0x10, 0x20]), block(0x10, &[0x30]), block(0x20, &[0x30]), block(0x30, &[])];
+            let instructions: Vec<InstructionInfo> = basic_blocks.iter().flat_map(|b| b.instructions.clone()).collect();
+            FunctionInfo {
+                name: format!("sub_{:x}", address),
+                address,
+                size: 0x40,
+                instruction_count: instructions.len(),
+                instructions,
+                basic_blocks,
+                cyclomatic_complexity: 2,
+                ..Default::default()
+            }
+        };
+        let a1 = diamond(0x1000, &["mov", "add", "push", "ret"]);
+        let a2 = diamond(0x2000, &["mov", "add", "pop", "ret"]);
+        let b1 = diamond(0x9000, &["mov", "add", "pop", "ret"]);
+        assert_eq!(DiffAlgorithms::calculate_md_index(&a1), DiffAlgorithms::calculate_md_index(&b1));
+
+        let mut config = DiffConfig { pass_order: vec![MatchPass::MdIndexSmallPrimes], ..Default::default() };
+        let engine = MatchingEngine::new().with_config(config.clone());
+        let (matches, audit) = engine.match_functions_audited(&[a1.clone(), a2.clone()], std::slice::from_ref(&b1)).unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].function_a.address, 0x2000);
+        assert_eq!(audit.entries[0].pass, MatchPass::MdIndexSmallPrimes);
+
+        // The MD-Index alone can't tell the two apart and takes the first
+        config.pass_order = vec![MatchPass::MdIndex];
+        let (matches, _) = MatchingEngine::new().with_config(config).match_functions_audited(&[a1, a2], &[b1]).unwrap();
+        assert_eq!(matches[0].function_a.address, 0x1000);
+    }
+
+    #[test]
+    fn symmetric_matching_keeps_only_pairs_the_reverse_run_allows() {
+        let functions_a = generate_mock_functions("binary_a").unwrap();
+        let functions_b = generate_mock_functions("binary_b").unwrap();
+        let engine = MatchingEngine::new();
+        let forward = engine.match_functions(&functions_a, &functions_b).unwrap();
+        let reverse = engine.match_functions(&functions_b, &functions_a).unwrap();
+        let symmetric = engine.match_functions_symmetric(&functions_a, &functions_b).unwrap();
+
+        assert!(symmetric.len() <= forward.len());
+        for m in &symmetric {
+            let confirmed = reverse
+                .iter()
+                .any(|r| r.function_a.address == m.function_b.address && r.function_b.address == m.function_a.address);
+            assert!(confirmed || m.notes.iter().any(|note| note == "Not confirmed by B→A matching"), "{}", m.function_a.name);
+        }
+
+        // Diffing a binary against itself agrees in both directions
+        let identical = engine.match_functions_symmetric(&functions_a, &functions_a).unwrap();
+        assert_eq!(identical.len(), engine.match_functions(&functions_a, &functions_a).unwrap().len());
+        assert!(identical.iter().all(|m| m.notes.is_empty()));
+    }
+
+    #[test]
+    fn function_split_in_two_is_reported_both_ways() {
+        let first_half = ["push", "mov", "sub", "lea", "call", "test", "jz", "xor"];
+        let second_half = ["imul", "shl", "sar", "and", "or", "cmp", "jne", "ret"];
+        let whole: Vec<&str> = first_half.iter().chain(&second_half).copied().collect();
+        let functions_a = vec![straight_line("foo", 0x1000, &whole), straight_line("other", 0x2000, &["nop"; 10])];
+        let functions_b = vec![
+            straight_line("foo_part1", 0x5000, &first_half),
+            straight_line("foo_part2", 0x6000, &second_half),
+            straight_line("zzz", 0x7000, &["hlt"; 10]),
+        ];
+
+        let splits = MatchingEngine::detect_split_merge(&functions_a, &functions_b);
+        assert_eq!(splits.len(), 1, "{:?}", splits);
+        assert_eq!(splits[0].kind, SplitMergeKind::Split);
+        assert_eq!(splits[0].name, "foo");
+        let mut parts: Vec<&str> = splits[0].parts.iter().map(|(name, _)| name.as_str()).collect();
+        parts.sort();
+        assert_eq!(parts, ["foo_part1", "foo_part2"]);
+
+        let merges = MatchingEngine::detect_split_merge(&functions_b, &functions_a);
+        assert_eq!(merges.len(), 1, "{:?}", merges);
+        assert_eq!(merges[0].kind, SplitMergeKind::Merge);
+        assert_eq!(merges[0].name, "foo");
+    }
 }
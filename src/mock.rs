@@ -1,8 +1,19 @@
 use crate::types::*;
+use crate::provider::FunctionProvider;
 use anyhow::Result;
 use log::info;
 use std::hash::Hash;
 
+/// Provider serving `generate_mock_functions` for any binary name.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MockProvider;
+
+impl FunctionProvider for MockProvider {
+    fn functions(&self, binary_name: &str) -> Result<Vec<FunctionInfo>> {
+        generate_mock_functions(binary_name)
+    }
+}
+
 /// Generate mock functions for testing/demo purposes
 pub fn generate_mock_functions(binary_name: &str) -> Result<Vec<FunctionInfo>> {
     info!("Extracting function information from binary: {}", binary_name);
@@ -61,6 +72,7 @@ pub fn generate_mock_functions(binary_name: &str) -> Result<Vec<FunctionInfo>> {
                 edges,
                 mnemonic_hash: format!("bb_hash_{}_{}", i, bb_idx),
                 instruction_count: 3,
+                is_landing_pad: false,
             };
             basic_blocks.push(basic_block);
         }
@@ -84,6 +96,9 @@ pub fn generate_mock_functions(binary_name: &str) -> Result<Vec<FunctionInfo>> {
             cfg_hash,
             instruction_count: (bb_count * 3),
             call_count: if *complexity > 2 { 2 } else { 1 },
+            has_exception_handling: false,
+            pointer_size: 8,
+            endianness: Endianness::Little,
         };
         functions.push(function);
     }
@@ -0,0 +1,197 @@
+//! Canonical instruction tokenization shared by the similarity metrics.
+//!
+//! Every metric that compares instructions across binaries wants the same
+//! view of them: register names, immediates and memory operands abstracted
+//! away, and assembler mnemonic aliases folded together. Keeping that logic
+//! here stops individual metrics from drifting apart.
+
+use crate::types::{Architecture, InstructionInfo};
+
+/// Token substituted for register operands.
+pub const REG_TOKEN: &str = "REG";
+/// Token substituted for immediate operands.
+pub const IMM_TOKEN: &str = "IMM";
+/// Token substituted for memory operands.
+pub const MEM_TOKEN: &str = "MEM";
+/// Token substituted for anything else (symbols, labels, branch targets).
+pub const SYM_TOKEN: &str = "SYM";
+
+/// Normalize a register token to its architectural full-width name.
+/// x86 sub-registers map to their 64-bit parent (`eax`/`ax`/`al` -> `rax`,
+/// `r8d` -> `r8`) and AArch64 `wN` maps to `xN`, so code that only differs
+/// in operand width still shares register usage. Returns `None` for tokens
+/// that are not recognized registers (immediates, symbols, size keywords).
+pub fn normalize_register(token: &str) -> Option<String> {
+    let t = token.to_ascii_lowercase();
+    if t.is_empty() {
+        return None;
+    }
+
+    // x86 / x86-64 general purpose registers
+    let x86_families: [(&str, &[&str]); 8] = [
+        ("rax", &["rax", "eax", "ax", "ah", "al"]),
+        ("rbx", &["rbx", "ebx", "bx", "bh", "bl"]),
+        ("rcx", &["rcx", "ecx", "cx", "ch", "cl"]),
+        ("rdx", &["rdx", "edx", "dx", "dh", "dl"]),
+        ("rsi", &["rsi", "esi", "si", "sil"]),
+        ("rdi", &["rdi", "edi", "di", "dil"]),
+        ("rbp", &["rbp", "ebp", "bp", "bpl"]),
+        ("rsp", &["rsp", "esp", "sp", "spl"]),
+    ];
+    for (full, aliases) in x86_families {
+        if aliases.contains(&t.as_str()) {
+            return Some(full.to_string());
+        }
+    }
+    if t == "rip" || t == "eip" {
+        return Some("rip".to_string());
+    }
+
+    // x86-64 r8..r15 (optional d/w/b width suffix) and ARM32 r0..r15
+    if let Some(rest) = t.strip_prefix('r') {
+        let digits = rest.trim_end_matches(['d', 'w', 'b']);
+        if let Ok(n) = digits.parse::<u32>() {
+            if n <= 15 && rest.len() - digits.len() <= 1 {
+                return Some(format!("r{}", n));
+            }
+        }
+    }
+
+    // SIMD registers
+    for prefix in ["xmm", "ymm", "zmm"] {
+        if let Some(rest) = t.strip_prefix(prefix) {
+            if rest.parse::<u32>().is_ok() {
+                return Some(format!("xmm{}", rest));
+            }
+        }
+    }
+
+    // AArch64 x0..x30 / w0..w30
+    if let Some(rest) = t.strip_prefix('x').or_else(|| t.strip_prefix('w')) {
+        if let Ok(n) = rest.parse::<u32>() {
+            if n <= 30 {
+                return Some(format!("x{}", n));
+            }
+        }
+    }
+
+    // ARM special registers and MIPS `$`-prefixed registers
+    match t.as_str() {
+        "lr" | "pc" | "fp" | "xzr" | "wzr" => Some(t),
+        _ if t.starts_with('$') && t.len() > 1 => Some(t),
+        _ => None,
+    }
+}
+
+/// Lowercase a mnemonic and fold assembler aliases into one spelling, so
+/// `jz`/`je` or `retn`/`ret` produce the same token.
+pub fn canonical_mnemonic(mnemonic: &str, arch: Architecture) -> String {
+    let m = mnemonic.trim().to_ascii_lowercase();
+
+    let canonical = match arch {
+        Architecture::X86 | Architecture::X86_64 | Architecture::Unknown => match m.as_str() {
+            "jz" => "je",
+            "jnz" => "jne",
+            "jc" | "jnae" => "jb",
+            "jnc" | "jnb" => "jae",
+            "jna" => "jbe",
+            "jnbe" => "ja",
+            "jnge" => "jl",
+            "jnl" => "jge",
+            "jng" => "jle",
+            "jnle" => "jg",
+            "jpe" => "jp",
+            "jpo" => "jnp",
+            "sal" => "shl",
+            "retn" | "retq" => "ret",
+            "cmovz" => "cmove",
+            "cmovnz" => "cmovne",
+            "setz" => "sete",
+            "setnz" => "setne",
+            _ => m.as_str(),
+        },
+        Architecture::Arm | Architecture::Aarch64 => match m.as_str() {
+            "b.eq" => "beq",
+            "b.ne" => "bne",
+            "b.lt" => "blt",
+            "b.le" => "ble",
+            "b.gt" => "bgt",
+            "b.ge" => "bge",
+            "b.hs" | "b.cs" | "bcs" => "bhs",
+            "b.lo" | "b.cc" | "bcc" => "blo",
+            _ => m.as_str(),
+        },
+        Architecture::Mips => m.as_str(),
+    };
+
+    canonical.to_string()
+}
+
+/// Classify a single operand as one of the abstract operand tokens.
+pub fn classify_operand(operand: &str, arch: Architecture) -> &'static str {
+    let lower = operand.trim().to_ascii_lowercase();
+
+    // MIPS registers are `$`-prefixed, including numeric ones such as `$5`
+    if arch == Architecture::Mips && lower.starts_with('$') {
+        return REG_TOKEN;
+    }
+
+    if lower.contains('[') || lower.contains('(') || lower.contains(" ptr") {
+        return MEM_TOKEN;
+    }
+
+    let literal = lower
+        .trim_start_matches(['#', '$'])
+        .trim_start_matches(['-', '+']);
+    if literal.starts_with("0x") || literal.starts_with(|c: char| c.is_ascii_digit()) {
+        return IMM_TOKEN;
+    }
+
+    // AT&T syntax prefixes registers with `%`
+    if normalize_register(lower.trim_start_matches('%')).is_some() {
+        return REG_TOKEN;
+    }
+
+    SYM_TOKEN
+}
+
+/// Render one instruction as a canonical token, e.g. `mov REG,MEM`.
+pub fn normalize_instruction(instr: &InstructionInfo, arch: Architecture) -> String {
+    let mnemonic = canonical_mnemonic(&instr.mnemonic, arch);
+    if instr.operands.is_empty() {
+        return mnemonic;
+    }
+
+    let operands: Vec<&str> = instr.operands.iter().map(|op| classify_operand(op, arch)).collect();
+    format!("{} {}", mnemonic, operands.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FunctionInfo;
+
+    #[test]
+    fn normalized_instructions_fold_operands_and_aliases() {
+        let instruction = |mnemonic: &str, operands: &[&str]| InstructionInfo {
+            mnemonic: mnemonic.to_string(),
+            operands: operands.iter().map(|op| op.to_string()).collect(),
+            ..Default::default()
+        };
+        let function = FunctionInfo {
+            instructions: vec![
+                instruction("MOV", &["eax", "dword ptr [rbp-0x8]"]),
+                instruction("jz", &["sub_1000"]),
+                instruction("add", &["%r8d", "$0x10"]),
+                instruction("retn", &[]),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(
+            function.normalized_instructions(Architecture::X86_64),
+            vec!["mov REG,MEM", "je SYM", "add REG,IMM", "ret"]
+        );
+        // `$` marks a register on MIPS, not an immediate
+        assert_eq!(classify_operand("$5", Architecture::Mips), "REG");
+    }
+}
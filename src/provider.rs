@@ -0,0 +1,131 @@
+use crate::types::FunctionInfo;
+use anyhow::{Result, Context};
+use serde::Deserialize;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::Path;
+
+/// A source of function information for a binary: the built-in mock data,
+/// a Binary Ninja view, an export on disk and so on. `BinaryDiffEngine::
+/// perform_diff_with` diffs whatever a provider returns.
+pub trait FunctionProvider {
+    /// Every function of the binary identified by `binary_name`, whose
+    /// meaning (file path, view name, key) is up to the provider.
+    fn functions(&self, binary_name: &str) -> Result<Vec<FunctionInfo>>;
+}
+
+/// Provider reading pre-extracted functions from JSON dumps, so binaries can
+/// be diffed without Binary Ninja. The binary name is the dump's path; the
+/// file holds a `Vec<FunctionInfo>` in its serde form, as written by
+/// `JsonFileProvider::save`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonFileProvider;
+
+impl JsonFileProvider {
+    /// Write `functions` to `output_path` in the format `functions` reads.
+    pub fn save(functions: &[FunctionInfo], output_path: &Path) -> Result<()> {
+        let json_data = serde_json::to_string(functions)
+            .context("Failed to serialize function dump")?;
+
+        fs::write(output_path, json_data)
+            .with_context(|| format!("Failed to write function dump {}", output_path.display()))?;
+
+        Ok(())
+    }
+}
+
+impl FunctionProvider for JsonFileProvider {
+    fn functions(&self, binary_name: &str) -> Result<Vec<FunctionInfo>> {
+        let file = File::open(binary_name)
+            .with_context(|| format!("Failed to open function dump {}", binary_name))?;
+
+        let mut deserializer = serde_json::Deserializer::from_reader(BufReader::new(file));
+        let functions = Vec::<FunctionInfo>::deserialize(&mut deserializer)
+            .with_context(|| format!("Malformed function dump {}", binary_name))?;
+        deserializer.end()
+            .with_context(|| format!("Trailing data after functions in {}", binary_name))?;
+
+        Ok(functions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockProvider;
+    use crate::types::{BasicBlockInfo, InstructionInfo};
+    use crate::BinaryDiffEngine;
+
+    /// Serves a fixed pair of functions under the name "in_memory".
+    struct InMemoryProvider;
+
+    impl FunctionProvider for InMemoryProvider {
+        fn functions(&self, binary_name: &str) -> Result<Vec<FunctionInfo>> {
+            anyhow::ensure!(binary_name == "in_memory", "Unknown binary {}", binary_name);
+            let function = |name: &str, address: u64| {
+                let instructions: Vec<InstructionInfo> = ["push", "mov", "add", "ret"]
+                    .iter()
+                    .enumerate()
+                    .map(|(i, m)| InstructionInfo {
+                        address: address + i as u64,
+                        mnemonic: m.to_string(),
+                        length: 1,
+                        bytes: vec![0x90],
+                        ..Default::default()
+                    })
+                    .collect();
+                FunctionInfo {
+                    name: name.to_string(),
+                    address,
+                    size: 4,
+                    instruction_count: 4,
+                    basic_blocks: vec![BasicBlockInfo {
+                        address,
+                        size: 4,
+                        instructions: instructions.clone(),
+                        instruction_count: 4,
+                        ..Default::default()
+                    }],
+                    instructions,
+                    ..Default::default()
+                }
+            };
+            Ok(vec![function("alpha", 0x10), function("beta", 0x100)])
+        }
+    }
+
+    #[test]
+    fn diffs_whatever_the_provider_returns() {
+        let engine = BinaryDiffEngine::new();
+        let result = engine.perform_diff_with(&InMemoryProvider, "in_memory", "in_memory").unwrap();
+        assert_eq!(result.matched_functions.len(), 2);
+        assert!(engine.perform_diff_with(&InMemoryProvider, "in_memory", "missing").is_err());
+
+        let mock = engine.perform_diff_with(&MockProvider, "binary_a", "binary_b").unwrap();
+        let direct = engine.perform_diff_mock("binary_a", "binary_b").unwrap();
+        assert_eq!(mock.matched_functions.len(), direct.matched_functions.len());
+    }
+
+    #[test]
+    fn json_dump_round_trips_and_names_bad_files() {
+        let dir = std::env::temp_dir().join("rust_diff_json_provider_test");
+        fs::create_dir_all(&dir).unwrap();
+        let dump = dir.join("a.json");
+        let functions = MockProvider.functions("binary_a").unwrap();
+
+        JsonFileProvider::save(&functions, &dump).unwrap();
+        let loaded = JsonFileProvider.functions(dump.to_str().unwrap()).unwrap();
+        assert_eq!(serde_json::to_string(&loaded).unwrap(), serde_json::to_string(&functions).unwrap());
+        let result = BinaryDiffEngine::new()
+            .perform_diff_with(&JsonFileProvider, dump.to_str().unwrap(), dump.to_str().unwrap())
+            .unwrap();
+        assert_eq!(result.matched_functions.len(), functions.len());
+
+        let truncated = dir.join("truncated.json");
+        fs::write(&truncated, "[{\"name\": \"x\"").unwrap();
+        let error = JsonFileProvider.functions(truncated.to_str().unwrap()).unwrap_err();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(format!("{:#}", error).contains("truncated.json"), "{:#}", error);
+        assert!(JsonFileProvider.functions("/nonexistent/dump.json").is_err());
+    }
+}
@@ -0,0 +1,196 @@
+use crate::algorithms::DiffAlgorithms;
+use crate::matching::MatchingEngine;
+use crate::types::{FunctionInfo, FunctionMatch};
+use anyhow::{Result, bail};
+use rustc_hash::{FxHashMap, FxHashSet};
+
+/// An interactive diff that keeps the pairwise similarity matrix of two
+/// binaries, so refining the result (pinning or rejecting matches) only
+/// re-solves the assignment instead of rescoring every pair.
+///
+/// Matching follows `MatchingEngine::match_functions_optimal`: pinned pairs
+/// are kept as `Manual` matches, and the remaining functions are assigned to
+/// maximize total similarity, never pairing anything below the engine's
+/// thresholds or a pair rejected with `remove_match`.
+pub struct DiffSession {
+    engine: MatchingEngine,
+    functions_a: Vec<FunctionInfo>,
+    functions_b: Vec<FunctionInfo>,
+    /// Similarity of every A×B pair, 0.0 where matching is not allowed.
+    scores: Vec<Vec<f64>>,
+    /// User-pinned (index_a, index_b) pairs.
+    pinned: Vec<(usize, usize)>,
+    /// Pairs the user rejected; the assignment leaves them alone.
+    rejected: FxHashSet<(usize, usize)>,
+    /// Built matches by (index_a, index_b), reused across recomputes.
+    cache: FxHashMap<(usize, usize), FunctionMatch>,
+    assigned: Vec<(usize, usize)>,
+}
+
+impl DiffSession {
+    /// Score every pair once and compute the initial matches. Pins already
+    /// in the engine's `DiffConfig::manual_matches` are applied.
+    pub fn new(engine: MatchingEngine, functions_a: Vec<FunctionInfo>, functions_b: Vec<FunctionInfo>) -> Result<Self> {
+        engine.config().validate()?;
+        let scores = engine.similarity_matrix(&functions_a, &functions_b)?;
+        let manual_matches = engine.config().manual_matches.clone();
+
+        let mut session = Self {
+            engine,
+            functions_a,
+            functions_b,
+            scores,
+            pinned: Vec::new(),
+            rejected: FxHashSet::default(),
+            cache: FxHashMap::default(),
+            assigned: Vec::new(),
+        };
+        for (address_a, address_b) in manual_matches {
+            if let (Some(idx_a), Some(idx_b)) = (session.index_a(address_a), session.index_b(address_b)) {
+                session.pin(idx_a, idx_b);
+            }
+        }
+        session.recompute();
+        Ok(session)
+    }
+
+    /// Pin the function at `address_a` in A to the one at `address_b` in B,
+    /// replacing any pin involving either. Takes effect on `recompute`.
+    pub fn apply_manual_match(&mut self, address_a: u64, address_b: u64) -> Result<()> {
+        let (idx_a, idx_b) = match (self.index_a(address_a), self.index_b(address_b)) {
+            (Some(idx_a), Some(idx_b)) => (idx_a, idx_b),
+            _ => bail!("No functions at 0x{:x} in A and 0x{:x} in B", address_a, address_b),
+        };
+        self.pin(idx_a, idx_b);
+        Ok(())
+    }
+
+    /// Drop the match between `address_a` and `address_b`: a pin is removed,
+    /// and the pair is never matched again by the assignment. Takes effect
+    /// on `recompute`. Returns false if the pair isn't currently matched.
+    pub fn remove_match(&mut self, address_a: u64, address_b: u64) -> bool {
+        let (idx_a, idx_b) = match (self.index_a(address_a), self.index_b(address_b)) {
+            (Some(idx_a), Some(idx_b)) => (idx_a, idx_b),
+            _ => return false,
+        };
+        let matched = self.pinned.contains(&(idx_a, idx_b)) || self.assigned.contains(&(idx_a, idx_b));
+        if matched {
+            self.pinned.retain(|&pair| pair != (idx_a, idx_b));
+            self.rejected.insert((idx_a, idx_b));
+        }
+        matched
+    }
+
+    /// Re-solve the assignment over the functions not covered by a pin,
+    /// using the cached similarity matrix.
+    pub fn recompute(&mut self) {
+        let pinned_a: FxHashSet<usize> = self.pinned.iter().map(|&(idx_a, _)| idx_a).collect();
+        let pinned_b: FxHashSet<usize> = self.pinned.iter().map(|&(_, idx_b)| idx_b).collect();
+        let free_a: Vec<usize> = (0..self.functions_a.len()).filter(|idx| !pinned_a.contains(idx)).collect();
+        let free_b: Vec<usize> = (0..self.functions_b.len()).filter(|idx| !pinned_b.contains(idx)).collect();
+
+        let scores: Vec<Vec<f64>> = free_a
+            .iter()
+            .map(|&idx_a| {
+                free_b
+                    .iter()
+                    .map(|&idx_b| if self.rejected.contains(&(idx_a, idx_b)) { 0.0 } else { self.scores[idx_a][idx_b] })
+                    .collect()
+            })
+            .collect();
+
+        self.assigned = DiffAlgorithms::optimal_assignment(&scores)
+            .into_iter()
+            .enumerate()
+            .filter_map(|(row, col)| col.filter(|&col| scores[row][col] > 0.0).map(|col| (free_a[row], free_b[col])))
+            .collect();
+
+        for &(idx_a, idx_b) in &self.assigned {
+            if !self.cache.contains_key(&(idx_a, idx_b)) {
+                let m = self.engine.match_single_function(&self.functions_a[idx_a], &self.functions_b[idx_b]);
+                self.cache.insert((idx_a, idx_b), m);
+            }
+        }
+    }
+
+    /// Pinned matches first, in pinning order, then the assigned ones in A
+    /// order.
+    pub fn matches(&self) -> Vec<FunctionMatch> {
+        let pinned = self
+            .pinned
+            .iter()
+            .map(|&(idx_a, idx_b)| self.engine.manual_match(&self.functions_a[idx_a], &self.functions_b[idx_b]));
+        let assigned = self.assigned.iter().map(|pair| self.cache[pair].clone());
+        pinned.chain(assigned).collect()
+    }
+
+    /// Functions of A without a match.
+    pub fn unmatched_a(&self) -> Vec<&FunctionInfo> {
+        let matched: FxHashSet<usize> = self.pairs().map(|(idx_a, _)| idx_a).collect();
+        self.functions_a.iter().enumerate().filter(|(idx, _)| !matched.contains(idx)).map(|(_, f)| f).collect()
+    }
+
+    /// Functions of B without a match.
+    pub fn unmatched_b(&self) -> Vec<&FunctionInfo> {
+        let matched: FxHashSet<usize> = self.pairs().map(|(_, idx_b)| idx_b).collect();
+        self.functions_b.iter().enumerate().filter(|(idx, _)| !matched.contains(idx)).map(|(_, f)| f).collect()
+    }
+
+    fn pairs(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.pinned.iter().chain(&self.assigned).copied()
+    }
+
+    fn pin(&mut self, idx_a: usize, idx_b: usize) {
+        self.pinned.retain(|&(a, b)| a != idx_a && b != idx_b);
+        self.pinned.push((idx_a, idx_b));
+        self.rejected.remove(&(idx_a, idx_b));
+    }
+
+    fn index_a(&self, address: u64) -> Option<usize> {
+        self.functions_a.iter().position(|f| f.address == address)
+    }
+
+    fn index_b(&self, address: u64) -> Option<usize> {
+        self.functions_b.iter().position(|f| f.address == address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::generate_mock_functions;
+    use crate::types::MatchType;
+
+    #[test]
+    fn pins_and_rejections_survive_recompute() {
+        let functions_a = generate_mock_functions("binary_a").unwrap();
+        let functions_b = generate_mock_functions("binary_b").unwrap();
+        let optimal = MatchingEngine::new().match_functions_optimal(&functions_a, &functions_b).unwrap();
+        let mut session = DiffSession::new(MatchingEngine::new(), functions_a.clone(), functions_b.clone()).unwrap();
+        assert_eq!(session.matches().len(), optimal.len());
+
+        let (address_a, address_b) = (functions_a[0].address, functions_b[functions_b.len() - 1].address);
+        session.apply_manual_match(address_a, address_b).unwrap();
+        session.recompute();
+        let matches = session.matches();
+        assert_eq!((matches[0].function_a.address, matches[0].function_b.address), (address_a, address_b));
+        assert_eq!(matches[0].match_type, MatchType::Manual);
+        let mut seen_a = FxHashSet::default();
+        let mut seen_b = FxHashSet::default();
+        assert!(matches.iter().all(|m| seen_a.insert(m.function_a.address) && seen_b.insert(m.function_b.address)));
+        assert_eq!(matches.len() + session.unmatched_a().len(), functions_a.len());
+
+        // Rejecting another match keeps the pin, and the pair stays apart
+        let rejected = (matches[1].function_a.address, matches[1].function_b.address);
+        assert!(session.remove_match(rejected.0, rejected.1));
+        session.recompute();
+        session.recompute();
+        let matches = session.matches();
+        assert!(matches.iter().all(|m| (m.function_a.address, m.function_b.address) != rejected));
+        assert_eq!((matches[0].function_a.address, matches[0].function_b.address), (address_a, address_b));
+        assert_eq!(matches[0].match_type, MatchType::Manual);
+
+        assert!(!session.remove_match(rejected.0, rejected.1));
+        assert!(session.apply_manual_match(0xdead, address_b).is_err());
+    }
+}
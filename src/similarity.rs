@@ -1,10 +1,57 @@
-use crate::{FunctionInfo, BasicBlockInfo, InstructionInfo};
+use crate::{FunctionInfo, FunctionMatch, BasicBlockInfo, ByteDiffKind, ByteDiffSpan, InstructionDiffEntry, InstructionInfo, OperandChange};
+use crate::normalize;
 use std::collections::HashMap;
-use rustc_hash::FxHashSet;
+use rustc_hash::{FxHashMap, FxHashSet};
 use petgraph::Graph;
+use petgraph::algo::{condensation, toposort};
+use petgraph::graph::NodeIndex;
+use std::collections::VecDeque;
+use std::cell::OnceCell;
 
 pub struct SimilarityAnalyzer;
 
+/// Largest changed region (elements of A × elements of B, after trimming the
+/// common prefix and suffix) that `SimilarityAnalyzer::lcs_alignment`
+/// aligns element by element; a bigger region is reported as removed then
+/// added as a whole, keeping the table to a few tens of megabytes.
+const ALIGNMENT_MAX_CELLS: usize = 4 * 1024 * 1024;
+
+/// Inverted index from each string/constant reference to the functions that
+/// use it. Looking up a function's references yields the functions sharing
+/// at least one rare reference with it, without comparing reference sets
+/// pairwise.
+pub struct ReferenceIndex {
+    postings: FxHashMap<String, Vec<usize>>,
+}
+
+impl ReferenceIndex {
+    /// Index the references of every function, by position in `functions`.
+    pub fn build(functions: &[FunctionInfo]) -> Self {
+        let mut postings: FxHashMap<String, Vec<usize>> = FxHashMap::default();
+        for (idx, func) in functions.iter().enumerate() {
+            for reference in SimilarityAnalyzer::extract_references(func) {
+                postings.entry(reference).or_default().push(idx);
+            }
+        }
+        Self { postings }
+    }
+
+    /// Indexed functions sharing with `func` at least one reference used by
+    /// no more than `max_frequency` indexed functions, in ascending order.
+    pub fn candidates(&self, func: &FunctionInfo, max_frequency: usize) -> Vec<usize> {
+        let mut candidates: Vec<usize> = SimilarityAnalyzer::extract_references(func)
+            .iter()
+            .filter_map(|reference| self.postings.get(reference))
+            .filter(|posting| posting.len() <= max_frequency)
+            .flatten()
+            .copied()
+            .collect();
+        candidates.sort_unstable();
+        candidates.dedup();
+        candidates
+    }
+}
+
 impl SimilarityAnalyzer {
     /// Calculate Jaccard similarity between two sets of strings
     pub fn jaccard_similarity(set_a: &FxHashSet<String>, set_b: &FxHashSet<String>) -> f64 {
@@ -18,13 +65,15 @@ impl SimilarityAnalyzer {
         }
     }
 
-    /// Calculate cosine similarity between two frequency vectors
+    /// Calculate cosine similarity between two frequency vectors.
+    /// Like `jaccard_similarity`, two empty vectors (no entry with a non-zero
+    /// count) are identical and score 1.0, while an empty vector against a
+    /// non-empty one scores 0.0.
     pub fn cosine_similarity(freq_a: &HashMap<String, usize>, freq_b: &HashMap<String, usize>) -> f64 {
         let mut dot_product = 0.0;
         let mut norm_a = 0.0;
-        let mut norm_b = 0.0;
         
-        // Calculate dot product and norms
+        // Calculate dot product and norm of A
         for (key, &count_a) in freq_a {
             norm_a += (count_a as f64).powi(2);
             if let Some(&count_b) = freq_b.get(key) {
@@ -32,43 +81,238 @@ impl SimilarityAnalyzer {
             }
         }
         
-        for &count_b in freq_b.values() {
-            norm_b += (count_b as f64).powi(2);
+        // Without overlap the score is 0.0 unless both are empty; B's norm
+        // is only needed to tell those apart
+        if dot_product == 0.0 {
+            let b_empty = freq_b.values().all(|&count| count == 0);
+            return if norm_a == 0.0 && b_empty { 1.0 } else { 0.0 };
         }
         
-        if norm_a == 0.0 || norm_b == 0.0 {
-            return 0.0;
-        }
-        
-        dot_product / (norm_a.sqrt() * norm_b.sqrt())
+        let norm_b: f64 = freq_b.values().map(|&count| (count as f64).powi(2)).sum();
+        (dot_product / (norm_a.sqrt() * norm_b.sqrt())).min(1.0)
     }
 
-    /// Calculate edit distance between two strings
+    /// Calculate edit distance between two strings, in characters. Uses a
+    /// rolling two-row buffer over the shorter string, so memory is
+    /// O(min(len)).
     pub fn edit_distance(s1: &str, s2: &str) -> usize {
-        let len1 = s1.len();
-        let len2 = s2.len();
-        let mut dp = vec![vec![0; len2 + 1]; len1 + 1];
-        
-        // Initialize base cases
-        for i in 0..=len1 {
-            dp[i][0] = i;
-        }
-        for j in 0..=len2 {
-            dp[0][j] = j;
+        let a: Vec<char> = s1.chars().collect();
+        let b: Vec<char> = s2.chars().collect();
+        let (long, short) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+
+        // prev[j] = distance between the first i-1 chars of `long` and the
+        // first j chars of `short`
+        let mut prev: Vec<usize> = (0..=short.len()).collect();
+        let mut curr = vec![0; short.len() + 1];
+
+        for (i, &ch_long) in long.iter().enumerate() {
+            curr[0] = i + 1;
+            for (j, &ch_short) in short.iter().enumerate() {
+                curr[j + 1] = if ch_long == ch_short {
+                    prev[j]
+                } else {
+                    1 + prev[j + 1].min(curr[j]).min(prev[j])
+                };
+            }
+            std::mem::swap(&mut prev, &mut curr);
         }
-        
-        // Fill DP table
-        for i in 1..=len1 {
-            for j in 1..=len2 {
-                if s1.chars().nth(i - 1) == s2.chars().nth(j - 1) {
-                    dp[i][j] = dp[i - 1][j - 1];
+
+        prev[short.len()]
+    }
+
+    /// Align two sequences along their longest common subsequence.
+    /// Returns one entry per output row: `(Some(i), Some(j))` for equal
+    /// elements, `(Some(i), None)` for elements only in `a` and
+    /// `(None, Some(j))` for elements only in `b`. Within a changed region,
+    /// removals are listed before additions.
+    ///
+    /// The common prefix and suffix are aligned directly. If what remains is
+    /// larger than `ALIGNMENT_MAX_CELLS`, it is reported as removed then
+    /// added without searching for common elements inside it.
+    pub fn lcs_alignment<T: PartialEq>(a: &[T], b: &[T]) -> Vec<(Option<usize>, Option<usize>)> {
+        let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+        let suffix = a[prefix..]
+            .iter()
+            .rev()
+            .zip(b[prefix..].iter().rev())
+            .take_while(|(x, y)| x == y)
+            .count();
+        let (n, m) = (a.len() - suffix, b.len() - suffix);
+
+        let mut alignment = Vec::with_capacity(a.len().max(b.len()));
+        alignment.extend((0..prefix).map(|k| (Some(k), Some(k))));
+        if (n - prefix).saturating_mul(m - prefix) <= ALIGNMENT_MAX_CELLS {
+            let (rows, cols) = (n - prefix, m - prefix);
+            // lcs[i][j] = LCS length of a[prefix + i..n] and b[prefix + j..m]
+            let mut lcs = vec![vec![0u32; cols + 1]; rows + 1];
+            for i in (0..rows).rev() {
+                for j in (0..cols).rev() {
+                    lcs[i][j] = if a[prefix + i] == b[prefix + j] {
+                        lcs[i + 1][j + 1] + 1
+                    } else {
+                        lcs[i + 1][j].max(lcs[i][j + 1])
+                    };
+                }
+            }
+
+            let (mut i, mut j) = (0, 0);
+            while i < rows && j < cols {
+                if a[prefix + i] == b[prefix + j] {
+                    alignment.push((Some(prefix + i), Some(prefix + j)));
+                    i += 1;
+                    j += 1;
+                } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+                    alignment.push((Some(prefix + i), None));
+                    i += 1;
                 } else {
-                    dp[i][j] = 1 + dp[i - 1][j].min(dp[i][j - 1]).min(dp[i - 1][j - 1]);
+                    alignment.push((None, Some(prefix + j)));
+                    j += 1;
                 }
             }
+            alignment.extend((prefix + i..n).map(|i| (Some(i), None)));
+            alignment.extend((prefix + j..m).map(|j| (None, Some(j))));
+        } else {
+            alignment.extend((prefix..n).map(|i| (Some(i), None)));
+            alignment.extend((prefix..m).map(|j| (None, Some(j))));
         }
-        
-        dp[len1][len2]
+        alignment.extend((0..suffix).map(|k| (Some(n + k), Some(m + k))));
+        alignment
+    }
+
+    /// Byte-level diff of the two functions' concatenated instruction
+    /// bytes, as consecutive spans covering both sides. Catches patched
+    /// immediates and displacements that keep the mnemonic, which the
+    /// instruction diffs treat as equal or as operand changes.
+    pub fn byte_level_diff(func_a: &FunctionInfo, func_b: &FunctionInfo) -> Vec<ByteDiffSpan> {
+        fn flatten(func: &FunctionInfo) -> (Vec<u8>, Vec<u64>) {
+            let mut bytes = Vec::new();
+            let mut offsets = Vec::new();
+            for instr in &func.instructions {
+                let base = instr.address.wrapping_sub(func.address);
+                for (k, &byte) in instr.bytes.iter().enumerate() {
+                    bytes.push(byte);
+                    offsets.push(base + k as u64);
+                }
+            }
+            (bytes, offsets)
+        }
+        // Offset of the byte at `index`, or just past the last byte
+        fn offset_at(offsets: &[u64], index: usize) -> u64 {
+            offsets.get(index).copied().unwrap_or_else(|| offsets.last().map_or(0, |&last| last + 1))
+        }
+
+        let (bytes_a, offsets_a) = flatten(func_a);
+        let (bytes_b, offsets_b) = flatten(func_b);
+
+        // Whether each aligned step is an equal byte (true) or a change,
+        // with the number of A and B bytes it consumes
+        let steps: Vec<(bool, usize, usize)> = Self::lcs_alignment(&bytes_a, &bytes_b)
+            .into_iter()
+            .map(|(a, b)| (a.is_some() && b.is_some(), usize::from(a.is_some()), usize::from(b.is_some())))
+            .collect();
+
+        let mut spans = Vec::new();
+        let (mut i, mut j, mut k) = (0, 0, 0);
+        while k < steps.len() {
+            let equal = steps[k].0;
+            let (start_i, start_j) = (i, j);
+            while k < steps.len() && steps[k].0 == equal {
+                i += steps[k].1;
+                j += steps[k].2;
+                k += 1;
+            }
+            let (len_a, len_b) = (i - start_i, j - start_j);
+            let kind = match (equal, len_a, len_b) {
+                (true, _, _) => ByteDiffKind::Equal,
+                (false, 0, _) => ByteDiffKind::Inserted,
+                (false, _, 0) => ByteDiffKind::Removed,
+                (false, _, _) => ByteDiffKind::Changed,
+            };
+            if len_a + len_b > 0 {
+                spans.push(ByteDiffSpan {
+                    kind,
+                    offset_a: offset_at(&offsets_a, start_i),
+                    len_a,
+                    offset_b: offset_at(&offsets_b, start_j),
+                    len_b,
+                });
+            }
+        }
+        spans
+    }
+
+    /// Per-instruction diff of two functions, aligned along the longest
+    /// common subsequence of their instructions (compared case- and
+    /// whitespace-insensitively). Within each changed region, a removed
+    /// instruction is paired with the next added one sharing its mnemonic
+    /// and operand count and reported as `Modified` with the differing
+    /// operands, instead of as a removal plus an addition.
+    pub fn instruction_diff(func_a: &FunctionInfo, func_b: &FunctionInfo) -> Vec<InstructionDiffEntry> {
+        fn normalize_operand(op: &str) -> String {
+            op.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+        }
+        fn key(instr: &InstructionInfo) -> (String, Vec<String>) {
+            (
+                instr.mnemonic.trim().to_lowercase(),
+                instr.operands.iter().map(|op| normalize_operand(op)).collect(),
+            )
+        }
+
+        let keys_a: Vec<_> = func_a.instructions.iter().map(key).collect();
+        let keys_b: Vec<_> = func_b.instructions.iter().map(key).collect();
+        let alignment = Self::lcs_alignment(&keys_a, &keys_b);
+
+        let mut entries = Vec::with_capacity(alignment.len());
+        let mut removed: Vec<usize> = Vec::new();
+        let mut added: Vec<usize> = Vec::new();
+        let flush = |removed: &mut Vec<usize>, added: &mut Vec<usize>, entries: &mut Vec<InstructionDiffEntry>| {
+            let mut next_added = 0;
+            for &index_a in removed.iter() {
+                let (mnemonic_a, operands_a) = &keys_a[index_a];
+                let partner = added[next_added..].iter().position(|&index_b| {
+                    let (mnemonic_b, operands_b) = &keys_b[index_b];
+                    mnemonic_a == mnemonic_b && operands_a.len() == operands_b.len()
+                });
+                match partner {
+                    Some(offset) => {
+                        let index_b = added[next_added + offset];
+                        entries.extend(added[next_added..next_added + offset].iter().map(|&index_b| InstructionDiffEntry::Added { index_b }));
+                        next_added += offset + 1;
+
+                        let old_operands = &func_a.instructions[index_a].operands;
+                        let new_operands = &func_b.instructions[index_b].operands;
+                        let operand_changes = (0..old_operands.len())
+                            .filter(|&position| keys_a[index_a].1[position] != keys_b[index_b].1[position])
+                            .map(|position| OperandChange {
+                                position,
+                                old: old_operands[position].clone(),
+                                new: new_operands[position].clone(),
+                            })
+                            .collect();
+                        entries.push(InstructionDiffEntry::Modified { index_a, index_b, operand_changes });
+                    }
+                    None => entries.push(InstructionDiffEntry::Removed { index_a }),
+                }
+            }
+            entries.extend(added[next_added..].iter().map(|&index_b| InstructionDiffEntry::Added { index_b }));
+            removed.clear();
+            added.clear();
+        };
+
+        for (a, b) in alignment {
+            match (a, b) {
+                (Some(index_a), Some(index_b)) => {
+                    flush(&mut removed, &mut added, &mut entries);
+                    entries.push(InstructionDiffEntry::Equal { index_a, index_b });
+                }
+                (Some(index_a), None) => removed.push(index_a),
+                (None, Some(index_b)) => added.push(index_b),
+                (None, None) => {}
+            }
+        }
+        flush(&mut removed, &mut added, &mut entries);
+
+        entries
     }
 
     /// Calculate normalized edit distance (0.0 to 1.0)
@@ -119,6 +363,63 @@ impl SimilarityAnalyzer {
         Self::normalized_edit_distance(&seq_a, &seq_b)
     }
 
+    /// Length of the longest common subsequence of the two mnemonic
+    /// sequences, normalized by the longer sequence. Unlike edit distance it
+    /// only rewards preserved order, so instructions interleaved with new
+    /// ones still count.
+    pub fn lcs_mnemonic_similarity(instrs_a: &[InstructionInfo], instrs_b: &[InstructionInfo]) -> f64 {
+        let longer = instrs_a.len().max(instrs_b.len());
+        if longer == 0 {
+            return 1.0;
+        }
+
+        // Rolling single row over instrs_b
+        let mut row = vec![0usize; instrs_b.len() + 1];
+        for instr_a in instrs_a {
+            let mut diagonal = 0;
+            for (j, instr_b) in instrs_b.iter().enumerate() {
+                let above = row[j + 1];
+                row[j + 1] = if instr_a.mnemonic == instr_b.mnemonic {
+                    diagonal + 1
+                } else {
+                    above.max(row[j])
+                };
+                diagonal = above;
+            }
+        }
+
+        row[instrs_b.len()] as f64 / longer as f64
+    }
+
+    /// Jaccard index of the sets of `n` consecutive mnemonics (shingles) of
+    /// each function. Short local patterns survive reordering of larger
+    /// blocks. A function shorter than `n` forms a single shingle of its
+    /// whole sequence; two empty functions score 1.0.
+    pub fn ngram_similarity(func_a: &FunctionInfo, func_b: &FunctionInfo, n: usize) -> f64 {
+        fn shingles(func: &FunctionInfo, n: usize) -> FxHashSet<Vec<&str>> {
+            let mnemonics: Vec<&str> = func.instructions.iter().map(|instr| instr.mnemonic.as_str()).collect();
+            if mnemonics.is_empty() {
+                FxHashSet::default()
+            } else if mnemonics.len() < n {
+                std::iter::once(mnemonics).collect()
+            } else {
+                mnemonics.windows(n).map(|window| window.to_vec()).collect()
+            }
+        }
+
+        let n = n.max(1);
+        let shingles_a = shingles(func_a, n);
+        let shingles_b = shingles(func_b, n);
+
+        let intersection = shingles_a.intersection(&shingles_b).count();
+        let union = shingles_a.len() + shingles_b.len() - intersection;
+        if union == 0 {
+            1.0 // Both functions are empty
+        } else {
+            intersection as f64 / union as f64
+        }
+    }
+
     /// Calculate control flow similarity using graph comparison
     pub fn control_flow_similarity(func_a: &FunctionInfo, func_b: &FunctionInfo) -> f64 {
         // Create adjacency lists for both functions
@@ -129,8 +430,9 @@ impl SimilarityAnalyzer {
         Self::graph_similarity(&graph_a, &graph_b)
     }
 
-    /// Build a control flow graph from function info
-    fn build_cfg_graph(func: &FunctionInfo) -> Graph<u64, ()> {
+    /// Build a control flow graph from function info.
+    /// Node `i` corresponds to `func.basic_blocks[i]`.
+    pub(crate) fn build_cfg_graph(func: &FunctionInfo) -> Graph<u64, ()> {
         let mut graph = Graph::new();
         let mut node_map = HashMap::new();
         
@@ -154,6 +456,81 @@ impl SimilarityAnalyzer {
         graph
     }
 
+    /// Index of the entry block: the block at the function's address, or the
+    /// first block when none starts there.
+    pub(crate) fn entry_block_index(func: &FunctionInfo) -> usize {
+        func.basic_blocks
+            .iter()
+            .position(|bb| bb.address == func.address)
+            .unwrap_or(0)
+    }
+
+    /// Compute a function's CFG depth and width.
+    /// Depth is the number of nodes on the longest path through the DAG of
+    /// strongly connected components (loops collapse to one node); width is
+    /// the largest number of blocks at any BFS level from the entry block.
+    pub fn cfg_depth_and_width(func: &FunctionInfo) -> (usize, usize) {
+        let graph = Self::build_cfg_graph(func);
+        if graph.node_count() == 0 {
+            return (0, 0);
+        }
+
+        let dag = condensation(graph.clone(), true);
+        let depth = match toposort(&dag, None) {
+            Ok(order) => {
+                let mut longest = vec![1usize; dag.node_count()];
+                for node in order {
+                    for succ in dag.neighbors(node) {
+                        longest[succ.index()] = longest[succ.index()].max(longest[node.index()] + 1);
+                    }
+                }
+                longest.into_iter().max().unwrap_or(0)
+            }
+            // A condensation is acyclic by construction
+            Err(_) => 0,
+        };
+
+        let entry = NodeIndex::new(Self::entry_block_index(func));
+        let mut level = vec![usize::MAX; graph.node_count()];
+        let mut level_sizes: Vec<usize> = Vec::new();
+        let mut queue = VecDeque::new();
+        level[entry.index()] = 0;
+        queue.push_back(entry);
+        while let Some(node) = queue.pop_front() {
+            let l = level[node.index()];
+            if level_sizes.len() <= l {
+                level_sizes.push(0);
+            }
+            level_sizes[l] += 1;
+            for succ in graph.neighbors(node) {
+                if level[succ.index()] == usize::MAX {
+                    level[succ.index()] = l + 1;
+                    queue.push_back(succ);
+                }
+            }
+        }
+        let width = level_sizes.into_iter().max().unwrap_or(0);
+
+        (depth, width)
+    }
+
+    /// Compare CFG depth and width, distinguishing deep loop nests from wide
+    /// switch-like fan-out even when block and edge counts agree.
+    pub fn cfg_shape_similarity(func_a: &FunctionInfo, func_b: &FunctionInfo) -> f64 {
+        let (depth_a, width_a) = Self::cfg_depth_and_width(func_a);
+        let (depth_b, width_b) = Self::cfg_depth_and_width(func_b);
+
+        let ratio = |x: usize, y: usize| {
+            if x == 0 && y == 0 {
+                1.0
+            } else {
+                1.0 - x.abs_diff(y) as f64 / x.max(y) as f64
+            }
+        };
+
+        0.5 * ratio(depth_a, depth_b) + 0.5 * ratio(width_a, width_b)
+    }
+
     /// Calculate similarity between two graphs
     fn graph_similarity(graph_a: &Graph<u64, ()>, graph_b: &Graph<u64, ()>) -> f64 {
         let nodes_a = graph_a.node_count();
@@ -169,7 +546,7 @@ impl SimilarityAnalyzer {
         let node_similarity = if nodes_a == 0 || nodes_b == 0 {
             0.0
         } else {
-            1.0 - ((nodes_a as f64 - nodes_b as f64).abs() / nodes_a.max(nodes_b) as f64)
+            1.0 - (nodes_a.abs_diff(nodes_b) as f64 / nodes_a.max(nodes_b) as f64)
         };
         
         let edge_similarity = if edges_a == 0 && edges_b == 0 {
@@ -177,13 +554,129 @@ impl SimilarityAnalyzer {
         } else if edges_a == 0 || edges_b == 0 {
             0.0
         } else {
-            1.0 - ((edges_a as f64 - edges_b as f64).abs() / edges_a.max(edges_b) as f64)
+            1.0 - (edges_a.abs_diff(edges_b) as f64 / edges_a.max(edges_b) as f64)
         };
         
         // Weighted combination
         0.6 * node_similarity + 0.4 * edge_similarity
     }
 
+    /// Calculate CFG similarity robust to basic-block splitting and merging.
+    /// Straight-line chains (a block with a single successor whose successor
+    /// has a single predecessor) are first merged into super-blocks in both
+    /// functions, then the condensed graphs are compared by shape and by the
+    /// multiset of super-block mnemonic sequences.
+    pub fn split_merge_normalized_similarity(func_a: &FunctionInfo, func_b: &FunctionInfo) -> f64 {
+        let (blocks_a, edges_a) = Self::merge_straight_line_blocks(func_a);
+        let (blocks_b, edges_b) = Self::merge_straight_line_blocks(func_b);
+
+        if blocks_a.is_empty() && blocks_b.is_empty() {
+            return 1.0;
+        }
+        if blocks_a.is_empty() || blocks_b.is_empty() {
+            return 0.0;
+        }
+
+        let count_ratio = |x: usize, y: usize| {
+            if x == 0 && y == 0 {
+                1.0
+            } else {
+                1.0 - x.abs_diff(y) as f64 / x.max(y) as f64
+            }
+        };
+        let shape = 0.6 * count_ratio(blocks_a.len(), blocks_b.len()) + 0.4 * count_ratio(edges_a, edges_b);
+
+        let mut seq_a: HashMap<&str, usize> = HashMap::new();
+        let mut seq_b: HashMap<&str, usize> = HashMap::new();
+        for seq in &blocks_a {
+            *seq_a.entry(seq.as_str()).or_insert(0) += 1;
+        }
+        for seq in &blocks_b {
+            *seq_b.entry(seq.as_str()).or_insert(0) += 1;
+        }
+        let mut min_sum = 0usize;
+        let mut max_sum = 0usize;
+        for (seq, &count_a) in &seq_a {
+            let count_b = seq_b.get(seq).copied().unwrap_or(0);
+            min_sum += count_a.min(count_b);
+            max_sum += count_a.max(count_b);
+        }
+        max_sum += seq_b.iter().filter(|(seq, _)| !seq_a.contains_key(*seq)).map(|(_, &c)| c).sum::<usize>();
+        let content = if max_sum == 0 { 1.0 } else { min_sum as f64 / max_sum as f64 };
+
+        0.5 * shape + 0.5 * content
+    }
+
+    /// Merge straight-line block chains into super-blocks.
+    /// Returns each super-block's mnemonic sequence (space separated) and the
+    /// number of edges remaining between super-blocks.
+    fn merge_straight_line_blocks(func: &FunctionInfo) -> (Vec<String>, usize) {
+        let index_of: HashMap<u64, usize> = func
+            .basic_blocks
+            .iter()
+            .enumerate()
+            .map(|(i, bb)| (bb.address, i))
+            .collect();
+
+        let successors: Vec<Vec<usize>> = func
+            .basic_blocks
+            .iter()
+            .map(|bb| bb.edges.iter().filter_map(|addr| index_of.get(addr).copied()).collect())
+            .collect();
+        let mut predecessor_count = vec![0usize; func.basic_blocks.len()];
+        for succs in &successors {
+            for &s in succs {
+                predecessor_count[s] += 1;
+            }
+        }
+
+        // The edge i -> s is internal to a chain when it is the only way out
+        // of i and the only way into s.
+        let merges_into_next = |i: usize| -> Option<usize> {
+            match successors[i].as_slice() {
+                [s] if *s != i && predecessor_count[*s] == 1 => Some(*s),
+                _ => None,
+            }
+        };
+        let mut is_continuation = vec![false; func.basic_blocks.len()];
+        for i in 0..func.basic_blocks.len() {
+            if let Some(s) = merges_into_next(i) {
+                is_continuation[s] = true;
+            }
+        }
+
+        let mut visited = vec![false; func.basic_blocks.len()];
+        let mut super_blocks = Vec::new();
+        let mut internal_edges = 0usize;
+
+        // Chain heads first, then any leftover blocks (pure straight-line cycles)
+        let heads = (0..func.basic_blocks.len())
+            .filter(|&i| !is_continuation[i])
+            .chain(0..func.basic_blocks.len());
+        for head in heads {
+            if visited[head] {
+                continue;
+            }
+            let mut mnemonics: Vec<&str> = Vec::new();
+            let mut current = head;
+            loop {
+                visited[current] = true;
+                mnemonics.extend(func.basic_blocks[current].instructions.iter().map(|i| i.mnemonic.as_str()));
+                match merges_into_next(current) {
+                    Some(next) if !visited[next] => {
+                        internal_edges += 1;
+                        current = next;
+                    }
+                    _ => break,
+                }
+            }
+            super_blocks.push(mnemonics.join(" "));
+        }
+
+        let total_edges: usize = successors.iter().map(|s| s.len()).sum();
+        (super_blocks, total_edges - internal_edges)
+    }
+
     /// Calculate function call similarity
     pub fn function_call_similarity(func_a: &FunctionInfo, func_b: &FunctionInfo) -> f64 {
         // Extract function calls from instructions
@@ -193,8 +686,10 @@ impl SimilarityAnalyzer {
         Self::jaccard_similarity(&calls_a, &calls_b)
     }
 
-    /// Extract function calls from instructions
-    fn extract_function_calls(func: &FunctionInfo) -> FxHashSet<String> {
+    /// Extract function calls from instructions. A call to the function
+    /// itself is recorded as `<self>` so recursion matches across binaries
+    /// even when the function was renamed or moved.
+    pub(crate) fn extract_function_calls(func: &FunctionInfo) -> FxHashSet<String> {
         let mut calls = FxHashSet::default();
         
         for instr in &func.instructions {
@@ -202,7 +697,16 @@ impl SimilarityAnalyzer {
             if instr.mnemonic.to_lowercase().contains("call") {
                 // Extract the target from operands
                 if let Some(target) = instr.operands.first() {
-                    calls.insert(target.clone());
+                    let is_self = *target == func.name
+                        || target
+                            .strip_prefix("0x")
+                            .and_then(|hex| u64::from_str_radix(hex, 16).ok())
+                            == Some(func.address);
+                    if is_self {
+                        calls.insert("<self>".to_string());
+                    } else {
+                        calls.insert(target.clone());
+                    }
                 }
             }
         }
@@ -258,28 +762,152 @@ impl SimilarityAnalyzer {
         strings
     }
 
-    /// Calculate overall function similarity using multiple metrics
-    pub fn comprehensive_similarity(func_a: &FunctionInfo, func_b: &FunctionInfo) -> f64 {
-        let weights = [
-            (Self::control_flow_similarity(func_a, func_b), 0.3),
-            (Self::function_call_similarity(func_a, func_b), 0.2),
-            (Self::constant_similarity(func_a, func_b), 0.2),
-            (Self::string_similarity(func_a, func_b), 0.1),
-            (Self::instruction_sequence_similarity(&func_a.instructions, &func_b.instructions), 0.2),
-        ];
-        
-        let mut total_weighted_score = 0.0;
-        let mut total_weight = 0.0;
-        
-        for (score, weight) in weights {
-            total_weighted_score += score * weight;
-            total_weight += weight;
+    /// String and constant operands referenced by a function.
+    pub fn extract_references(func: &FunctionInfo) -> FxHashSet<String> {
+        let mut references = Self::extract_strings(func);
+        references.extend(Self::extract_constants(func));
+        references
+    }
+
+    /// Calculate register usage similarity between functions.
+    /// Compares the multisets of registers referenced in operands via weighted
+    /// Jaccard (sum of minimum counts over sum of maximum counts).
+    pub fn register_usage_similarity(func_a: &FunctionInfo, func_b: &FunctionInfo) -> f64 {
+        let regs_a = Self::extract_registers(func_a);
+        let regs_b = Self::extract_registers(func_b);
+
+        if regs_a.is_empty() && regs_b.is_empty() {
+            return 1.0;
         }
-        
-        if total_weight > 0.0 {
-            total_weighted_score / total_weight
+
+        let mut min_sum = 0usize;
+        let mut max_sum = 0usize;
+
+        for (reg, &count_a) in &regs_a {
+            let count_b = regs_b.get(reg).copied().unwrap_or(0);
+            min_sum += count_a.min(count_b);
+            max_sum += count_a.max(count_b);
+        }
+
+        for (reg, &count_b) in &regs_b {
+            if !regs_a.contains_key(reg) {
+                max_sum += count_b;
+            }
+        }
+
+        if max_sum == 0 {
+            1.0
         } else {
-            0.0
+            min_sum as f64 / max_sum as f64
+        }
+    }
+
+    /// Extract a register-usage histogram from function operands.
+    /// Operands are split on non-identifier characters so memory operands such
+    /// as `[rbp-0x8]` contribute their base register.
+    fn extract_registers(func: &FunctionInfo) -> HashMap<String, usize> {
+        let mut registers = HashMap::new();
+
+        for instr in &func.instructions {
+            for operand in &instr.operands {
+                for token in operand.split(|c: char| !(c.is_ascii_alphanumeric() || c == '$')) {
+                    if let Some(reg) = normalize::normalize_register(token) {
+                        *registers.entry(reg).or_insert(0) += 1;
+                    }
+                }
+            }
+        }
+
+        registers
+    }
+
+    /// Calculate exception-handling structure similarity.
+    /// Functions that disagree on having exception handling score 0.0; when
+    /// both have it, the landing-pad counts are compared.
+    pub fn exception_handling_similarity(func_a: &FunctionInfo, func_b: &FunctionInfo) -> f64 {
+        if func_a.has_exception_handling != func_b.has_exception_handling {
+            return 0.0;
+        }
+        if !func_a.has_exception_handling {
+            return 1.0;
+        }
+
+        let pads_a = func_a.landing_pad_count();
+        let pads_b = func_b.landing_pad_count();
+        let max_pads = pads_a.max(pads_b);
+        if max_pads == 0 {
+            1.0
+        } else {
+            1.0 - pads_a.abs_diff(pads_b) as f64 / max_pads as f64
+        }
+    }
+
+    /// Calculate raw-byte similarity as the Jaccard index of the sets of
+    /// k-byte shingles over each function's concatenated instruction bytes.
+    /// With `mask_operands`, every byte after an instruction's first is
+    /// zeroed, so encodings that share opcodes but differ in registers,
+    /// immediates or displacements still line up. Functions too short to
+    /// form a single k-gram are compared like empty sets.
+    pub fn byte_kgram_similarity(func_a: &FunctionInfo, func_b: &FunctionInfo, k: usize, mask_operands: bool) -> f64 {
+        let bytes_a = Self::instruction_bytes(func_a, mask_operands);
+        let bytes_b = Self::instruction_bytes(func_b, mask_operands);
+        let k = k.max(1);
+
+        let kgrams_a: FxHashSet<&[u8]> = bytes_a.windows(k).collect();
+        let kgrams_b: FxHashSet<&[u8]> = bytes_b.windows(k).collect();
+
+        let intersection = kgrams_a.intersection(&kgrams_b).count();
+        let union = kgrams_a.len() + kgrams_b.len() - intersection;
+        if union == 0 {
+            1.0 // Both sets are empty
+        } else {
+            intersection as f64 / union as f64
+        }
+    }
+
+    /// Concatenate a function's instruction bytes, optionally keeping only
+    /// the first byte of each instruction and zeroing the rest.
+    fn instruction_bytes(func: &FunctionInfo, mask_operands: bool) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for instr in &func.instructions {
+            let start = bytes.len();
+            bytes.extend_from_slice(&instr.bytes);
+            if mask_operands && instr.bytes.len() > 1 {
+                bytes[start + 1..].fill(0);
+            }
+        }
+        bytes
+    }
+
+    /// Compare the distributions of basic-block byte sizes, so functions
+    /// with equal block counts but tiny versus huge blocks are told apart.
+    /// Both sorted size lists are sampled at the same quantiles and scored
+    /// as `1 - sum |a - b| / sum max(a, b)`, a normalized earth-mover
+    /// distance between the two distributions.
+    pub fn block_size_distribution_similarity(func_a: &FunctionInfo, func_b: &FunctionInfo) -> f64 {
+        let mut sizes_a: Vec<u64> = func_a.basic_blocks.iter().map(|bb| bb.size).collect();
+        let mut sizes_b: Vec<u64> = func_b.basic_blocks.iter().map(|bb| bb.size).collect();
+        if sizes_a.is_empty() || sizes_b.is_empty() {
+            return if sizes_a.len() == sizes_b.len() { 1.0 } else { 0.0 };
+        }
+        sizes_a.sort_unstable();
+        sizes_b.sort_unstable();
+
+        let samples = sizes_a.len().max(sizes_b.len());
+        let quantile = |sizes: &[u64], i: usize| sizes[i * sizes.len() / samples];
+
+        let mut difference = 0u64;
+        let mut total = 0u64;
+        for i in 0..samples {
+            let (a, b) = (quantile(&sizes_a, i), quantile(&sizes_b, i));
+            difference += a.abs_diff(b);
+            total += a.max(b);
+        }
+
+        if total == 0 {
+            1.0
+        } else {
+            1.0 - difference as f64 / total as f64
         }
     }
 
@@ -298,4 +926,469 @@ impl SimilarityAnalyzer {
         
         matrix
     }
-}
\ No newline at end of file
+}
+/// Byte k-gram length used by [`LazyMatchMetrics::byte_similarity`].
+const LAZY_BYTE_KGRAM: usize = 4;
+
+/// Expensive per-match metrics that the pipeline does not compute, evaluated
+/// on first request and cached, so inspecting a few matches doesn't cost a
+/// byte-level pass over every match.
+pub struct LazyMatchMetrics<'a> {
+    function_match: &'a FunctionMatch,
+    byte_similarity: OnceCell<f64>,
+    instruction_diff: OnceCell<Vec<InstructionDiffEntry>>,
+}
+
+impl<'a> LazyMatchMetrics<'a> {
+    pub fn new(function_match: &'a FunctionMatch) -> Self {
+        Self {
+            function_match,
+            byte_similarity: OnceCell::new(),
+            instruction_diff: OnceCell::new(),
+        }
+    }
+
+    /// Raw-byte similarity of the pair, as
+    /// `SimilarityAnalyzer::byte_kgram_similarity` with 4-byte k-grams.
+    pub fn byte_similarity(&self) -> f64 {
+        *self.byte_similarity.get_or_init(|| {
+            SimilarityAnalyzer::byte_kgram_similarity(
+                &self.function_match.function_a,
+                &self.function_match.function_b,
+                LAZY_BYTE_KGRAM,
+                false,
+            )
+        })
+    }
+
+    /// Instruction-level alignment of the pair, as
+    /// `SimilarityAnalyzer::instruction_diff`.
+    pub fn instruction_diff(&self) -> &[InstructionDiffEntry] {
+        self.instruction_diff.get_or_init(|| {
+            SimilarityAnalyzer::instruction_diff(&self.function_match.function_a, &self.function_match.function_b)
+        })
+    }
+
+    /// Fraction of aligned entries that are unchanged instructions; 1.0 when
+    /// both functions are empty.
+    pub fn alignment_similarity(&self) -> f64 {
+        let diff = self.instruction_diff();
+        if diff.is_empty() {
+            return 1.0;
+        }
+        let equal = diff.iter().filter(|entry| matches!(entry, InstructionDiffEntry::Equal { .. })).count();
+        equal as f64 / diff.len() as f64
+    }
+
+    /// The byte similarity if it has already been computed.
+    pub fn cached_byte_similarity(&self) -> Option<f64> {
+        self.byte_similarity.get().copied()
+    }
+
+    /// Whether the instruction alignment has already been computed.
+    pub fn is_instruction_diff_cached(&self) -> bool {
+        self.instruction_diff.get().is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithms::DiffAlgorithms;
+    use crate::SimilarityWeights;
+
+    fn function(instructions: &[(&str, &[&str])]) -> FunctionInfo {
+        FunctionInfo {
+            instructions: instructions
+                .iter()
+                .map(|(mnemonic, operands)| InstructionInfo {
+                    mnemonic: mnemonic.to_string(),
+                    operands: operands.iter().map(|op| op.to_string()).collect(),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn register_usage_separates_functions_with_equal_mnemonics() {
+        let a = function(&[("mov", &["eax", "[rbp-0x8]"]), ("add", &["rax", "0x1"])]);
+        let same_registers = function(&[("mov", &["rax", "qword [rbp-8]"]), ("add", &["al", "1"])]);
+        let other_registers = function(&[("mov", &["r8d", "[rsi]"]), ("add", &["r9", "1"])]);
+
+        assert!((SimilarityAnalyzer::register_usage_similarity(&a, &same_registers) - 1.0).abs() < 1e-9);
+        assert_eq!(SimilarityAnalyzer::register_usage_similarity(&a, &other_registers), 0.0);
+        assert!(
+            DiffAlgorithms::calculate_function_similarity(&a, &same_registers)
+                > DiffAlgorithms::calculate_function_similarity(&a, &other_registers)
+        );
+    }
+
+    #[test]
+    fn exception_handling_prefers_functions_with_landing_pads() {
+        let mut plain = function(&[("push", &["rbp"]), ("call", &["0x1000"]), ("ret", &[])]);
+        plain.basic_blocks = vec![
+            BasicBlockInfo { address: 0x10, edges: vec![0x20], ..Default::default() },
+            BasicBlockInfo { address: 0x20, ..Default::default() },
+        ];
+        let mut with_eh = plain.clone();
+        with_eh.has_exception_handling = true;
+        with_eh.basic_blocks[1].is_landing_pad = true;
+        let other_with_eh = with_eh.clone();
+
+        assert_eq!(SimilarityAnalyzer::exception_handling_similarity(&with_eh, &plain), 0.0);
+        assert!(
+            DiffAlgorithms::calculate_function_similarity(&with_eh, &other_with_eh)
+                > DiffAlgorithms::calculate_function_similarity(&with_eh, &plain)
+        );
+    }
+
+    #[test]
+    fn cfg_shape_separates_deep_from_wide() {
+        let graph = |blocks: &[(u64, &[u64])]| FunctionInfo {
+            address: blocks[0].0,
+            basic_blocks: blocks
+                .iter()
+                .map(|(address, edges)| BasicBlockInfo { address: *address, edges: edges.to_vec(), ..Default::default() })
+                .collect(),
+            ..Default::default()
+        };
+        // Five blocks and five edges each: a chain with a loop, and a fan-out
+        let deep = graph(&[(1, &[2]), (2, &[3]), (3, &[4]), (4, &[5, 2]), (5, &[])]);
+        let wide = graph(&[(1, &[2, 3, 4, 5]), (2, &[5]), (3, &[]), (4, &[]), (5, &[])]);
+
+        assert_eq!(SimilarityAnalyzer::cfg_depth_and_width(&deep), (3, 1));
+        assert_eq!(SimilarityAnalyzer::cfg_depth_and_width(&wide), (3, 4));
+        assert_eq!(SimilarityAnalyzer::cfg_shape_similarity(&deep, &deep), 1.0);
+        assert!(SimilarityAnalyzer::cfg_shape_similarity(&deep, &wide) < 1.0);
+
+        let (_, details) = DiffAlgorithms::compute_match_details(&deep, &wide);
+        assert!(details.cfg_shape_similarity < 1.0);
+    }
+
+    #[test]
+    fn block_size_distribution_separates_tiny_from_huge_blocks() {
+        let sized = |sizes: &[u64]| FunctionInfo {
+            basic_blocks: sizes
+                .iter()
+                .enumerate()
+                .map(|(i, size)| BasicBlockInfo { address: i as u64 * 0x100, size: *size, ..Default::default() })
+                .collect(),
+            ..Default::default()
+        };
+        let tiny = sized(&[2, 2, 2, 2]);
+        let huge = sized(&[40, 60, 80, 100]);
+
+        assert!(SimilarityAnalyzer::block_size_distribution_similarity(&tiny, &huge) < 0.2);
+        assert_eq!(SimilarityAnalyzer::block_size_distribution_similarity(&tiny, &tiny), 1.0);
+        // Order doesn't matter, only the distribution
+        assert_eq!(SimilarityAnalyzer::block_size_distribution_similarity(&sized(&[5, 1]), &sized(&[1, 5])), 1.0);
+        let close = SimilarityAnalyzer::block_size_distribution_similarity(&sized(&[4, 8]), &sized(&[4, 4, 8, 9]));
+        assert!(close > 0.8 && close < 1.0);
+        assert_eq!(SimilarityAnalyzer::block_size_distribution_similarity(&sized(&[]), &sized(&[])), 1.0);
+        assert_eq!(SimilarityAnalyzer::block_size_distribution_similarity(&sized(&[]), &sized(&[3])), 0.0);
+
+        let (_, details) = DiffAlgorithms::compute_match_details(&tiny, &huge);
+        assert!(details.block_size_similarity < 0.2);
+    }
+
+    #[test]
+    fn lcs_rewards_preserved_order_over_interleaving() {
+        let a = function(&[("mov", &[]), ("add", &[]), ("push", &[]), ("sub", &[]), ("ret", &[])]);
+        let b = function(&[("mov", &[]), ("xor", &[]), ("add", &[]), ("sub", &[]), ("ret", &[]), ("lea", &[])]);
+
+        let lcs = SimilarityAnalyzer::lcs_mnemonic_similarity(&a.instructions, &b.instructions);
+        assert!((lcs - 4.0 / 6.0).abs() < 1e-12);
+        let jaccard = SimilarityAnalyzer::basic_block_mnemonic_similarity(
+            &BasicBlockInfo { instructions: a.instructions.clone(), ..Default::default() },
+            &BasicBlockInfo { instructions: b.instructions.clone(), ..Default::default() },
+        );
+        assert!(lcs > jaccard);
+        assert_eq!(SimilarityAnalyzer::lcs_mnemonic_similarity(&[], &[]), 1.0);
+        assert_eq!(SimilarityAnalyzer::lcs_mnemonic_similarity(&a.instructions, &[]), 0.0);
+
+        // Only computed when weighted
+        let (_, details) = DiffAlgorithms::compute_match_details(&a, &b);
+        assert_eq!(details.lcs_similarity, 0.0);
+        let weights = SimilarityWeights { lcs: 0.1, cfg: 0.10, ..SimilarityWeights::default() };
+        weights.validate().unwrap();
+        let (_, details) = DiffAlgorithms::compute_match_details_weighted(&a, &b, &weights);
+        assert!((details.lcs_similarity - lcs).abs() < 1e-12);
+    }
+
+    #[test]
+    fn split_blocks_do_not_lower_normalized_similarity() {
+        let block = |address: u64, mnemonics: &[&str], edges: &[u64]| BasicBlockInfo {
+            address,
+            instructions: mnemonics.iter().map(|m| InstructionInfo { mnemonic: m.to_string(), ..Default::default() }).collect(),
+            edges: edges.to_vec(),
+            ..Default::default()
+        };
+        let original = FunctionInfo {
+            basic_blocks: vec![
+                block(1, &["push", "mov", "cmp"], &[2, 3]),
+                block(2, &["add", "sub", "jmp"], &[4]),
+                block(3, &["xor"], &[4]),
+                block(4, &["ret"], &[]),
+            ],
+            ..Default::default()
+        };
+        // Block 2 split in two with a fallthrough edge between the halves
+        let split = FunctionInfo {
+            basic_blocks: vec![
+                block(1, &["push", "mov", "cmp"], &[2, 3]),
+                block(2, &["add"], &[5]),
+                block(5, &["sub", "jmp"], &[4]),
+                block(3, &["xor"], &[4]),
+                block(4, &["ret"], &[]),
+            ],
+            ..Default::default()
+        };
+
+        assert!((SimilarityAnalyzer::split_merge_normalized_similarity(&original, &split) - 1.0).abs() < 1e-9);
+        assert!(SimilarityAnalyzer::control_flow_similarity(&original, &split) < 1.0);
+    }
+
+    #[test]
+    fn cosine_similarity_handles_empty_and_zero_vectors() {
+        let counts = |pairs: &[(&str, usize)]| -> HashMap<String, usize> {
+            pairs.iter().map(|(key, count)| (key.to_string(), *count)).collect()
+        };
+        let empty = counts(&[]);
+        let zero = counts(&[("q", 0)]);
+        let populated = counts(&[("x", 3), ("y", 1)]);
+
+        assert_eq!(SimilarityAnalyzer::cosine_similarity(&empty, &empty), 1.0);
+        assert_eq!(SimilarityAnalyzer::cosine_similarity(&empty, &zero), 1.0);
+        assert_eq!(SimilarityAnalyzer::cosine_similarity(&empty, &populated), 0.0);
+        assert_eq!(SimilarityAnalyzer::cosine_similarity(&populated, &empty), 0.0);
+        assert!((SimilarityAnalyzer::cosine_similarity(&populated, &populated) - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn reference_index_agrees_with_pairwise_comparison() {
+        let referencing = |address: u64, references: &[&str]| FunctionInfo {
+            address,
+            instructions: references
+                .iter()
+                .map(|reference| InstructionInfo {
+                    mnemonic: "lea".to_string(),
+                    operands: vec!["rax".to_string(), reference.to_string()],
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        };
+        let functions_a = [
+            referencing(0, &["\"hello\"", "0x0"]),
+            referencing(10, &["\"world\"", "0x0"]),
+            referencing(20, &["0x0"]),
+            referencing(30, &["\"rare\"", "\"hello\""]),
+        ];
+        let functions_b = [
+            referencing(0, &["\"hello\"", "0x0"]),
+            referencing(10, &["\"world\"", "0x0"]),
+            referencing(20, &["0x0"]),
+            referencing(30, &["0x0", "\"other\""]),
+            referencing(40, &["\"rare\""]),
+        ];
+        let max_frequency = 1;
+        let references_b: Vec<_> = functions_b.iter().map(SimilarityAnalyzer::extract_references).collect();
+        let frequency = |reference: &String| references_b.iter().filter(|refs| refs.contains(reference)).count();
+
+        let index = ReferenceIndex::build(&functions_b);
+        for func_a in &functions_a {
+            let references_a = SimilarityAnalyzer::extract_references(func_a);
+            let pairwise: Vec<usize> = (0..functions_b.len())
+                .filter(|&j| references_a.intersection(&references_b[j]).any(|r| frequency(r) <= max_frequency))
+                .collect();
+            assert_eq!(index.candidates(func_a, max_frequency), pairwise);
+        }
+        assert_eq!(index.candidates(&functions_a[3], max_frequency), vec![0, 4]);
+        assert!(index.candidates(&functions_a[2], max_frequency).is_empty());
+    }
+
+    #[test]
+    fn byte_kgrams_can_ignore_operand_bytes() {
+        let encoded = |instructions: &[(&str, &[u8])]| FunctionInfo {
+            instructions: instructions
+                .iter()
+                .map(|(mnemonic, bytes)| InstructionInfo {
+                    mnemonic: mnemonic.to_string(),
+                    bytes: bytes.to_vec(),
+                    length: bytes.len(),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        };
+        // Same opcodes, different registers and immediates, AT&T mnemonics
+        let a = encoded(&[("mov", &[0x8b, 0x45, 0x08]), ("add", &[0x83, 0xc0, 0x01]), ("ret", &[0xc3])]);
+        let b = encoded(&[("MOVL", &[0x8b, 0x4d, 0x0c]), ("ADDL", &[0x83, 0xc1, 0x02]), ("RETQ", &[0xc3])]);
+        assert!(SimilarityAnalyzer::byte_kgram_similarity(&a, &b, 3, false) < 0.5);
+        assert_eq!(SimilarityAnalyzer::byte_kgram_similarity(&a, &b, 3, true), 1.0);
+        assert!(SimilarityAnalyzer::instruction_sequence_similarity(&a.instructions, &b.instructions) < 0.5);
+
+        let empty = encoded(&[]);
+        assert_eq!(SimilarityAnalyzer::byte_kgram_similarity(&empty, &empty, 4, true), 1.0);
+        assert_eq!(SimilarityAnalyzer::byte_kgram_similarity(&a, &empty, 4, true), 0.0);
+    }
+
+    #[test]
+    fn edit_distance_matches_the_full_table() {
+        fn full_table(s1: &str, s2: &str) -> usize {
+            let (a, b): (Vec<char>, Vec<char>) = (s1.chars().collect(), s2.chars().collect());
+            let mut dp = vec![vec![0; b.len() + 1]; a.len() + 1];
+            for (i, row) in dp.iter_mut().enumerate() {
+                row[0] = i;
+            }
+            for (j, cell) in dp[0].iter_mut().enumerate() {
+                *cell = j;
+            }
+            for i in 1..=a.len() {
+                for j in 1..=b.len() {
+                    dp[i][j] = if a[i - 1] == b[j - 1] {
+                        dp[i - 1][j - 1]
+                    } else {
+                        1 + dp[i - 1][j].min(dp[i][j - 1]).min(dp[i - 1][j - 1])
+                    };
+                }
+            }
+            dp[a.len()][b.len()]
+        }
+
+        let pairs = [("", ""), ("abc", ""), ("kitten", "sitting"), ("mov push ret", "push mov add ret"), ("flaw", "lawn"), ("ab", "abcdef"), ("héllo", "hello")];
+        for (a, b) in pairs {
+            assert_eq!(SimilarityAnalyzer::edit_distance(a, b), full_table(a, b));
+            assert_eq!(SimilarityAnalyzer::edit_distance(b, a), full_table(a, b));
+        }
+    }
+
+    #[test]
+    fn instruction_diff_reports_operand_changes() {
+        let original = function(&[("push", &["rbp"]), ("sub", &["rsp", "0x100"]), ("ret", &[])]);
+        let patched = function(&[("push", &["rbp"]), ("sub", &["rsp", "0x200"]), ("nop", &[]), ("ret", &[])]);
+        assert_eq!(
+            SimilarityAnalyzer::instruction_diff(&original, &patched),
+            vec![
+                InstructionDiffEntry::Equal { index_a: 0, index_b: 0 },
+                InstructionDiffEntry::Modified {
+                    index_a: 1,
+                    index_b: 1,
+                    operand_changes: vec![OperandChange { position: 1, old: "0x100".to_string(), new: "0x200".to_string() }],
+                },
+                InstructionDiffEntry::Added { index_b: 2 },
+                InstructionDiffEntry::Equal { index_a: 2, index_b: 3 },
+            ]
+        );
+
+        let unrelated = function(&[("mov", &["eax", "1"])]);
+        let diff = SimilarityAnalyzer::instruction_diff(&original, &unrelated);
+        assert_eq!(diff.len(), 4);
+        assert!(diff.iter().all(|e| matches!(e, InstructionDiffEntry::Removed { .. } | InstructionDiffEntry::Added { .. })));
+    }
+
+    #[test]
+    fn lazy_metrics_are_computed_once_on_demand() {
+        let mut function_a = function(&[("mov", &["eax", "1"]), ("add", &["eax", "2"]), ("ret", &[])]);
+        let mut function_b = function(&[("mov", &["eax", "1"]), ("sub", &["eax", "2"]), ("ret", &[])]);
+        for (function, opcode) in [(&mut function_a, 0x01), (&mut function_b, 0x29)] {
+            let bytes = [vec![0xb8, 0x01, 0x00, 0x00, 0x00], vec![0x83, opcode, 0x02], vec![0xc3]];
+            for (instruction, bytes) in function.instructions.iter_mut().zip(bytes) {
+                instruction.bytes = bytes;
+            }
+        }
+        let function_match = FunctionMatch { function_a, function_b, ..Default::default() };
+
+        let metrics = function_match.lazy_metrics();
+        assert!(metrics.cached_byte_similarity().is_none());
+        assert!(!metrics.is_instruction_diff_cached());
+
+        let eager = SimilarityAnalyzer::byte_kgram_similarity(&function_match.function_a, &function_match.function_b, 4, false);
+        assert_eq!(metrics.byte_similarity(), eager);
+        assert_eq!(metrics.cached_byte_similarity(), Some(eager));
+
+        let diff = metrics.instruction_diff().as_ptr();
+        assert!(metrics.is_instruction_diff_cached());
+        assert_eq!(metrics.instruction_diff().as_ptr(), diff);
+        assert_eq!(
+            metrics.instruction_diff(),
+            SimilarityAnalyzer::instruction_diff(&function_match.function_a, &function_match.function_b).as_slice()
+        );
+        let alignment = metrics.alignment_similarity();
+        assert!(alignment > 0.0 && alignment < 1.0, "{}", alignment);
+    }
+
+    #[test]
+    fn ngram_similarity_is_the_jaccard_of_shingles() {
+        let mnemonics = |mnemonics: &[&str]| function(&mnemonics.iter().map(|m| (*m, &[][..])).collect::<Vec<_>>());
+        let (a, b) = (mnemonics(&["a", "b", "c", "d", "e"]), mnemonics(&["a", "b", "c", "d", "x"]));
+
+        // {abc, bcd, cde} and {abc, bcd, cdx} share 2 of 4 trigrams
+        assert!((SimilarityAnalyzer::ngram_similarity(&a, &b, 3) - 0.5).abs() < 1e-12);
+        // {ab, bc, cd, de} and {ab, bc, cd, dx} share 3 of 5 bigrams
+        assert!((SimilarityAnalyzer::ngram_similarity(&a, &b, 2) - 0.6).abs() < 1e-12);
+        assert_eq!(SimilarityAnalyzer::ngram_similarity(&a, &a, 3), 1.0);
+
+        // Streams shorter than n compare as a single shingle
+        assert_eq!(SimilarityAnalyzer::ngram_similarity(&mnemonics(&["a", "b"]), &mnemonics(&["a", "b"]), 3), 1.0);
+        assert_eq!(SimilarityAnalyzer::ngram_similarity(&mnemonics(&["a", "b"]), &mnemonics(&["b", "a"]), 3), 0.0);
+        assert_eq!(SimilarityAnalyzer::ngram_similarity(&mnemonics(&[]), &mnemonics(&[]), 3), 1.0);
+        assert_eq!(SimilarityAnalyzer::ngram_similarity(&mnemonics(&[]), &a, 3), 0.0);
+    }
+
+    #[test]
+    fn byte_level_diff_locates_the_changed_immediate() {
+        let function = |address: u64, immediate: u8| {
+            let instruction = |offset: u64, mnemonic: &str, bytes: Vec<u8>| InstructionInfo {
+                address: address + offset,
+                mnemonic: mnemonic.to_string(),
+                length: bytes.len(),
+                bytes,
+                ..Default::default()
+            };
+            FunctionInfo {
+                address,
+                instructions: vec![
+                    instruction(0, "push", vec![0x55]),
+                    instruction(1, "mov", vec![0xb8, immediate, 0, 0, 0]),
+                    instruction(6, "ret", vec![0xc3]),
+                ],
+                ..Default::default()
+            }
+        };
+
+        // Offsets are relative to each function, so the load address doesn't matter
+        let spans = SimilarityAnalyzer::byte_level_diff(&function(0x1000, 1), &function(0x8000, 2));
+        let changed: Vec<&ByteDiffSpan> = spans.iter().filter(|span| span.kind != ByteDiffKind::Equal).collect();
+        assert_eq!(changed.len(), 1, "{:?}", spans);
+        assert_eq!((changed[0].kind, changed[0].offset_a, changed[0].len_a, changed[0].offset_b), (ByteDiffKind::Changed, 2, 1, 2));
+        assert_eq!(SimilarityAnalyzer::byte_level_diff(&function(0, 1), &function(0, 1)).len(), 1);
+
+        let mut truncated = function(0, 1);
+        truncated.instructions.pop();
+        let spans = SimilarityAnalyzer::byte_level_diff(&function(0, 1), &truncated);
+        let last = spans.last().unwrap();
+        assert_eq!((last.kind, last.offset_a, last.offset_b), (ByteDiffKind::Removed, 6, 6));
+    }
+
+    #[test]
+    fn lcs_alignment_bounds_the_table_for_large_inputs() {
+        let small = SimilarityAnalyzer::lcs_alignment(&[1, 2, 3, 4], &[1, 5, 3, 4]);
+        assert_eq!(small, vec![(Some(0), Some(0)), (Some(1), None), (None, Some(1)), (Some(2), Some(2)), (Some(3), Some(3))]);
+
+        // The 3000 × 3000 middle is over the cap, so it is replaced wholesale
+        // while the shared prefix and suffix are still aligned
+        let a: Vec<u32> = (0..3002).collect();
+        let mut b: Vec<u32> = (0..3002).map(|x| x + 10_000).collect();
+        b[0] = 0;
+        b[3001] = 3001;
+        let alignment = SimilarityAnalyzer::lcs_alignment(&a, &b);
+        assert_eq!(alignment.len(), 6002);
+        assert_eq!(alignment[0], (Some(0), Some(0)));
+        assert!(alignment[1..3001].iter().all(|&(i, j)| i.is_some() && j.is_none()));
+        assert!(alignment[3001..6001].iter().all(|&(i, j)| i.is_none() && j.is_some()));
+        assert_eq!(alignment[6001], (Some(3001), Some(3001)));
+    }
+}
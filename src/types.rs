@@ -1,4 +1,28 @@
 use serde::{Serialize, Deserialize};
+use sha2::{Sha256, Digest};
+use crate::normalize;
+use crate::config::DiffConfig;
+
+/// Instruction set of the analyzed binary, used to pick register names and
+/// mnemonic aliases during normalization.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Default)]
+pub enum Architecture {
+    X86,
+    X86_64,
+    Arm,
+    Aarch64,
+    Mips,
+    #[default]
+    Unknown,
+}
+
+/// Byte order of the analyzed binary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Default)]
+pub enum Endianness {
+    #[default]
+    Little,
+    Big,
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 #[serde(default)]
@@ -19,6 +43,99 @@ pub struct BasicBlockInfo {
     pub edges: Vec<u64>,
     pub mnemonic_hash: String,
     pub instruction_count: usize,
+    /// Exception landing pad / cleanup block, as reported by the importer.
+    pub is_landing_pad: bool,
+}
+
+impl InstructionInfo {
+    /// Whether this is a call instruction on any supported architecture.
+    pub fn is_call(&self) -> bool {
+        let m = self.mnemonic.to_ascii_lowercase();
+        m.starts_with("call") || matches!(m.as_str(), "bl" | "blx" | "blr" | "jal" | "jalr")
+    }
+
+    /// Whether this instruction returns from the function.
+    pub fn is_return(&self) -> bool {
+        let m = self.mnemonic.to_ascii_lowercase();
+        if m.starts_with("ret") {
+            return true;
+        }
+        // ARM `bx lr` and MIPS `jr $ra`
+        let target = self.operands.first().map(|op| op.to_ascii_lowercase());
+        matches!((m.as_str(), target.as_deref()), ("bx", Some("lr")) | ("jr", Some("$ra")))
+    }
+
+    /// Whether this is a jump or branch within the function. Calls and
+    /// returns are not branches.
+    pub fn is_branch(&self) -> bool {
+        if self.is_call() || self.is_return() {
+            return false;
+        }
+        let m = self.mnemonic.to_ascii_lowercase();
+        // x86 `jmp`/`jcc` and MIPS `j`/`jr`
+        m.starts_with('j') || Self::is_unconditional_arm_branch(&m) || Self::is_conditional_arm_mips_branch(&m)
+    }
+
+    /// Whether this is a branch that may fall through to the next block.
+    pub fn is_conditional_branch(&self) -> bool {
+        if !self.is_branch() {
+            return false;
+        }
+        let m = self.mnemonic.to_ascii_lowercase();
+        !(m == "jmp" || m == "j" || m == "jr" || Self::is_unconditional_arm_branch(&m))
+    }
+
+    /// Branch target address, taken from the last operand that parses as an
+    /// address (`0x401000`, `#0x401000` or decimal).
+    pub fn branch_target(&self) -> Option<u64> {
+        self.operands.iter().rev().find_map(|op| {
+            let op = op.trim().trim_start_matches('#');
+            match op.strip_prefix("0x").or_else(|| op.strip_prefix("0X")) {
+                Some(hex) => u64::from_str_radix(hex, 16).ok(),
+                None => op.parse().ok(),
+            }
+        })
+    }
+
+    fn is_unconditional_arm_branch(m: &str) -> bool {
+        matches!(m, "b" | "br" | "bx" | "b.al")
+    }
+
+    fn is_conditional_arm_mips_branch(m: &str) -> bool {
+        const CONDITIONS: [&str; 16] = [
+            "eq", "ne", "cs", "cc", "hs", "lo", "mi", "pl", "vs", "vc", "hi", "ls", "ge", "lt", "gt", "le",
+        ];
+        if matches!(m, "cbz" | "cbnz" | "tbz" | "tbnz" | "beqz" | "bnez" | "bgtz" | "blez" | "bltz" | "bgez") {
+            return true;
+        }
+        m.strip_prefix("b.")
+            .or_else(|| m.strip_prefix('b'))
+            .is_some_and(|cond| CONDITIONS.contains(&cond))
+    }
+}
+
+/// Per-type control-flow edge counts for a function. Successor edges are
+/// classified from the block layout: an edge to the next block in address
+/// order is a fallthrough, other edges out of multi-successor blocks are
+/// conditional, and the single edge out of any other block is an
+/// unconditional jump. Calls and returns are counted from instructions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct EdgeTypeCounts {
+    pub conditional: usize,
+    pub unconditional: usize,
+    pub fallthrough: usize,
+    pub call: usize,
+    pub ret: usize,
+}
+
+impl EdgeTypeCounts {
+    pub fn as_array(&self) -> [usize; 5] {
+        [self.conditional, self.unconditional, self.fallthrough, self.call, self.ret]
+    }
+
+    pub fn total(&self) -> usize {
+        self.as_array().iter().sum()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -34,6 +151,220 @@ pub struct FunctionInfo {
     pub cfg_hash: String,
     pub instruction_count: usize,
     pub call_count: usize,
+    /// Whether the function has exception handling (landing pads, cleanup
+    /// edges). False when the importer doesn't know.
+    pub has_exception_handling: bool,
+    /// Pointer size of the binary in bytes (4 or 8), as reported by the
+    /// importer; 0 when unknown. Addresses are still stored as `u64`.
+    pub pointer_size: u8,
+    pub endianness: Endianness,
+}
+
+impl FunctionInfo {
+    /// Render an address in this function's binary with the binary's
+    /// pointer width: 8 hex digits for 32-bit binaries, 16 otherwise.
+    pub fn format_address(&self, address: u64) -> String {
+        if self.pointer_size == 4 {
+            format!("0x{:08x}", address)
+        } else {
+            format!("0x{:016x}", address)
+        }
+    }
+
+    /// This function's own address, see [`Self::format_address`].
+    pub fn display_address(&self) -> String {
+        self.format_address(self.address)
+    }
+
+    /// Deterministically combine `cfg_hash` and `call_graph_hash` into a
+    /// single key. Each component is length-prefixed before hashing, so no
+    /// choice of component strings can collide the way a delimiter-joined
+    /// `"{cfg}_{call}"` key can.
+    pub fn combined_hash(&self) -> u128 {
+        let mut hasher = Sha256::new();
+        for component in [&self.cfg_hash, &self.call_graph_hash] {
+            hasher.update((component.len() as u64).to_le_bytes());
+            hasher.update(component.as_bytes());
+        }
+        let digest = hasher.finalize();
+        let mut bytes = [0u8; 16];
+        bytes.copy_from_slice(&digest[..16]);
+        u128::from_le_bytes(bytes)
+    }
+
+    /// Canonical token stream for this function's instructions: registers,
+    /// immediates and memory operands become `REG`/`IMM`/`MEM` and mnemonic
+    /// aliases are folded, e.g. `mov REG,MEM`. Similarity metrics should
+    /// consume this rather than normalizing instructions themselves.
+    pub fn normalized_instructions(&self, arch: Architecture) -> Vec<String> {
+        self.instructions
+            .iter()
+            .map(|instr| normalize::normalize_instruction(instr, arch))
+            .collect()
+    }
+
+    /// Classify this function's control-flow edges by type.
+    pub fn edge_type_counts(&self) -> EdgeTypeCounts {
+        let mut counts = EdgeTypeCounts::default();
+
+        for bb in &self.basic_blocks {
+            // A block ending at the top of the address space has no fallthrough
+            let fallthrough_addr = bb.address.checked_add(bb.size);
+            for &target in &bb.edges {
+                if bb.size > 0 && Some(target) == fallthrough_addr {
+                    counts.fallthrough += 1;
+                } else if bb.edges.len() > 1 {
+                    counts.conditional += 1;
+                } else {
+                    counts.unconditional += 1;
+                }
+            }
+
+            for instr in &bb.instructions {
+                if instr.is_call() {
+                    counts.call += 1;
+                }
+            }
+            if bb.instructions.last().is_some_and(|instr| instr.is_return()) {
+                counts.ret += 1;
+            }
+        }
+
+        counts
+    }
+
+    /// Rebuild the CFG of a function imported without block edges. When no
+    /// block has any edge but the function contains branch instructions,
+    /// each block gets the successors implied by its last instruction: the
+    /// branch target when it starts a block of this function, plus the next
+    /// block in address order unless the block ends in an unconditional
+    /// branch or a return. Returns true if edges were reconstructed.
+    pub fn reconstruct_missing_edges(&mut self) -> bool {
+        if self.basic_blocks.iter().any(|bb| !bb.edges.is_empty())
+            || !self.basic_blocks.iter().flat_map(|bb| &bb.instructions).any(|instr| instr.is_branch())
+        {
+            return false;
+        }
+
+        let mut block_addrs: Vec<u64> = self.basic_blocks.iter().map(|bb| bb.address).collect();
+        block_addrs.sort_unstable();
+
+        for bb in &mut self.basic_blocks {
+            let next = block_addrs.iter().copied().find(|&addr| addr > bb.address);
+            let (falls_through, target) = match bb.instructions.last() {
+                Some(last) if last.is_return() => (false, None),
+                Some(last) if last.is_branch() => (last.is_conditional_branch(), last.branch_target()),
+                _ => (true, None),
+            };
+
+            if let Some(target) = target.filter(|t| block_addrs.binary_search(t).is_ok()) {
+                bb.edges.push(target);
+            }
+            if let Some(next) = next.filter(|n| falls_through && !bb.edges.contains(n)) {
+                bb.edges.push(next);
+            }
+        }
+
+        true
+    }
+
+    /// Addresses of basic blocks with no path from the entry block (the
+    /// block at the function's address, or the first block) along block
+    /// edges. Functions without any edges have no known CFG and report none.
+    pub fn unreachable_blocks(&self) -> Vec<u64> {
+        if self.basic_blocks.iter().all(|bb| bb.edges.is_empty()) {
+            return Vec::new();
+        }
+
+        let entry = self
+            .basic_blocks
+            .iter()
+            .find(|bb| bb.address == self.address)
+            .unwrap_or(&self.basic_blocks[0]);
+        let mut reached = std::collections::HashSet::from([entry.address]);
+        let mut stack = vec![entry];
+        while let Some(bb) = stack.pop() {
+            for target in &bb.edges {
+                if reached.insert(*target) {
+                    if let Some(next) = self.basic_blocks.iter().find(|b| b.address == *target) {
+                        stack.push(next);
+                    }
+                }
+            }
+        }
+
+        self.basic_blocks
+            .iter()
+            .map(|bb| bb.address)
+            .filter(|addr| !reached.contains(addr))
+            .collect()
+    }
+
+    /// Remove unreachable basic blocks, as found by `unreachable_blocks`,
+    /// together with their instructions, so they don't count towards
+    /// structural metrics. Returns the number of blocks removed.
+    pub fn remove_unreachable_blocks(&mut self) -> usize {
+        let unreachable = self.unreachable_blocks();
+        if unreachable.is_empty() {
+            return 0;
+        }
+
+        let dead_instrs: std::collections::HashSet<u64> = self
+            .basic_blocks
+            .iter()
+            .filter(|bb| unreachable.contains(&bb.address))
+            .flat_map(|bb| bb.instructions.iter().map(|instr| instr.address))
+            .collect();
+        self.basic_blocks.retain(|bb| !unreachable.contains(&bb.address));
+        self.instructions.retain(|instr| !dead_instrs.contains(&instr.address));
+        self.instruction_count = self.instructions.len();
+
+        unreachable.len()
+    }
+
+    /// Number of basic blocks flagged as exception landing pads.
+    pub fn landing_pad_count(&self) -> usize {
+        self.basic_blocks.iter().filter(|bb| bb.is_landing_pad).count()
+    }
+
+    /// Rough estimate of the memory held by this function, including heap
+    /// allocations for names, hashes, instructions and basic blocks.
+    pub fn estimated_memory_footprint(&self) -> usize {
+        fn instruction_footprint(instr: &InstructionInfo) -> usize {
+            std::mem::size_of::<InstructionInfo>()
+                + instr.mnemonic.len()
+                + instr.operands.iter().map(|op| std::mem::size_of::<String>() + op.len()).sum::<usize>()
+                + instr.bytes.len()
+        }
+
+        let blocks: usize = self.basic_blocks.iter().map(|bb| {
+            std::mem::size_of::<BasicBlockInfo>()
+                + bb.mnemonic_hash.len()
+                + bb.edges.len() * std::mem::size_of::<u64>()
+                + bb.instructions.iter().map(instruction_footprint).sum::<usize>()
+        }).sum();
+
+        std::mem::size_of::<FunctionInfo>()
+            + self.name.len()
+            + self.cfg_hash.len()
+            + self.call_graph_hash.len()
+            + self.instructions.iter().map(instruction_footprint).sum::<usize>()
+            + blocks
+    }
+
+    /// Drop raw instruction bytes (in both the flat and per-block lists) to
+    /// reduce memory on very large inputs. Byte-level metrics become
+    /// unavailable for this function.
+    pub fn strip_instruction_bytes(&mut self) {
+        for instr in &mut self.instructions {
+            instr.bytes = Vec::new();
+        }
+        for bb in &mut self.basic_blocks {
+            for instr in &mut bb.instructions {
+                instr.bytes = Vec::new();
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -46,6 +377,189 @@ pub struct DiffResult {
     pub analysis_time: f64,
     pub binary_a_name: String,
     pub binary_b_name: String,
+    /// Best sub-threshold candidate for each unmatched function, when
+    /// `DiffConfig::collect_near_misses` is set.
+    pub near_misses: Vec<NearMiss>,
+    /// Functions left out of matching by the minimum size filters.
+    pub skipped_functions_a: Vec<FunctionInfo>,
+    pub skipped_functions_b: Vec<FunctionInfo>,
+    /// Silent degradations noticed during the diff (empty inputs, dangling
+    /// CFG edges, memory-budget fallbacks, downgraded exact matches). In
+    /// strict mode any of these fails the diff instead.
+    pub warnings: Vec<String>,
+    /// Matched functions that moved out of order relative to their neighbors.
+    pub relocated_functions: Vec<RelocatedFunction>,
+    /// Matched functions whose callees differ once mapped through the matches.
+    pub callee_changes: Vec<CalleeChange>,
+    /// Unmatched functions split into, or merged from, two functions of the
+    /// other binary. Empty unless `DiffConfig::detect_split_merges` is set.
+    pub split_merges: Vec<SplitMerge>,
+    pub provenance: Provenance,
+}
+
+/// What produced a diff result: the crate version, the effective
+/// configuration and hashes identifying both inputs. Carried into every
+/// report so results can be reproduced and checked later.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct Provenance {
+    pub crate_version: String,
+    pub config: DiffConfig,
+    /// SHA-256 over the extracted functions of each binary, see
+    /// `DiffAlgorithms::calculate_input_hash`.
+    pub input_hash_a: String,
+    pub input_hash_b: String,
+}
+
+impl Provenance {
+    /// Header lines for text-based reports.
+    pub fn header_lines(&self) -> Vec<String> {
+        let mut lines = self.identity_lines();
+        lines.push(format!("Config: {}", serde_json::to_string(&self.config).unwrap_or_default()));
+        lines
+    }
+
+    /// The version and input hash lines of `header_lines`, for reports that
+    /// lay out the config separately.
+    pub fn identity_lines(&self) -> Vec<String> {
+        vec![
+            format!("Generated by rust_diff v{}", self.crate_version),
+            format!("Input hash A: {}", self.input_hash_a),
+            format!("Input hash B: {}", self.input_hash_b),
+        ]
+    }
+}
+
+/// Fraction of each binary covered by matches, by function count and by
+/// instruction count. Each value is in [0.0, 1.0]; an empty side is 0.0.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct Coverage {
+    pub functions_a: f64,
+    pub functions_b: f64,
+    pub instructions_a: f64,
+    pub instructions_b: f64,
+}
+
+impl Coverage {
+    /// Coverage of `matches` against the given whole-binary totals.
+    pub fn from_totals(
+        matches: &[FunctionMatch],
+        total_functions_a: usize,
+        total_functions_b: usize,
+        total_instructions_a: usize,
+        total_instructions_b: usize,
+    ) -> Self {
+        fn fraction(part: usize, total: usize) -> f64 {
+            if total == 0 { 0.0 } else { (part as f64 / total as f64).min(1.0) }
+        }
+
+        let matched_instructions_a = matches.iter().map(|m| m.function_a.instructions.len()).sum();
+        let matched_instructions_b = matches.iter().map(|m| m.function_b.instructions.len()).sum();
+
+        Self {
+            functions_a: fraction(matches.len(), total_functions_a),
+            functions_b: fraction(matches.len(), total_functions_b),
+            instructions_a: fraction(matched_instructions_a, total_instructions_a),
+            instructions_b: fraction(matched_instructions_b, total_instructions_b),
+        }
+    }
+}
+
+impl DiffResult {
+    /// Fraction of binary A and B covered by matches. Skipped functions count
+    /// towards the totals.
+    pub fn coverage(&self) -> Coverage {
+        let instructions = |functions: &[FunctionInfo]| -> usize {
+            functions.iter().map(|f| f.instructions.len()).sum()
+        };
+        let matched_a: usize = self.matched_functions.iter().map(|m| m.function_a.instructions.len()).sum();
+        let matched_b: usize = self.matched_functions.iter().map(|m| m.function_b.instructions.len()).sum();
+
+        Coverage::from_totals(
+            &self.matched_functions,
+            self.matched_functions.len() + self.unmatched_functions_a.len() + self.skipped_functions_a.len(),
+            self.matched_functions.len() + self.unmatched_functions_b.len() + self.skipped_functions_b.len(),
+            matched_a + instructions(&self.unmatched_functions_a) + instructions(&self.skipped_functions_a),
+            matched_b + instructions(&self.unmatched_functions_b) + instructions(&self.skipped_functions_b),
+        )
+    }
+
+    /// Warning for a result that looks like an input problem rather than a
+    /// real difference: the two binaries appear to be the same program
+    /// (near-identical file names, or equal input hashes) yet almost nothing
+    /// matched. Usually the extraction failed or the architectures differ.
+    pub fn suspicious_input_warning(&self) -> Option<String> {
+        const NAME_SIMILARITY: f64 = 0.8;
+        const LOW_SIMILARITY: f64 = 0.1;
+
+        let stem = |name: &str| {
+            std::path::Path::new(name)
+                .file_stem()
+                .map_or_else(|| name.to_string(), |stem| stem.to_string_lossy().to_lowercase())
+        };
+        let (stem_a, stem_b) = (stem(&self.binary_a_name), stem(&self.binary_b_name));
+        let same_name = !stem_a.is_empty()
+            && crate::similarity::SimilarityAnalyzer::normalized_edit_distance(&stem_a, &stem_b) >= NAME_SIMILARITY;
+        let same_hash = !self.provenance.input_hash_a.is_empty()
+            && self.provenance.input_hash_a == self.provenance.input_hash_b;
+
+        let coverage = self.coverage();
+        let effective_similarity = self.similarity_score * coverage.functions_a.min(coverage.functions_b);
+        if (same_name || same_hash) && effective_similarity < LOW_SIMILARITY {
+            Some(format!(
+                "{} and {} look like the same binary but only {:.1}% of their functions matched \
+                 (overall similarity {:.4}); check that both were extracted correctly and for the same architecture",
+                self.binary_a_name,
+                self.binary_b_name,
+                coverage.functions_a.min(coverage.functions_b) * 100.0,
+                self.similarity_score
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+/// Direction of a `SplitMerge` relation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Default)]
+pub enum SplitMergeKind {
+    /// One function in A became two in B.
+    #[default]
+    Split,
+    /// Two functions in A became one in B.
+    Merge,
+}
+
+/// An unmatched function whose instructions are largely covered by two
+/// unmatched functions of the other binary together, e.g. after inlining or
+/// outlining changed the function boundaries.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct SplitMerge {
+    pub kind: SplitMergeKind,
+    /// The single function: in A for a split, in B for a merge.
+    pub name: String,
+    pub address: u64,
+    /// Names and addresses of the two functions on the other side.
+    pub parts: Vec<(String, u64)>,
+    /// Fraction of the single function's mnemonics found in the parts.
+    pub coverage: f64,
+}
+
+/// An unmatched function and its most similar unmatched function in the
+/// other binary, to help reviewers decide on a manual match.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct NearMiss {
+    /// True if the unmatched function is from binary A (candidate from B).
+    pub in_binary_a: bool,
+    pub name: String,
+    pub address: u64,
+    /// `None` when the other binary has no unmatched functions left.
+    pub candidate_name: Option<String>,
+    pub candidate_address: Option<u64>,
+    pub similarity: f64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -57,17 +571,386 @@ pub struct FunctionMatch {
     pub confidence: f64,
     pub match_type: MatchType,
     pub details: MatchDetails,
+    /// Free-form annotations added by post-processing passes.
+    pub notes: Vec<String>,
+    /// Rank of each function by address within its own binary (0-based).
+    pub position_a: usize,
+    pub position_b: usize,
+    /// How far the match's similarity is ahead of the best other candidate
+    /// in B, set by `MatchingEngine::recalibrate_confidence`. Negative when
+    /// another candidate scored higher.
+    pub runner_up_gap: Option<f64>,
+}
+
+impl FunctionMatch {
+    /// How far the function moved in memory: `function_b.address -
+    /// function_a.address`.
+    pub fn address_delta(&self) -> i64 {
+        self.function_b.address.wrapping_sub(self.function_a.address) as i64
+    }
+
+    /// How far the function moved in the layout order: `position_b - position_a`.
+    pub fn position_shift(&self) -> i64 {
+        self.position_b as i64 - self.position_a as i64
+    }
+
+    /// The similarity with an uncertainty band sized by the instruction count
+    /// of the smaller function: a score backed by a handful of instructions
+    /// is far less trustworthy than the same score over hundreds.
+    pub fn similarity_interval(&self) -> SimilarityInterval {
+        let samples = self.function_a.instructions.len().min(self.function_b.instructions.len());
+        SimilarityInterval::wilson(self.similarity, samples)
+    }
+
+    /// Byte-level and alignment metrics for this match, computed on demand.
+    pub fn lazy_metrics(&self) -> crate::similarity::LazyMatchMetrics<'_> {
+        crate::similarity::LazyMatchMetrics::new(self)
+    }
+}
+
+/// A similarity score and the range it plausibly lies in.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Default)]
+pub struct SimilarityInterval {
+    pub score: f64,
+    pub lower: f64,
+    pub upper: f64,
+}
+
+impl SimilarityInterval {
+    /// 95% Wilson score interval, treating `score` as the fraction of
+    /// `samples` observations that agree. With no samples the band covers
+    /// the whole range.
+    pub fn wilson(score: f64, samples: usize) -> Self {
+        let score = score.clamp(0.0, 1.0);
+        if samples == 0 {
+            return Self { score, lower: 0.0, upper: 1.0 };
+        }
+
+        const Z: f64 = 1.96;
+        let n = samples as f64;
+        let denominator = 1.0 + Z * Z / n;
+        let center = (score + Z * Z / (2.0 * n)) / denominator;
+        let margin = Z * (score * (1.0 - score) / n + Z * Z / (4.0 * n * n)).sqrt() / denominator;
+
+        Self {
+            score,
+            lower: (center - margin).max(0.0),
+            upper: (center + margin).min(1.0),
+        }
+    }
+
+    /// Width of the band, `upper - lower`.
+    pub fn width(&self) -> f64 {
+        self.upper - self.lower
+    }
+}
+
+/// A matched function whose position in the layout changed far more than
+/// those of its neighbors, i.e. it was moved rather than shifted along with
+/// surrounding insertions or deletions.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct RelocatedFunction {
+    pub name: String,
+    pub address_a: u64,
+    pub address_b: u64,
+    /// The function's own position shift.
+    pub position_shift: i64,
+    /// Median position shift of its neighbors in binary A.
+    pub neighbor_shift: i64,
+}
+
+/// A matched function whose call targets changed. A callee counts as kept
+/// only if it's called on both sides and, for functions of the binaries
+/// themselves, the two targets are matched to each other; external targets
+/// are compared by name.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct CalleeChange {
+    pub function_a: String,
+    pub address_a: u64,
+    pub function_b: String,
+    pub address_b: u64,
+    /// Callees only reached from the function in B, by their B names.
+    pub added: Vec<String>,
+    /// Callees only reached from the function in A, by their A names.
+    pub removed: Vec<String>,
 }
 
 #[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize, Default)]
 pub enum MatchType {
+    /// Identical CFG and call-graph hashes or, for a pair scored on its
+    /// own, similarity above `MatchTypeThresholds::exact_similarity`;
+    /// confidence is exactly 1.0.
     Exact,
     Structural,
     #[default]
     Heuristic,
+    /// Asserted by a user; confidence is always exactly 1.0.
     Manual,
 }
 
+impl MatchType {
+    /// Every match type, in report order.
+    pub const ALL: [MatchType; 4] = [
+        MatchType::Exact,
+        MatchType::Structural,
+        MatchType::Heuristic,
+        MatchType::Manual,
+    ];
+
+    /// Position of this type in [`Self::ALL`].
+    pub fn index(&self) -> usize {
+        match self {
+            MatchType::Exact => 0,
+            MatchType::Structural => 1,
+            MatchType::Heuristic => 2,
+            MatchType::Manual => 3,
+        }
+    }
+
+    /// Whether matches of this type carry a confidence of exactly 1.0 by
+    /// construction, which calibration and rescoring leave alone.
+    pub fn has_fixed_confidence(&self) -> bool {
+        matches!(self, MatchType::Exact | MatchType::Manual)
+    }
+}
+
+/// The matching pipeline pass that produced a match.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MatchPass {
+    /// Pairs pinned by the user in `DiffConfig::manual_matches`. Always
+    /// applied before the configured passes; never listed in `pass_order`.
+    Manual,
+    ExactHash,
+    Name,
+    MdIndex,
+    SmallPrimes,
+    /// One bucketing pass over both the MD-Index and the small-primes
+    /// product: pairs agreeing on both keys are claimed first, then pairs
+    /// agreeing on either.
+    MdIndexSmallPrimes,
+    PrologueEpilogue,
+    Reference,
+    Structural,
+    BlockEdgeBucket,
+    Fuzzy,
+}
+
+impl MatchPass {
+    /// The default pipeline, from highest to lowest confidence. `MdIndex`
+    /// and `SmallPrimes` are covered by `MdIndexSmallPrimes` and only run
+    /// when listed in a custom order.
+    pub const DEFAULT_ORDER: [MatchPass; 8] = [
+        MatchPass::ExactHash,
+        MatchPass::Name,
+        MatchPass::MdIndexSmallPrimes,
+        MatchPass::PrologueEpilogue,
+        MatchPass::Reference,
+        MatchPass::Structural,
+        MatchPass::BlockEdgeBucket,
+        MatchPass::Fuzzy,
+    ];
+}
+
+/// Record of one match claimed by the pipeline: which pass claimed the pair
+/// and the indices of both functions in their input lists.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub pass: MatchPass,
+    pub index_a: usize,
+    pub index_b: usize,
+    pub address_a: u64,
+    pub address_b: u64,
+    pub name_a: String,
+    pub name_b: String,
+}
+
+/// Audit log of a matching run, one entry per match in claim order.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct MatchAudit {
+    pub entries: Vec<AuditEntry>,
+}
+
+impl MatchAudit {
+    /// The entry that claimed the B-function at `index_b`, if any.
+    pub fn claim_for_b(&self, index_b: usize) -> Option<&AuditEntry> {
+        self.entries.iter().find(|e| e.index_b == index_b)
+    }
+
+    /// The entry that claimed the A-function at `index_a`, if any.
+    pub fn claim_for_a(&self, index_a: usize) -> Option<&AuditEntry> {
+        self.entries.iter().find(|e| e.index_a == index_a)
+    }
+}
+
+/// How a function takes part in recursion within its own binary's call graph.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Default)]
+pub enum RecursionKind {
+    #[default]
+    NonRecursive,
+    /// Calls itself directly.
+    SelfRecursive,
+    /// Part of a call cycle through at least one other function.
+    Mutual,
+}
+
+/// Reorder-invariant summary of a function's shape and content. Every
+/// component is independent of block layout, so compiler block reordering
+/// leaves the fingerprint unchanged; compare fingerprints with
+/// [`StructuralFingerprint::distance`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct StructuralFingerprint {
+    /// Number of reachable blocks at each dominator-tree depth.
+    pub dominator_depth_histogram: Vec<usize>,
+    /// Number of back edges (edges to a dominating block), i.e. loops.
+    pub loop_count: usize,
+    /// Sorted (in-degree, out-degree) of every block.
+    pub degree_sequence: Vec<(usize, usize)>,
+    /// 64-bit SimHash over the normalized instruction tokens.
+    pub instruction_simhash: u64,
+}
+
+impl StructuralFingerprint {
+    /// Distance in [0.0, 1.0]: the mean of the per-component distances
+    /// (histogram and degree multiset by weighted Jaccard, loop count by
+    /// relative difference, SimHash by Hamming distance). 0.0 is identical.
+    pub fn distance(&self, other: &StructuralFingerprint) -> f64 {
+        fn jaccard_distance(min_sum: usize, max_sum: usize) -> f64 {
+            if max_sum == 0 { 0.0 } else { 1.0 - min_sum as f64 / max_sum as f64 }
+        }
+
+        let len = self.dominator_depth_histogram.len().max(other.dominator_depth_histogram.len());
+        let (mut min_sum, mut max_sum) = (0, 0);
+        for depth in 0..len {
+            let a = self.dominator_depth_histogram.get(depth).copied().unwrap_or(0);
+            let b = other.dominator_depth_histogram.get(depth).copied().unwrap_or(0);
+            min_sum += a.min(b);
+            max_sum += a.max(b);
+        }
+        let histogram = jaccard_distance(min_sum, max_sum);
+
+        // Both degree sequences are sorted, so a merge counts the shared multiset
+        let (a, b) = (&self.degree_sequence, &other.degree_sequence);
+        let (mut i, mut j, mut shared) = (0, 0, 0);
+        while i < a.len() && j < b.len() {
+            match a[i].cmp(&b[j]) {
+                std::cmp::Ordering::Less => i += 1,
+                std::cmp::Ordering::Greater => j += 1,
+                std::cmp::Ordering::Equal => {
+                    shared += 1;
+                    i += 1;
+                    j += 1;
+                }
+            }
+        }
+        let degrees = jaccard_distance(shared, a.len() + b.len() - shared);
+
+        let max_loops = self.loop_count.max(other.loop_count);
+        let loops = if max_loops == 0 {
+            0.0
+        } else {
+            self.loop_count.abs_diff(other.loop_count) as f64 / max_loops as f64
+        };
+
+        let simhash = (self.instruction_simhash ^ other.instruction_simhash).count_ones() as f64 / 64.0;
+
+        (histogram + degrees + loops + simhash) / 4.0
+    }
+}
+
+/// Result of a matching run together with the exact unmatched accounting:
+/// indices into the A and B inputs that no match claimed, in input order.
+/// Unlike an address set difference this stays correct when several
+/// functions share an address.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct MatchOutcome {
+    pub matches: Vec<FunctionMatch>,
+    pub unmatched_a: Vec<usize>,
+    pub unmatched_b: Vec<usize>,
+    /// Indices left out of matching by the minimum size filters. These are
+    /// not repeated in the unmatched lists.
+    pub skipped_a: Vec<usize>,
+    pub skipped_b: Vec<usize>,
+    /// Degradations noticed while matching (e.g. downgraded exact matches).
+    pub warnings: Vec<String>,
+}
+
+/// Accuracy of a set of matches against known-correct `(address_a,
+/// address_b)` pairs. Precision and recall are 1.0 when their denominator is
+/// empty (no matches claimed, or no ground truth to find).
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct EvalReport {
+    pub precision: f64,
+    pub recall: f64,
+    pub f1: f64,
+    pub true_positives: usize,
+    /// Matched pairs absent from the ground truth, in match order.
+    pub false_positives: Vec<(u64, u64)>,
+    /// Ground-truth pairs no match reproduced, in ground-truth order.
+    pub false_negatives: Vec<(u64, u64)>,
+}
+
+/// Correspondence between two basic blocks inside a pair of matched functions.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct BlockMatch {
+    pub function_a_address: u64,
+    pub function_b_address: u64,
+    pub block_a_address: u64,
+    pub block_b_address: u64,
+    pub similarity: f64,
+}
+
+/// One operand that differs between two otherwise corresponding
+/// instructions, e.g. a changed buffer size or magic constant.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OperandChange {
+    /// Operand position within the instruction.
+    pub position: usize,
+    pub old: String,
+    pub new: String,
+}
+
+/// One row of a per-instruction diff between two matched functions, with
+/// indices into each function's `instructions`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InstructionDiffEntry {
+    Equal { index_a: usize, index_b: usize },
+    Removed { index_a: usize },
+    Added { index_b: usize },
+    /// Same mnemonic and operand count, different operands.
+    Modified { index_a: usize, index_b: usize, operand_changes: Vec<OperandChange> },
+}
+
+/// What a `ByteDiffSpan` covers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ByteDiffKind {
+    Equal,
+    /// Bytes replaced in place; the two sides may differ in length.
+    Changed,
+    /// Bytes only in B.
+    Inserted,
+    /// Bytes only in A.
+    Removed,
+}
+
+/// A run of a byte-level diff between two functions' instruction bytes.
+/// Offsets are relative to each function's address. Inserted spans have
+/// `len_a == 0` with `offset_a` at the insertion point, and removed spans
+/// likewise on the B side.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ByteDiffSpan {
+    pub kind: ByteDiffKind,
+    pub offset_a: u64,
+    pub len_a: usize,
+    pub offset_b: u64,
+    pub len_b: usize,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 #[serde(default)]
 pub struct MatchDetails {
@@ -77,4 +960,142 @@ pub struct MatchDetails {
     pub edge_similarity: f64,
     pub name_similarity: f64,
     pub call_similarity: f64,
+    pub register_similarity: f64,
+    pub exception_similarity: f64,
+    pub cfg_shape_similarity: f64,
+    pub block_size_similarity: f64,
+    /// 0.0 unless the `SimilarityWeights::lcs` weight is non-zero.
+    pub lcs_similarity: f64,
+}
+
+impl MatchDetails {
+    /// Mean of the CFG, basic-block and edge sub-scores: how alike the two
+    /// functions are in shape, independent of names and calls.
+    pub fn structural_similarity(&self) -> f64 {
+        (self.cfg_similarity + self.bb_similarity + self.edge_similarity) / 3.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn combined_hash_does_not_collide_on_the_separator() {
+        let a = FunctionInfo { cfg_hash: "a_b".to_string(), call_graph_hash: "c".to_string(), ..Default::default() };
+        let b = FunctionInfo { cfg_hash: "a".to_string(), call_graph_hash: "b_c".to_string(), ..Default::default() };
+        assert_ne!(a.combined_hash(), b.combined_hash());
+        assert_eq!(a.combined_hash(), a.clone().combined_hash());
+    }
+
+    #[test]
+    fn missing_edges_are_rebuilt_from_branches_and_fallthrough() {
+        let instruction = |address: u64, mnemonic: &str, operands: &[&str]| InstructionInfo {
+            address,
+            mnemonic: mnemonic.to_string(),
+            operands: operands.iter().map(|op| op.to_string()).collect(),
+            bytes: vec![0; 2],
+            length: 2,
+        };
+        let block = |address: u64, instructions: Vec<InstructionInfo>| BasicBlockInfo {
+            address,
+            size: 2 * instructions.len() as u64,
+            instruction_count: instructions.len(),
+            instructions,
+            ..Default::default()
+        };
+        let mut function = FunctionInfo {
+            address: 0x100,
+            basic_blocks: vec![
+                block(0x100, vec![instruction(0x100, "cmp", &["eax", "0"]), instruction(0x102, "je", &["0x10a"])]),
+                block(0x104, vec![instruction(0x104, "add", &["eax", "1"]), instruction(0x106, "jmp", &["0x10c"])]),
+                block(0x108, vec![instruction(0x108, "nop", &[])]),
+                block(0x10a, vec![instruction(0x10a, "call", &["0x500"])]),
+                block(0x10c, vec![instruction(0x10c, "ret", &[])]),
+            ],
+            ..Default::default()
+        };
+
+        assert!(function.reconstruct_missing_edges());
+        let edges: Vec<Vec<u64>> = function.basic_blocks.iter().map(|b| b.edges.clone()).collect();
+        assert_eq!(edges, vec![vec![0x10a, 0x104], vec![0x10c], vec![0x10a], vec![0x10c], vec![]]);
+        assert!(!function.reconstruct_missing_edges());
+
+        let arm_branch = instruction(0, "bne", &["#0x20"]);
+        assert!(arm_branch.is_conditional_branch());
+        assert_eq!(arm_branch.branch_target(), Some(0x20));
+        assert!(!instruction(0, "bic", &[]).is_branch());
+        assert!(!instruction(0, "bl", &[]).is_branch());
+    }
+
+    #[test]
+    fn unreachable_blocks_are_found_and_removed() {
+        let block = |address: u64, edges: Vec<u64>| BasicBlockInfo {
+            address,
+            size: 4,
+            edges,
+            instructions: vec![InstructionInfo { address, mnemonic: "nop".to_string(), ..Default::default() }],
+            instruction_count: 1,
+            ..Default::default()
+        };
+        let mut function = FunctionInfo {
+            address: 0x10,
+            basic_blocks: vec![block(0x10, vec![0x20]), block(0x20, vec![]), block(0x30, vec![0x20])],
+            instruction_count: 3,
+            ..Default::default()
+        };
+        function.instructions = function.basic_blocks.iter().flat_map(|b| b.instructions.clone()).collect();
+
+        assert_eq!(function.unreachable_blocks(), vec![0x30]);
+        assert_eq!(function.remove_unreachable_blocks(), 1);
+        assert_eq!(function.basic_blocks.len(), 2);
+        assert_eq!(function.instructions.len(), 2);
+        assert_eq!(function.instruction_count, 2);
+        assert!(function.unreachable_blocks().is_empty());
+
+        // Without any edges the CFG is unknown, so nothing counts as unreachable
+        let edgeless = FunctionInfo { address: 0x10, basic_blocks: vec![block(0x10, vec![]), block(0x20, vec![])], ..Default::default() };
+        assert!(edgeless.unreachable_blocks().is_empty());
+    }
+
+    #[test]
+    fn similarity_interval_narrows_with_more_instructions() {
+        let matched = |instructions: usize, similarity: f64| {
+            let function = FunctionInfo { instructions: vec![InstructionInfo::default(); instructions], ..Default::default() };
+            FunctionMatch { function_a: function.clone(), function_b: function, similarity, ..Default::default() }
+        };
+
+        let small = matched(2, 0.9).similarity_interval();
+        let large = matched(200, 0.9).similarity_interval();
+        assert!(small.lower <= 0.9 && small.upper >= 0.9);
+        assert!(small.width() > large.width());
+        assert!(large.width() < 0.1, "{:?}", large);
+        assert_eq!(matched(0, 0.5).similarity_interval().width(), 1.0);
+
+        // Only the match over a handful of instructions is flagged in the report
+        let result = DiffResult { matched_functions: vec![matched(2, 0.9), matched(200, 0.9)], ..Default::default() };
+        let report = crate::ui::DiffUI::generate_text_report(&result);
+        assert_eq!(report.matches("Uncertain:").count(), 1);
+    }
+
+    #[test]
+    fn low_similarity_between_same_named_binaries_is_flagged() {
+        let function = FunctionInfo { name: "unmatched".to_string(), ..Default::default() };
+        let result = DiffResult {
+            binary_a_name: "/tmp/v1/libfoo.so".to_string(),
+            binary_b_name: "/tmp/v2/libfoo.so".to_string(),
+            unmatched_functions_a: vec![function.clone(); 5],
+            unmatched_functions_b: vec![function; 5],
+            ..Default::default()
+        };
+        assert!(result.suspicious_input_warning().is_some());
+        assert!(crate::ui::DiffUI::generate_text_report(&result).contains("WARNING:"));
+
+        let unrelated = DiffResult { binary_b_name: "kernel32.dll".to_string(), ..result };
+        assert!(unrelated.suspicious_input_warning().is_none());
+        assert!(!crate::ui::DiffUI::generate_text_report(&unrelated).contains("WARNING:"));
+
+        let identical = crate::BinaryDiffEngine::new().perform_diff_mock("libfoo.so", "libfoo.so").unwrap();
+        assert!(identical.suspicious_input_warning().is_none());
+    }
 }
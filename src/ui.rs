@@ -1,8 +1,12 @@
-use crate::{DiffResult, FunctionMatch, MatchType};
-use std::collections::HashMap;
+use crate::{DiffResult, FunctionInfo, FunctionMatch, InstructionDiffEntry, MatchType, Provenance, SimilarityAnalyzer};
+use serde::Serialize;
 
 pub struct DiffUI;
 
+/// Matches whose similarity interval is wider than this are flagged in the
+/// report as backed by too few instructions.
+const UNCERTAIN_INTERVAL_WIDTH: f64 = 0.2;
+
 struct ReportColors {
     header: &'static str,
     label: &'static str,
@@ -52,7 +56,29 @@ impl ReportColors {
     }
 }
 
-fn generate_report_with_colors(diff_result: &DiffResult, c: &ReportColors) -> String {
+/// Options controlling the layout of text reports.
+#[derive(Debug, Clone, Default)]
+pub struct ReportOptions {
+    /// Replace the entries of identical matches (similarity 1.0) with a
+    /// single count line; changed and unmatched functions are still listed
+    /// in full.
+    pub collapse_identical: bool,
+}
+
+/// Format a count with thousands separators, e.g. `1,234`.
+fn format_count(n: usize) -> String {
+    let digits = n.to_string();
+    let mut formatted = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, ch) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            formatted.push(',');
+        }
+        formatted.push(ch);
+    }
+    formatted
+}
+
+fn generate_report_with_colors(diff_result: &DiffResult, c: &ReportColors, options: &ReportOptions) -> String {
     let mut report = String::new();
 
     // Header
@@ -62,27 +88,51 @@ fn generate_report_with_colors(diff_result: &DiffResult, c: &ReportColors) -> St
         c.bold, c.header, c.reset,
         c.bold, c.header, "=".repeat(60),
     ));
+    for line in diff_result.provenance.header_lines() {
+        report.push_str(&format!("{}\n", line));
+    }
+    report.push('\n');
+    if let Some(warning) = diff_result.suspicious_input_warning() {
+        report.push_str(&format!("{}{}WARNING:{} {}\n\n", c.bold, c.bad, c.reset, warning));
+    }
 
     // Summary
     report.push_str(&format!("{}{}SUMMARY:{}\n", c.bold, c.label, c.reset));
     report.push_str(&format!("  {}Total Matches:{} {}\n", c.good, c.reset, diff_result.matched_functions.len()));
     report.push_str(&format!("  {}Unmatched Functions A:{} {}\n", c.bad, c.reset, diff_result.unmatched_functions_a.len()));
     report.push_str(&format!("  {}Unmatched Functions B:{} {}\n", c.bad, c.reset, diff_result.unmatched_functions_b.len()));
-    report.push_str(&format!("  {}Overall Similarity:{} {:.4}\n\n", c.info, c.reset, diff_result.similarity_score));
+    if !diff_result.skipped_functions_a.is_empty() || !diff_result.skipped_functions_b.is_empty() {
+        report.push_str(&format!("  {}Skipped (below size limits):{} {} in A, {} in B\n", c.info, c.reset,
+            diff_result.skipped_functions_a.len(), diff_result.skipped_functions_b.len()));
+    }
+    report.push_str(&format!("  {}Overall Similarity:{} {:.4}\n", c.info, c.reset, diff_result.similarity_score));
+    let coverage = diff_result.coverage();
+    report.push_str(&format!("  {}Coverage A:{} {:.1}% of functions, {:.1}% of instructions\n",
+        c.info, c.reset, coverage.functions_a * 100.0, coverage.instructions_a * 100.0));
+    report.push_str(&format!("  {}Coverage B:{} {:.1}% of functions, {:.1}% of instructions\n",
+        c.info, c.reset, coverage.functions_b * 100.0, coverage.instructions_b * 100.0));
+    report.push_str(&format!("  {}Relocated Functions:{} {}\n\n", c.info, c.reset, diff_result.relocated_functions.len()));
 
     // Match type breakdown
-    let match_counts = count_match_types(&diff_result.matched_functions);
     report.push_str(&format!("{}{}MATCH TYPE BREAKDOWN:{}\n", c.bold, c.label, c.reset));
-    report.push_str(&format!("  Exact Matches: {}\n", match_counts.get(&MatchType::Exact).unwrap_or(&0)));
-    report.push_str(&format!("  Structural Matches: {}\n", match_counts.get(&MatchType::Structural).unwrap_or(&0)));
-    report.push_str(&format!("  Heuristic Matches: {}\n", match_counts.get(&MatchType::Heuristic).unwrap_or(&0)));
-    report.push_str(&format!("  Manual Matches: {}\n\n", match_counts.get(&MatchType::Manual).unwrap_or(&0)));
+    for (match_type, count) in DiffUI::count_match_types(&diff_result.matched_functions) {
+        report.push_str(&format!("  {:?} Matches: {}\n", match_type, count));
+    }
+    report.push('\n');
 
     // Detailed matches
     report.push_str(&format!("{}{}DETAILED MATCHES:{}\n", c.bold, c.label, c.reset));
     report.push_str(&format!("{}{}{}\n", c.separator, "-".repeat(60), c.reset));
 
     let mut sorted_matches = diff_result.matched_functions.clone();
+    if options.collapse_identical {
+        let before = sorted_matches.len();
+        sorted_matches.retain(|m| m.similarity < 1.0);
+        let identical = before - sorted_matches.len();
+        if identical > 0 {
+            report.push_str(&format!("{}{} functions identical{}\n\n", c.good, format_count(identical), c.reset));
+        }
+    }
     sorted_matches.sort_by(|a, b| {
         b.confidence
             .total_cmp(&a.confidence)
@@ -104,13 +154,18 @@ fn generate_report_with_colors(diff_result: &DiffResult, c: &ReportColors) -> St
 
         report.push_str(&format!("{}{}. {}{} <-> {}{}\n",
             c.bold, i + 1, c.good, m.function_a.name, m.function_b.name, c.reset));
-        report.push_str(&format!("   Addresses: {}0x{:x}{} <-> {}0x{:x}{}\n",
-            c.info, m.function_a.address, c.reset,
-            c.info, m.function_b.address, c.reset));
+        report.push_str(&format!("   Addresses: {}{}{} <-> {}{}{}\n",
+            c.info, m.function_a.display_address(), c.reset,
+            c.info, m.function_b.display_address(), c.reset));
         report.push_str(&format!("   Similarity: {}{:.4}{} | Confidence: {}{:.4}{} | Type: {}{:?}{}\n",
             cc, m.similarity, c.reset,
             cc, m.confidence, c.reset,
             mc, m.match_type, c.reset));
+        let interval = m.similarity_interval();
+        if interval.width() > UNCERTAIN_INTERVAL_WIDTH {
+            report.push_str(&format!("   {}Uncertain:{} similarity between {:.2} and {:.2} (too few instructions)\n",
+                c.confidence_low, c.reset, interval.lower, interval.upper));
+        }
         report.push_str(&format!("   Size: {} bytes <-> {} bytes\n",
             m.function_a.size, m.function_b.size));
         report.push_str(&format!("   Basic Blocks: {} <-> {}\n",
@@ -128,8 +183,8 @@ fn generate_report_with_colors(diff_result: &DiffResult, c: &ReportColors) -> St
             report.push_str(&format!("UNMATCHED FUNCTIONS IN {}:\n", label));
             report.push_str(&format!("{}{}{}\n", c.separator, "-".repeat(60), c.reset));
             for func in funcs {
-                report.push_str(&format!("  {} (0x{:x}) - {} bytes, {} BBs\n",
-                    func.name, func.address, func.size, func.basic_blocks.len()));
+                report.push_str(&format!("  {} ({}) - {} bytes, {} BBs\n",
+                    func.name, func.display_address(), func.size, func.basic_blocks.len()));
             }
             report.push_str("\n");
         }
@@ -138,23 +193,332 @@ fn generate_report_with_colors(diff_result: &DiffResult, c: &ReportColors) -> St
     report
 }
 
-fn count_match_types(matches: &[FunctionMatch]) -> HashMap<MatchType, usize> {
-    let mut counts = HashMap::new();
-    for m in matches {
-        *counts.entry(m.match_type.clone()).or_insert(0) += 1;
+/// Lines of context kept around each change in patch hunks.
+const PATCH_CONTEXT: usize = 3;
+
+/// Render a function's instructions as one `mnemonic op1, op2` line each,
+/// lowercased with whitespace collapsed so formatting noise isn't a change.
+fn disassembly_lines(func: &FunctionInfo) -> Vec<String> {
+    func.instructions
+        .iter()
+        .map(|instr| {
+            let operands = instr
+                .operands
+                .iter()
+                .map(|op| op.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase())
+                .collect::<Vec<_>>()
+                .join(", ");
+            let mnemonic = instr.mnemonic.trim().to_lowercase();
+            if operands.is_empty() { mnemonic } else { format!("{} {}", mnemonic, operands) }
+        })
+        .collect()
+}
+
+/// Emit unified-diff hunks for one matched function pair.
+fn generate_function_patch(m: &FunctionMatch, binary_a: &str, binary_b: &str) -> Option<String> {
+    let lines_a = disassembly_lines(&m.function_a);
+    let lines_b = disassembly_lines(&m.function_b);
+    if lines_a == lines_b {
+        return None;
+    }
+
+    let alignment = SimilarityAnalyzer::lcs_alignment(&lines_a, &lines_b);
+    let mut patch = format!(
+        "--- a/{}:{} ({})\n+++ b/{}:{} ({})\n",
+        binary_a, m.function_a.name, m.function_a.display_address(),
+        binary_b, m.function_b.name, m.function_b.display_address(),
+    );
+
+    let changed: Vec<usize> = alignment
+        .iter()
+        .enumerate()
+        .filter(|(_, (a, b))| a.is_none() || b.is_none())
+        .map(|(row, _)| row)
+        .collect();
+
+    // Group changed rows into hunks, merging those whose context overlaps
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for &row in &changed {
+        let start = row.saturating_sub(PATCH_CONTEXT);
+        let end = (row + PATCH_CONTEXT + 1).min(alignment.len());
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    for (start, end) in hunks {
+        let rows = &alignment[start..end];
+        // 1-based start lines; an empty side points at the line before, as in `diff -u`
+        let count_a = rows.iter().filter(|(a, _)| a.is_some()).count();
+        let count_b = rows.iter().filter(|(_, b)| b.is_some()).count();
+        let before_a = alignment[..start].iter().filter(|(a, _)| a.is_some()).count();
+        let before_b = alignment[..start].iter().filter(|(_, b)| b.is_some()).count();
+        let line_a = if count_a == 0 { before_a } else { before_a + 1 };
+        let line_b = if count_b == 0 { before_b } else { before_b + 1 };
+
+        patch.push_str(&format!("@@ -{},{} +{},{} @@\n", line_a, count_a, line_b, count_b));
+        for row in rows {
+            match *row {
+                (Some(i), Some(_)) => patch.push_str(&format!(" {}\n", lines_a[i])),
+                (Some(i), None) => patch.push_str(&format!("-{}\n", lines_a[i])),
+                (None, Some(j)) => patch.push_str(&format!("+{}\n", lines_b[j])),
+                (None, None) => {}
+            }
+        }
+    }
+
+    Some(patch)
+}
+
+/// Escape the characters Markdown would interpret in a function or binary
+/// name, including `|` so it doesn't split a table cell.
+fn markdown_cell(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        if matches!(ch, '\\' | '|' | '`' | '*' | '_' | '<' | '>' | '[' | ']') {
+            escaped.push('\\');
+        }
+        escaped.push(ch);
+    }
+    escaped
+}
+
+/// Longest function name shown in the summary table, in characters.
+const SUMMARY_NAME_WIDTH: usize = 28;
+
+/// Shorten `text` to at most `max_chars` characters, replacing the tail with
+/// an ellipsis when it is cut. Cuts fall on character boundaries, so
+/// multibyte names (demangled symbols, non-Latin identifiers) are safe.
+pub fn truncate_display(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let mut truncated: String = text.chars().take(max_chars.saturating_sub(1)).collect();
+    truncated.push('…');
+    truncated
+}
+
+/// Terminal columns taken by `text` in a monospace font: East Asian wide
+/// and fullwidth characters take two, combining marks none.
+fn display_width(text: &str) -> usize {
+    text.chars()
+        .map(|ch| match ch as u32 {
+            0x0300..=0x036F | 0x200B..=0x200F => 0,
+            0x1100..=0x115F
+            | 0x2E80..=0x303E
+            | 0x3041..=0x33FF
+            | 0x3400..=0x4DBF
+            | 0x4E00..=0x9FFF
+            | 0xA000..=0xA4CF
+            | 0xAC00..=0xD7A3
+            | 0xF900..=0xFAFF
+            | 0xFE30..=0xFE4F
+            | 0xFF00..=0xFF60
+            | 0xFFE0..=0xFFE6
+            | 0x1F300..=0x1F64F
+            | 0x1F900..=0x1F9FF
+            | 0x20000..=0x3FFFD => 2,
+            _ => 1,
+        })
+        .sum()
+}
+
+/// Version of the layout emitted by `DiffUI::generate_json_report`. Bump it
+/// whenever a field is renamed, removed or changes meaning; adding fields is
+/// backward compatible.
+pub const JSON_REPORT_SCHEMA_VERSION: u32 = 1;
+
+/// The JSON report layout, kept separate from `DiffResult` so the two can
+/// change independently.
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    schema_version: u32,
+    binary_a: &'a str,
+    binary_b: &'a str,
+    similarity_score: f64,
+    analysis_time_seconds: f64,
+    /// Crate version, effective config and input hashes of the run.
+    provenance: &'a Provenance,
+    match_type_counts: Vec<JsonMatchTypeCount>,
+    matches: Vec<JsonMatch<'a>>,
+    unmatched_a: Vec<JsonFunction<'a>>,
+    unmatched_b: Vec<JsonFunction<'a>>,
+    warnings: &'a [String],
+}
+
+#[derive(Serialize)]
+struct JsonMatchTypeCount {
+    match_type: String,
+    count: usize,
+}
+
+#[derive(Serialize)]
+struct JsonMatch<'a> {
+    function_a: JsonFunction<'a>,
+    function_b: JsonFunction<'a>,
+    similarity: f64,
+    confidence: f64,
+    match_type: String,
+}
+
+#[derive(Serialize)]
+struct JsonFunction<'a> {
+    name: &'a str,
+    address: u64,
+    size: u64,
+}
+
+impl<'a> JsonFunction<'a> {
+    fn new(func: &'a FunctionInfo) -> Self {
+        Self { name: &func.name, address: func.address, size: func.size }
+    }
+}
+
+impl DiffResult {
+    /// Produce a git-style unified diff of the disassembly of every matched
+    /// function whose instructions changed, concatenated into one document.
+    pub fn to_patch_report(&self) -> String {
+        let mut sorted_matches: Vec<&FunctionMatch> = self.matched_functions.iter().collect();
+        sorted_matches.sort_by_key(|m| (m.function_a.address, m.function_b.address));
+
+        sorted_matches
+            .into_iter()
+            .filter_map(|m| generate_function_patch(m, &self.binary_a_name, &self.binary_b_name))
+            .collect()
     }
-    counts
 }
 
 impl DiffUI {
+    /// Count matches of every `MatchType`, in `MatchType::ALL` order.
+    /// Types without matches are included with a count of zero.
+    pub fn count_match_types(matches: &[FunctionMatch]) -> Vec<(MatchType, usize)> {
+        let mut counts = [0usize; MatchType::ALL.len()];
+        for m in matches {
+            counts[m.match_type.index()] += 1;
+        }
+        MatchType::ALL.iter().cloned().zip(counts).collect()
+    }
+
     /// Generate a plain text diff report
     pub fn generate_text_report(diff_result: &DiffResult) -> String {
-        generate_report_with_colors(diff_result, &ReportColors::plain())
+        generate_report_with_colors(diff_result, &ReportColors::plain(), &ReportOptions::default())
+    }
+
+    /// Generate a plain text diff report laid out according to `options`
+    pub fn generate_text_report_with_options(diff_result: &DiffResult, options: &ReportOptions) -> String {
+        generate_report_with_colors(diff_result, &ReportColors::plain(), options)
     }
 
     /// Generate a color-coded terminal output
     pub fn generate_colored_report(diff_result: &DiffResult) -> String {
-        generate_report_with_colors(diff_result, &ReportColors::ansi())
+        generate_report_with_colors(diff_result, &ReportColors::ansi(), &ReportOptions::default())
+    }
+
+    /// Generate a color-coded terminal output laid out according to `options`
+    pub fn generate_colored_report_with_options(diff_result: &DiffResult, options: &ReportOptions) -> String {
+        generate_report_with_colors(diff_result, &ReportColors::ansi(), options)
+    }
+
+    /// Generate a GitHub-flavored Markdown report for pasting into pull
+    /// requests and issues: the run's provenance (config collapsed), a
+    /// summary, a table of matches in the text report's order and the
+    /// unmatched functions in collapsed sections.
+    pub fn generate_markdown_report(diff_result: &DiffResult) -> String {
+        let mut report = String::new();
+
+        report.push_str("# Binary Diff Report\n\n");
+        report.push_str(&format!("{} vs {}\n\n",
+            markdown_cell(&diff_result.binary_a_name), markdown_cell(&diff_result.binary_b_name)));
+        for line in diff_result.provenance.identity_lines() {
+            report.push_str(&format!("{}  \n", markdown_cell(&line)));
+        }
+        report.push_str(&format!("\n<details>\n<summary>Config</summary>\n\n```json\n{}\n```\n\n</details>\n\n",
+            serde_json::to_string_pretty(&diff_result.provenance.config).unwrap_or_default()));
+        if let Some(warning) = diff_result.suspicious_input_warning() {
+            report.push_str(&format!("> **Warning:** {}\n\n", warning));
+        }
+
+        report.push_str("## Summary\n\n");
+        report.push_str(&format!("- **Total Matches:** {}\n", diff_result.matched_functions.len()));
+        report.push_str(&format!("- **Unmatched Functions A:** {}\n", diff_result.unmatched_functions_a.len()));
+        report.push_str(&format!("- **Unmatched Functions B:** {}\n", diff_result.unmatched_functions_b.len()));
+        report.push_str(&format!("- **Overall Similarity:** {:.4}\n", diff_result.similarity_score));
+        let coverage = diff_result.coverage();
+        report.push_str(&format!("- **Coverage A:** {:.1}% of functions, {:.1}% of instructions\n",
+            coverage.functions_a * 100.0, coverage.instructions_a * 100.0));
+        report.push_str(&format!("- **Coverage B:** {:.1}% of functions, {:.1}% of instructions\n\n",
+            coverage.functions_b * 100.0, coverage.instructions_b * 100.0));
+
+        report.push_str("## Matches\n\n");
+        report.push_str("| Function A | Function B | Similarity | Confidence | Type |\n");
+        report.push_str("| --- | --- | ---: | ---: | --- |\n");
+        let mut sorted_matches: Vec<&FunctionMatch> = diff_result.matched_functions.iter().collect();
+        sorted_matches.sort_by(|a, b| {
+            b.confidence
+                .total_cmp(&a.confidence)
+                .then_with(|| a.function_a.address.cmp(&b.function_a.address))
+                .then_with(|| a.function_b.address.cmp(&b.function_b.address))
+        });
+        for m in sorted_matches {
+            report.push_str(&format!("| {} | {} | {:.4} | {:.4} | {:?} |\n",
+                markdown_cell(&m.function_a.name), markdown_cell(&m.function_b.name),
+                m.similarity, m.confidence, m.match_type));
+        }
+        report.push('\n');
+
+        for (label, funcs) in [
+            ("Binary A", &diff_result.unmatched_functions_a),
+            ("Binary B", &diff_result.unmatched_functions_b),
+        ] {
+            if !funcs.is_empty() {
+                report.push_str(&format!("<details>\n<summary>Unmatched functions in {} ({})</summary>\n\n", label, funcs.len()));
+                for func in funcs {
+                    report.push_str(&format!("- {} ({}) - {} bytes, {} BBs\n",
+                        markdown_cell(&func.name), func.display_address(), func.size, func.basic_blocks.len()));
+                }
+                report.push_str("\n</details>\n\n");
+            }
+        }
+
+        report
+    }
+
+    /// Generate a machine-readable JSON report. The layout is versioned by
+    /// `schema_version` (see `JSON_REPORT_SCHEMA_VERSION`) and does not
+    /// follow the serde form of `DiffResult`. Matches are ordered by A
+    /// address, then B address.
+    pub fn generate_json_report(diff_result: &DiffResult) -> String {
+        let mut matches: Vec<&FunctionMatch> = diff_result.matched_functions.iter().collect();
+        matches.sort_by_key(|m| (m.function_a.address, m.function_b.address));
+
+        let report = JsonReport {
+            schema_version: JSON_REPORT_SCHEMA_VERSION,
+            binary_a: &diff_result.binary_a_name,
+            binary_b: &diff_result.binary_b_name,
+            similarity_score: diff_result.similarity_score,
+            analysis_time_seconds: diff_result.analysis_time,
+            provenance: &diff_result.provenance,
+            match_type_counts: Self::count_match_types(&diff_result.matched_functions)
+                .into_iter()
+                .map(|(match_type, count)| JsonMatchTypeCount { match_type: format!("{:?}", match_type), count })
+                .collect(),
+            matches: matches
+                .into_iter()
+                .map(|m| JsonMatch {
+                    function_a: JsonFunction::new(&m.function_a),
+                    function_b: JsonFunction::new(&m.function_b),
+                    similarity: m.similarity,
+                    confidence: m.confidence,
+                    match_type: format!("{:?}", m.match_type),
+                })
+                .collect(),
+            unmatched_a: diff_result.unmatched_functions_a.iter().map(JsonFunction::new).collect(),
+            unmatched_b: diff_result.unmatched_functions_b.iter().map(JsonFunction::new).collect(),
+            warnings: &diff_result.warnings,
+        };
+
+        serde_json::to_string_pretty(&report).unwrap_or_default()
     }
 
     /// Generate a progress bar for diff operations
@@ -193,16 +557,17 @@ impl DiffUI {
 
             match (block_a, block_b) {
                 (Some(a), Some(b)) => {
-                    viz.push_str(&format!("  Block {}: 0x{:x} ({} instrs) | 0x{:x} ({} instrs)\n",
-                        i, a.address, a.instructions.len(), b.address, b.instructions.len()));
+                    viz.push_str(&format!("  Block {}: {} ({} instrs) | {} ({} instrs)\n",
+                        i, match_result.function_a.format_address(a.address), a.instructions.len(),
+                        match_result.function_b.format_address(b.address), b.instructions.len()));
                 }
                 (Some(a), None) => {
-                    viz.push_str(&format!("  Block {}: 0x{:x} ({} instrs) | <missing>\n",
-                        i, a.address, a.instructions.len()));
+                    viz.push_str(&format!("  Block {}: {} ({} instrs) | <missing>\n",
+                        i, match_result.function_a.format_address(a.address), a.instructions.len()));
                 }
                 (None, Some(b)) => {
-                    viz.push_str(&format!("  Block {}: <missing> | 0x{:x} ({} instrs)\n",
-                        i, b.address, b.instructions.len()));
+                    viz.push_str(&format!("  Block {}: <missing> | {} ({} instrs)\n",
+                        i, match_result.function_b.format_address(b.address), b.instructions.len()));
                 }
                 (None, None) => break,
             }
@@ -214,48 +579,258 @@ impl DiffUI {
         viz.push_str(&format!("  Complexity A: {}\n", match_result.function_a.cyclomatic_complexity));
         viz.push_str(&format!("  Complexity B: {}\n", match_result.function_b.cyclomatic_complexity));
 
+        viz.push_str("\nInstruction Diff:\n");
+        viz.push_str(&Self::generate_instruction_diff(match_result));
+
         viz
     }
 
-    /// Generate a summary table for matches
+    /// Side-by-side instruction diff of a matched pair, aligned by
+    /// `SimilarityAnalyzer::instruction_diff`. Each row is marked `=`
+    /// (equal), `-` (only in A), `+` (only in B) or `~` (same mnemonic,
+    /// different operands) and shows address, mnemonic and operands on both
+    /// sides.
+    pub fn generate_instruction_diff(match_result: &FunctionMatch) -> String {
+        let func_a = &match_result.function_a;
+        let func_b = &match_result.function_b;
+        let lines_a = disassembly_lines(func_a);
+        let lines_b = disassembly_lines(func_b);
+        let side_a = |index: usize| format!("{}  {}", func_a.format_address(func_a.instructions[index].address), lines_a[index]);
+        let side_b = |index: usize| format!("{}  {}", func_b.format_address(func_b.instructions[index].address), lines_b[index]);
+
+        let rows: Vec<(char, String, String)> = SimilarityAnalyzer::instruction_diff(func_a, func_b)
+            .into_iter()
+            .map(|entry| match entry {
+                InstructionDiffEntry::Equal { index_a, index_b } => ('=', side_a(index_a), side_b(index_b)),
+                InstructionDiffEntry::Removed { index_a } => ('-', side_a(index_a), String::new()),
+                InstructionDiffEntry::Added { index_b } => ('+', String::new(), side_b(index_b)),
+                InstructionDiffEntry::Modified { index_a, index_b, .. } => ('~', side_a(index_a), side_b(index_b)),
+            })
+            .collect();
+
+        let width = rows.iter().map(|(_, a, _)| display_width(a)).max().unwrap_or(0);
+        let mut diff = String::new();
+        for (marker, a, b) in rows {
+            let line = format!("{} {}{} | {}", marker, a, " ".repeat(width - display_width(&a)), b);
+            diff.push_str(line.trim_end());
+            diff.push('\n');
+        }
+        diff
+    }
+
+    /// Generate a summary table for matches. Column widths follow the
+    /// widest cell (names are cut at `SUMMARY_NAME_WIDTH` characters),
+    /// counting CJK and other wide characters as two terminal columns.
     pub fn generate_summary_table(matches: &[FunctionMatch]) -> String {
+        const HEADERS: [&str; 9] = ["Function A", "Function B", "Similarity", "Confidence", "Type", "Size A", "Size B", "BB A", "BB B"];
+
+        let rows: Vec<[String; 9]> = matches
+            .iter()
+            .map(|m| {
+                [
+                    truncate_display(&m.function_a.name, SUMMARY_NAME_WIDTH),
+                    truncate_display(&m.function_b.name, SUMMARY_NAME_WIDTH),
+                    format!("{:.4}", m.similarity),
+                    format!("{:.4}", m.confidence),
+                    format!("{:?}", m.match_type),
+                    m.function_a.size.to_string(),
+                    m.function_b.size.to_string(),
+                    m.function_a.basic_blocks.len().to_string(),
+                    m.function_b.basic_blocks.len().to_string(),
+                ]
+            })
+            .collect();
+
+        let mut widths = HEADERS.map(display_width);
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(display_width(cell));
+            }
+        }
+
+        let border = |left: &str, joint: &str, right: &str| {
+            let runs: Vec<String> = widths.iter().map(|w| "─".repeat(w + 2)).collect();
+            format!("{}{}{}\n", left, runs.join(joint), right)
+        };
+        let line = |cells: &[&str]| {
+            let padded: Vec<String> = cells
+                .iter()
+                .zip(&widths)
+                .map(|(cell, &width)| format!(" {}{} ", cell, " ".repeat(width - display_width(cell))))
+                .collect();
+            format!("│{}│\n", padded.join("│"))
+        };
+
+        // The title spans every column and their separators
+        let inner = widths.iter().map(|w| w + 3).sum::<usize>() - 1;
+        let title = "FUNCTION MATCHES";
+        let left = (inner - title.len()) / 2;
+
         let mut table = String::new();
+        table.push_str(&format!("┌{}┐\n", "─".repeat(inner)));
+        table.push_str(&format!("│{}{}{}│\n", " ".repeat(left), title, " ".repeat(inner - title.len() - left)));
+        table.push_str(&border("├", "┬", "┤"));
+        table.push_str(&line(&HEADERS));
+        table.push_str(&border("├", "┼", "┤"));
+        for row in &rows {
+            table.push_str(&line(&row.each_ref().map(String::as_str)));
+        }
+        table.push_str(&border("└", "┴", "┘"));
 
-        table.push_str("┌─────────────────────────────────────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┐\n");
-        table.push_str("│                                                    FUNCTION MATCHES                                                    │\n");
-        table.push_str("├─────────────────────────────────────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┤\n");
-        table.push_str("│ Function A                    │ Function B                    │ Similarity │ Confidence │ Type       │ Size A │ Size B │ BB A │ BB B │\n");
-        table.push_str("├─────────────────────────────────────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┤\n");
+        table
+    }
+}
 
-        for m in matches {
-            let func_a_name = if m.function_a.name.len() > 28 {
-                format!("{}...", &m.function_a.name[..25])
-            } else {
-                m.function_a.name.clone()
-            };
-
-            let func_b_name = if m.function_b.name.len() > 28 {
-                format!("{}...", &m.function_b.name[..25])
-            } else {
-                m.function_b.name.clone()
-            };
-
-            table.push_str(&format!(
-                "│ {:<29} │ {:<29} │ {:<10.4} │ {:<10.4} │ {:<10?} │ {:<6} │ {:<6} │ {:<4} │ {:<4} │\n",
-                func_a_name,
-                func_b_name,
-                m.similarity,
-                m.confidence,
-                m.match_type,
-                m.function_a.size,
-                m.function_b.size,
-                m.function_a.basic_blocks.len(),
-                m.function_b.basic_blocks.len()
-            ));
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BinaryDiffEngine;
+
+    #[test]
+    fn collapse_identical_summarizes_unchanged_matches() {
+        assert_eq!(format_count(1234), "1,234");
+        assert_eq!(format_count(123), "123");
+        assert_eq!(format_count(1_000_000), "1,000,000");
+
+        let mut result = BinaryDiffEngine::new().perform_diff_mock("binary_a", "binary_b").unwrap();
+        let total = result.matched_functions.len();
+        result.matched_functions[0].similarity = 0.75;
+        result.matched_functions[0].function_a.name = "changed_fn".to_string();
+
+        let collapsed = DiffUI::generate_text_report_with_options(&result, &ReportOptions { collapse_identical: true });
+        assert!(collapsed.contains(&format!("{} functions identical", total - 1)));
+        assert!(collapsed.contains("changed_fn"));
+        assert_eq!(collapsed.matches("Addresses:").count(), 1);
+        assert_eq!(DiffUI::generate_text_report(&result).matches("Addresses:").count(), total);
+    }
+
+    #[test]
+    fn patch_report_shows_one_changed_instruction() {
+        let mut result = BinaryDiffEngine::new().perform_diff_mock("binary_a", "binary_b").unwrap();
+        assert_eq!(result.to_patch_report(), "");
+
+        let m = result.matched_functions.iter_mut().find(|m| m.function_a.name == "process_data").unwrap();
+        m.function_b.instructions[7].mnemonic = "xor".to_string();
+        let patch = result.to_patch_report();
+        assert_eq!(patch.lines().filter(|l| l.starts_with('-') && !l.starts_with("---")).count(), 1);
+        assert_eq!(patch.lines().filter(|l| l.starts_with('+') && !l.starts_with("+++")).count(), 1);
+        assert!(patch.contains("xor"));
+    }
+
+    #[test]
+    fn text_report_pads_addresses_to_the_pointer_size() {
+        let function = |pointer_size| FunctionInfo { name: "f".to_string(), address: 0x401000, pointer_size, ..Default::default() };
+        assert_eq!(function(4).display_address(), "0x00401000");
+        assert_eq!(function(8).display_address(), "0x0000000000401000");
+
+        let mut result = BinaryDiffEngine::new().perform_diff_mock("binary_a", "binary_b").unwrap();
+        result.matched_functions = vec![FunctionMatch { function_a: function(4), function_b: function(4), ..Default::default() }];
+        assert!(DiffUI::generate_text_report(&result).contains("Addresses: 0x00401000 <-> 0x00401000"));
+    }
+
+    #[test]
+    fn match_type_counts_list_every_type_in_order() {
+        let of_type = |match_type| FunctionMatch { match_type, ..Default::default() };
+        let matches = [of_type(MatchType::Exact), of_type(MatchType::Exact), of_type(MatchType::Manual)];
+        assert_eq!(
+            DiffUI::count_match_types(&matches),
+            vec![(MatchType::Exact, 2), (MatchType::Structural, 0), (MatchType::Heuristic, 0), (MatchType::Manual, 1)]
+        );
+        assert!(MatchType::ALL.iter().enumerate().all(|(i, match_type)| match_type.index() == i));
+
+        let result = BinaryDiffEngine::new().perform_diff_mock("binary_a", "binary_b").unwrap();
+        let report = DiffUI::generate_text_report(&result);
+        for label in ["Exact Matches:", "Structural Matches:", "Heuristic Matches:", "Manual Matches:"] {
+            assert!(report.contains(label));
         }
+    }
 
-        table.push_str("└─────────────────────────────────────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘\n");
+    #[test]
+    fn markdown_report_has_one_table_row_per_match() {
+        let mut result = BinaryDiffEngine::new().perform_diff_mock("binary_a", "binary_b").unwrap();
+        result.matched_functions[0].function_a.name = "a|b".to_string();
+        let report = DiffUI::generate_markdown_report(&result);
+
+        assert!(report.contains("| Function A | Function B | Similarity | Confidence | Type |\n| --- | --- | ---: | ---: | --- |\n"));
+        let rows = report
+            .lines()
+            .filter(|line| line.starts_with("| ") && !line.starts_with("| Function A") && !line.starts_with("| ---"))
+            .count();
+        assert_eq!(rows, result.matched_functions.len());
+        assert!(report.contains(r"a\|b"));
+    }
 
-        table
+    #[test]
+    fn json_report_is_versioned_and_lists_every_match() {
+        let result = BinaryDiffEngine::new().perform_diff_mock("binary_a", "binary_b").unwrap();
+        let report: serde_json::Value = serde_json::from_str(&DiffUI::generate_json_report(&result)).unwrap();
+
+        assert_eq!(report["schema_version"], JSON_REPORT_SCHEMA_VERSION);
+        assert_eq!(report["binary_a"], "binary_a");
+        let matches = report["matches"].as_array().unwrap();
+        assert_eq!(matches.len(), result.matched_functions.len());
+        let addresses: Vec<u64> = matches.iter().map(|m| m["function_a"]["address"].as_u64().unwrap()).collect();
+        assert!(addresses.windows(2).all(|pair| pair[0] <= pair[1]));
+        let counted: u64 = report["match_type_counts"].as_array().unwrap().iter().map(|c| c["count"].as_u64().unwrap()).sum();
+        assert_eq!(counted as usize, result.matched_functions.len());
+        assert_eq!(report["unmatched_a"].as_array().unwrap().len(), result.unmatched_functions_a.len());
+    }
+
+    #[test]
+    fn summary_table_columns_line_up_with_wide_names() {
+        let mut result = BinaryDiffEngine::new().perform_diff_mock("binary_a", "binary_b").unwrap();
+        // Wide characters straddle the truncation point, and `→` sits right at it
+        result.matched_functions[0].function_a.name = "関数名前空間の非常に長い名前を持つ関数でありますよこれは本当に長い".to_string();
+        result.matched_functions[1].function_b.name = "é".repeat(24) + "→→→→→→→";
+        let table = DiffUI::generate_summary_table(&result.matched_functions);
+
+        let lines: Vec<&str> = table.lines().collect();
+        assert!(lines.iter().all(|line| display_width(line) == display_width(lines[0])), "{}", table);
+        let separators = |line: &str| {
+            let mut column = 0;
+            let mut positions = Vec::new();
+            for ch in line.chars() {
+                if "│┬┼┴├┤┌┐└┘".contains(ch) {
+                    positions.push(column);
+                }
+                column += display_width(ch.encode_utf8(&mut [0; 4]));
+            }
+            positions
+        };
+        for line in &lines[2..] {
+            assert_eq!(separators(line), separators(lines[3]), "{}", table);
+        }
+    }
+
+    #[test]
+    fn truncation_cuts_on_character_boundaries() {
+        // Byte 25 falls inside `→`, and the cut lands inside `élan`
+        let name = format!("{}→élan::demangled", "a".repeat(24));
+        assert_eq!(truncate_display(&name, 28), format!("{}→él…", "a".repeat(24)));
+        assert_eq!(truncate_display("関数名前空間の関数", 4), "関数名…");
+        assert_eq!(truncate_display("short", 28), "short");
+        assert_eq!(truncate_display("abc", 0), "…");
+
+        let mut result = BinaryDiffEngine::new().perform_diff_mock("binary_a", "binary_b").unwrap();
+        result.matched_functions[0].function_a.name = format!("{}é{}", "x".repeat(24), "y".repeat(10));
+        let table = DiffUI::generate_summary_table(&result.matched_functions);
+        assert!(table.contains(&format!("{}éyy…", "x".repeat(24))));
+    }
+
+    #[test]
+    fn instruction_diff_marks_an_inserted_instruction() {
+        let result = BinaryDiffEngine::new().perform_diff_mock("binary_a", "binary_b").unwrap();
+        let mut function_match = result.matched_functions[0].clone();
+        function_match.function_b = function_match.function_a.clone();
+        let mut inserted = function_match.function_b.instructions[0].clone();
+        inserted.mnemonic = "nop".to_string();
+        inserted.operands.clear();
+        function_match.function_b.instructions.insert(1, inserted);
+
+        let diff = DiffUI::generate_instruction_diff(&function_match);
+        assert_eq!(diff.lines().filter(|line| line.starts_with('+')).count(), 1, "{}", diff);
+        assert_eq!(diff.lines().filter(|line| line.starts_with('-')).count(), 0, "{}", diff);
+        assert_eq!(diff.lines().filter(|line| line.starts_with('=')).count(), function_match.function_a.instructions.len());
+        assert!(DiffUI::generate_diff_visualization(&function_match).contains("Instruction Diff:"));
     }
 }
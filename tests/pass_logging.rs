@@ -0,0 +1,54 @@
+//! Installs a global logger, so it lives in its own test binary.
+
+use log::kv::{Error, Key, Value, VisitSource};
+use rust_diff::{MatchPass, mock};
+use rust_diff::matching::MatchingEngine;
+use std::sync::Mutex;
+
+/// Key-values of every record logged with any.
+static STRUCTURED_LOGS: Mutex<Vec<Vec<(String, String)>>> = Mutex::new(Vec::new());
+
+struct StructuredLogger;
+
+struct Collect(Vec<(String, String)>);
+
+impl<'kvs> VisitSource<'kvs> for Collect {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), Error> {
+        self.0.push((key.to_string(), value.to_string()));
+        Ok(())
+    }
+}
+
+impl log::Log for StructuredLogger {
+    fn enabled(&self, _: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        let mut pairs = Collect(Vec::new());
+        record.key_values().visit(&mut pairs).unwrap();
+        if !pairs.0.is_empty() {
+            STRUCTURED_LOGS.lock().unwrap().push(pairs.0);
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+fn value<'a>(pairs: &'a [(String, String)], key: &str) -> Option<&'a str> {
+    pairs.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+}
+
+#[test]
+fn every_pass_logs_structured_statistics() {
+    log::set_logger(&StructuredLogger).unwrap();
+    rust_diff::set_log_level(log::LevelFilter::Info);
+    let functions = mock::generate_mock_functions("binary").unwrap();
+    MatchingEngine::new().match_functions(&functions, &functions).unwrap();
+
+    let logs = STRUCTURED_LOGS.lock().unwrap();
+    assert_eq!(logs.len(), MatchPass::DEFAULT_ORDER.len());
+    assert_eq!(value(&logs[0], "pass"), Some("ExactHash"));
+    assert_eq!(value(&logs[0], "matches"), Some(functions.len().to_string().as_str()));
+    assert!(logs.iter().all(|pairs| value(pairs, "elapsed_ms").is_some()));
+}